@@ -1,3 +1,6 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
 pub struct Mixer {
     pulse_table: [f32; 31],
     tnd_table: [f32; 203],
@@ -29,14 +32,15 @@ impl Default for Mixer {
 }
 
 impl Mixer {
-    pub fn mix(&self, pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    /// Expansion audio from the cartridge is added after the 2A03's non-linear mix, already scaled to its level
+    pub fn mix(&self, pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8, expansion: f32) -> f32 {
         let pulse_sum = (pulse_1 + pulse_2) as usize;
         let pulse_output = self.pulse_table[pulse_sum.min(30)];
 
         let tnd_sum = 3 * triangle as usize + 2 * noise as usize + dmc as usize;
         let tnd_output = self.tnd_table[tnd_sum.min(202)];
 
-        let mixed = pulse_output + tnd_output;
+        let mixed = pulse_output + tnd_output + expansion;
         (mixed - 1.0).clamp(-1.0, 1.0)
     }
 }
@@ -143,6 +147,11 @@ pub struct ChannelVolumes {
     pub triangle: f32,
     pub noise: f32,
     pub dmc: f32,
+    pub expansion: f32,
+
+    pub vrc6_pulse_1: f32,
+    pub vrc6_pulse_2: f32,
+    pub vrc6_sawtooth: f32,
 }
 
 impl Default for ChannelVolumes {
@@ -153,6 +162,11 @@ impl Default for ChannelVolumes {
             triangle: 1.0,
             noise: 1.0,
             dmc: 1.0,
+            expansion: 1.0,
+
+            vrc6_pulse_1: 1.0,
+            vrc6_pulse_2: 1.0,
+            vrc6_sawtooth: 1.0,
         }
     }
 }
@@ -178,14 +192,15 @@ impl AudioProcessor {
         }
     }
 
-    pub fn process(&mut self, pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    pub fn process(&mut self, pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8, expansion: f32) -> f32 {
         let pulse_1 = (pulse_1 as f32 * self.channel_volumes.pulse_1) as u8;
         let pulse_2 = (pulse_2 as f32 * self.channel_volumes.pulse_2) as u8;
         let triangle = (triangle as f32 * self.channel_volumes.triangle) as u8;
         let noise = (noise as f32 * self.channel_volumes.noise) as u8;
         let dmc = (dmc as f32 * self.channel_volumes.dmc) as u8;
+        let expansion = expansion * self.channel_volumes.expansion;
 
-        let mixed = self.mixer.mix(pulse_1, pulse_2, triangle, noise, dmc);
+        let mixed = self.mixer.mix(pulse_1, pulse_2, triangle, noise, dmc, expansion);
         let filtered = self.high_pass.process(mixed);
         let filtered = self.low_pass.process(filtered);
         let compressed = self.compressor.process(filtered);
@@ -202,3 +217,56 @@ impl AudioProcessor {
         }
     }
 }
+
+impl SaveState for HighPassFilter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_f32(self.previous_input);
+        writer.write_f32(self.previous_output);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.previous_input = reader.read_f32()?;
+        self.previous_output = reader.read_f32()?;
+
+        Ok(())
+    }
+}
+
+impl SaveState for LowPassFilter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_f32(self.previous_output);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.previous_output = reader.read_f32()?;
+
+        Ok(())
+    }
+}
+
+impl SaveState for Compressor {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_f32(self.envelope);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.envelope = reader.read_f32()?;
+
+        Ok(())
+    }
+}
+
+/// Only the filter history is captured, channel and master volumes are user preferences rather than emulated state
+impl SaveState for AudioProcessor {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.high_pass.save_state(writer);
+        self.low_pass.save_state(writer);
+        self.compressor.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.high_pass.load_state(reader)?;
+        self.low_pass.load_state(reader)?;
+        self.compressor.load_state(reader)
+    }
+}
@@ -1,3 +1,6 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
 const DMC_RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
 
 pub struct DmcChannel {
@@ -191,3 +194,59 @@ impl Default for DmcChannel {
         Self::new()
     }
 }
+
+impl SaveState for DmcChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.loop_flag);
+        writer.write_u8(self.rate_index);
+        writer.write_u8(self.output_level);
+        writer.write_u16(self.sample_address);
+        writer.write_u16(self.sample_length);
+
+        writer.write_u16(self.timer_period);
+        writer.write_u16(self.timer);
+
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.current_address);
+        writer.write_u16(self.bytes_remaining);
+
+        writer.write_u8(self.sample_buffer);
+        writer.write_bool(self.sample_buffer_empty);
+
+        writer.write_u8(self.shift_register);
+        writer.write_u8(self.bits_remaining);
+        writer.write_bool(self.silence_flag);
+
+        writer.write_bool(self.interrupt_flag);
+        writer.write_bool(self.needs_init);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.irq_enabled = reader.read_bool()?;
+        self.loop_flag = reader.read_bool()?;
+        self.rate_index = reader.read_u8()?;
+        self.output_level = reader.read_u8()?;
+        self.sample_address = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+
+        self.timer_period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+
+        self.enabled = reader.read_bool()?;
+        self.current_address = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+
+        self.sample_buffer = reader.read_u8()?;
+        self.sample_buffer_empty = reader.read_bool()?;
+
+        self.shift_register = reader.read_u8()?;
+        self.bits_remaining = reader.read_u8()?;
+        self.silence_flag = reader.read_bool()?;
+
+        self.interrupt_flag = reader.read_bool()?;
+        self.needs_init = reader.read_bool()?;
+
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
 pub struct Envelope {
     pub start: bool,
     decay_level: u8,
@@ -50,3 +53,23 @@ impl Default for Envelope {
         Self::new()
     }
 }
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.start);
+        writer.write_u8(self.decay_level);
+        writer.write_u8(self.divider);
+        writer.write_bool(self.constant_volume);
+        writer.write_u8(self.constant_volume_value);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.start = reader.read_bool()?;
+        self.decay_level = reader.read_u8()?;
+        self.divider = reader.read_u8()?;
+        self.constant_volume = reader.read_bool()?;
+        self.constant_volume_value = reader.read_u8()?;
+
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FrameCounterMode {
     FourStep,
@@ -96,10 +99,8 @@ impl FrameCounter {
             22371 => {
                 signals.clock_envelopes = true;
             }
-            29828 => {
-                if !self.irq_inhibit {
-                    self.irq_flag = true;
-                }
+            29828 if !self.irq_inhibit => {
+                self.irq_flag = true;
             }
             29829 => {
                 signals.clock_envelopes = true;
@@ -140,3 +141,34 @@ impl FrameCounter {
         }
     }
 }
+
+impl SaveState for FrameCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.mode == FrameCounterMode::FiveStep);
+        writer.write_u32(self.cycle_counter);
+        writer.write_bool(self.irq_inhibit);
+        writer.write_bool(self.irq_flag);
+
+        writer.write_u8(self.write_delay_counter);
+        writer.write_bool(self.pending_write.is_some());
+        writer.write_u8(self.pending_write.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.mode = if reader.read_bool()? {
+            FrameCounterMode::FiveStep
+        } else {
+            FrameCounterMode::FourStep
+        };
+        self.cycle_counter = reader.read_u32()?;
+        self.irq_inhibit = reader.read_bool()?;
+        self.irq_flag = reader.read_bool()?;
+
+        self.write_delay_counter = reader.read_u8()?;
+        let has_pending_write = reader.read_bool()?;
+        let pending_write = reader.read_u8()?;
+        self.pending_write = has_pending_write.then_some(pending_write);
+
+        Ok(())
+    }
+}
@@ -7,11 +7,15 @@ mod pulse_channel;
 mod triangle_channel;
 
 use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 use audio_output::*;
+
+pub use audio_output::ChannelVolumes;
 use dmc_channel::*;
 use frame_counter::*;
 use noise_channel::*;
-use pulse_channel::*;
+pub(crate) use pulse_channel::PulseChannel;
 use triangle_channel::*;
 
 pub const LENGTH_TABLE: [u8; 32] = [
@@ -206,17 +210,50 @@ impl Apu {
         self.cycle += 1;
     }
 
-    pub fn output(&mut self) -> f32 {
+    /// The mixed sample for this cycle, `expansion` is the cartridge's expansion audio level
+    pub fn output(&mut self, expansion: f32) -> f32 {
         let pulse_1 = self.pulse_1.raw_output();
         let pulse_2 = self.pulse_2.raw_output();
         let triangle = self.triangle.raw_output();
         let noise = self.noise.raw_output();
         let dmc = self.dmc.raw_output();
 
-        self.audio_processor.process(pulse_1, pulse_2, triangle, noise, dmc)
+        self.audio_processor.process(pulse_1, pulse_2, triangle, noise, dmc, expansion)
     }
 
     pub fn irq_pending(&mut self) -> bool {
         self.frame_irq || self.dmc_irq
     }
 }
+
+impl SaveState for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse_1.save_state(writer);
+        self.pulse_2.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+
+        self.frame_counter.save_state(writer);
+        writer.write_u64(self.cycle);
+        writer.write_bool(self.frame_irq);
+        writer.write_bool(self.dmc_irq);
+
+        self.audio_processor.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.pulse_1.load_state(reader)?;
+        self.pulse_2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+
+        self.frame_counter.load_state(reader)?;
+        self.cycle = reader.read_u64()?;
+        self.frame_irq = reader.read_bool()?;
+        self.dmc_irq = reader.read_bool()?;
+
+        self.audio_processor.load_state(reader)
+    }
+}
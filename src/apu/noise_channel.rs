@@ -1,5 +1,7 @@
 use crate::apu::LENGTH_TABLE;
 use crate::apu::envelope::Envelope;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 
 const NOISE_PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
 
@@ -117,3 +119,37 @@ impl Default for NoiseChannel {
         Self::new()
     }
 }
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.length_counter_halt);
+        writer.write_bool(self.constant_volume);
+        writer.write_u8(self.volume);
+        writer.write_bool(self.mode);
+        writer.write_u8(self.period_index);
+
+        writer.write_u16(self.timer_period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.length_counter);
+
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.shift_register);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.length_counter_halt = reader.read_bool()?;
+        self.constant_volume = reader.read_bool()?;
+        self.volume = reader.read_u8()?;
+        self.mode = reader.read_bool()?;
+        self.period_index = reader.read_u8()?;
+
+        self.timer_period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.length_counter = reader.read_u8()?;
+
+        self.enabled = reader.read_bool()?;
+        self.shift_register = reader.read_u16()?;
+        self.envelope.load_state(reader)
+    }
+}
@@ -1,5 +1,7 @@
 use crate::apu::LENGTH_TABLE;
 use crate::apu::envelope::Envelope;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 
 const DUTY_CYCLES: [[u8; 8]; 4] = [
     [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
@@ -216,3 +218,69 @@ impl Default for SweepUnit {
         Self::new()
     }
 }
+
+impl SaveState for PulseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.duty_cycle);
+        writer.write_bool(self.length_counter_halt);
+        writer.write_bool(self.constant_volume);
+        writer.write_u8(self.volume);
+
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+
+        writer.write_u16(self.timer_period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.length_counter);
+
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty_position);
+        self.envelope.save_state(writer);
+        self.sweep_unit.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.duty_cycle = reader.read_u8()?;
+        self.length_counter_halt = reader.read_bool()?;
+        self.constant_volume = reader.read_bool()?;
+        self.volume = reader.read_u8()?;
+
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()?;
+
+        self.timer_period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.length_counter = reader.read_u8()?;
+
+        self.enabled = reader.read_bool()?;
+        self.duty_position = reader.read_u8()?;
+        self.envelope.load_state(reader)?;
+        self.sweep_unit.load_state(reader)
+    }
+}
+
+impl SaveState for SweepUnit {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.divider);
+        writer.write_bool(self.reload);
+        writer.write_bool(self.muting);
+        writer.write_bool(self.period_update.is_some());
+        writer.write_u16(self.period_update.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.divider = reader.read_u8()?;
+        self.reload = reader.read_bool()?;
+        self.muting = reader.read_bool()?;
+
+        let has_period_update = reader.read_bool()?;
+        let period_update = reader.read_u16()?;
+        self.period_update = has_period_update.then_some(period_update);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use crate::apu::LENGTH_TABLE;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 
 const TRIANGLE_SEQUENCE: [u8; 32] = [
     15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
@@ -110,3 +112,33 @@ impl Default for TriangleChannel {
         Self::new()
     }
 }
+
+impl SaveState for TriangleChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.counter_halt);
+        writer.write_u8(self.linear_reload);
+        writer.write_u8(self.linear_counter);
+        writer.write_bool(self.linear_reload_flag);
+        writer.write_u16(self.timer_period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.length_counter);
+
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.sequence_position);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.counter_halt = reader.read_bool()?;
+        self.linear_reload = reader.read_u8()?;
+        self.linear_counter = reader.read_u8()?;
+        self.linear_reload_flag = reader.read_bool()?;
+        self.timer_period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.length_counter = reader.read_u8()?;
+
+        self.enabled = reader.read_bool()?;
+        self.sequence_position = reader.read_u8()?;
+
+        Ok(())
+    }
+}
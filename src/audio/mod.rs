@@ -1,4 +1,5 @@
 mod downsampler;
+mod wav;
 
 use crate::audio::downsampler::Downsampler;
 use anyhow::{Context, Result, anyhow};
@@ -11,6 +12,18 @@ use ringbuf::producer::Producer;
 use ringbuf::traits::{Consumer, Observer, Split};
 use ringbuf::{HeapCons, HeapProd, HeapRb};
 
+pub use wav::{WavFormat, WavWriter};
+
+/// Where the emulator sends its audio, one sample per CPU cycle
+pub trait AudioSink {
+    fn push_source_sample(&mut self, sample: f32);
+
+    /// Called once no more samples are coming, e.g. to complete a file
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
 pub struct AudioOutput {
     _stream: Stream,
     sample_producer: HeapProd<f32>,
@@ -63,14 +76,6 @@ impl AudioOutput {
         })
     }
 
-    pub fn push_source_sample(&mut self, sample: f32) {
-        let output_samples = self.downsampler.process(sample);
-
-        for output in output_samples {
-            let _ = self.sample_producer.try_push(output);
-        }
-    }
-
     pub fn buffer_available(&self) -> usize {
         self.sample_producer.vacant_len()
     }
@@ -103,3 +108,13 @@ impl AudioOutput {
         Ok(stream)
     }
 }
+
+impl AudioSink for AudioOutput {
+    fn push_source_sample(&mut self, sample: f32) {
+        let output_samples = self.downsampler.process(sample);
+
+        for output in output_samples {
+            let _ = self.sample_producer.try_push(output);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use super::AudioSink;
+use crate::audio::downsampler::Downsampler;
+use anyhow::{Context, Result, ensure};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WavFormat {
+    Pcm16,
+    Float32,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 2,
+            WavFormat::Float32 => 4,
+        }
+    }
+
+    /// Bytes before the samples: the RIFF header, fmt chunk and data chunk header, and for float data the extended fmt
+    /// chunk and the fact chunk that the format requires for anything other than PCM
+    fn header_size(self) -> u64 {
+        match self {
+            WavFormat::Pcm16 => 44,
+            WavFormat::Float32 => 58,
+        }
+    }
+}
+
+/// Writes audio to a mono WAV file instead of a sound card, resampling it the same way as `AudioOutput`. The sizes in
+/// the header are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    format: WavFormat,
+    sample_rate: u32,
+    downsampler: Downsampler,
+
+    samples_written: u64,
+    error: Option<std::io::Error>, // The first failed write, reported by `finish` since pushing samples can't fail
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, source_rate: f64, sample_rate: u32, format: WavFormat) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Failed to create WAV file: {}", path.display()))?;
+        Self::new(BufWriter::new(file), source_rate, sample_rate, format)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, source_rate: f64, sample_rate: u32, format: WavFormat) -> Result<Self> {
+        write_header(&mut writer, format, sample_rate, 0).context("Failed to write WAV header")?;
+
+        Ok(Self {
+            writer,
+            format,
+            sample_rate,
+            downsampler: Downsampler::new(source_rate, sample_rate as f64, true),
+
+            samples_written: 0,
+            error: None,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Samples written to the file so far, at the output rate
+    pub fn samples_written(&self) -> u64 {
+        self.samples_written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        match self.format {
+            WavFormat::Pcm16 => self.writer.write_all(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes()),
+            WavFormat::Float32 => self.writer.write_all(&sample.to_le_bytes()),
+        }
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn push_source_sample(&mut self, sample: f32) {
+        for output in self.downsampler.process(sample) {
+            if self.error.is_some() {
+                return;
+            }
+
+            match self.write_sample(output) {
+                Ok(()) => self.samples_written += 1,
+                Err(err) => self.error = Some(err),
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err).context("Failed to write WAV samples");
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.format, self.sample_rate, self.samples_written).context("Failed to write WAV header")?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush().context("Failed to write WAV file")
+    }
+}
+
+/// Writes the header for `samples` samples, failing if the file would be too large for the 32-bit RIFF sizes
+fn write_header(writer: &mut impl Write, format: WavFormat, sample_rate: u32, samples: u64) -> Result<()> {
+    let bytes_per_sample = format.bytes_per_sample();
+    let data_size = samples * bytes_per_sample as u64;
+    let riff_size = format.header_size() - 8 + data_size;
+    ensure!(riff_size <= u32::MAX as u64, "WAV file is too large for {} samples, the limit is 4GB", samples);
+
+    let (format_tag, format_size): (u16, u32) = match format {
+        WavFormat::Pcm16 => (1, 16),
+        WavFormat::Float32 => (3, 18), // IEEE float, with an empty extension
+    };
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(riff_size as u32).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&format_size.to_le_bytes())?;
+    writer.write_all(&format_tag.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // Mono
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * bytes_per_sample as u32).to_le_bytes())?; // Bytes per second
+    writer.write_all(&bytes_per_sample.to_le_bytes())?; // Block align
+    writer.write_all(&(bytes_per_sample * 8).to_le_bytes())?; // Bits per sample
+
+    if format == WavFormat::Float32 {
+        writer.write_all(&0u16.to_le_bytes())?;
+
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&(samples as u32).to_le_bytes())?; // Samples per channel
+    }
+
+    writer.write_all(b"data")?;
+    writer.write_all(&(data_size as u32).to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn field(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_pcm16_file() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 8000.0, 4000, WavFormat::Pcm16).unwrap();
+        for _ in 0..2000 {
+            wav.push_source_sample(2.0);
+        }
+        wav.finish().unwrap();
+
+        let samples = wav.samples_written() as usize;
+        assert!((990..=1000).contains(&samples));
+
+        let bytes = wav.into_inner().into_inner();
+        assert_eq!(bytes.len(), 44 + samples * 2);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(field(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(field(&bytes, 24), 4000);
+        assert_eq!(field(&bytes, 40), samples as u32 * 2);
+
+        // Out of range samples are clipped once the filter has settled
+        assert_eq!(i16::from_le_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]), i16::MAX);
+    }
+
+    #[test]
+    fn writes_float_file() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 8000.0, 4000, WavFormat::Float32).unwrap();
+        for _ in 0..2000 {
+            wav.push_source_sample(0.25);
+        }
+        wav.finish().unwrap();
+
+        let samples = wav.samples_written();
+        let bytes = wav.into_inner().into_inner();
+        assert_eq!(bytes.len() as u64, 58 + samples * 4);
+        assert_eq!(field(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(field(&bytes, 16), 18);
+        assert_eq!(u16::from_le_bytes([bytes[20], bytes[21]]), 3);
+        assert_eq!(u16::from_le_bytes([bytes[34], bytes[35]]), 32);
+
+        // Non-PCM data needs a fact chunk with the sample count
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(field(&bytes, 46), samples as u32);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(field(&bytes, 54), samples as u32 * 4);
+
+        let last = f32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+        assert!((last - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn rejects_files_over_4gb() {
+        let largest = (u32::MAX as u64 - 36) / 2;
+        assert!(write_header(&mut Vec::new(), WavFormat::Pcm16, 44100, largest).is_ok());
+        assert!(write_header(&mut Vec::new(), WavFormat::Pcm16, 44100, largest + 1).is_err());
+        assert!(write_header(&mut Vec::new(), WavFormat::Float32, 44100, 1 << 30).is_err());
+    }
+}
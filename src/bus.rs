@@ -2,6 +2,8 @@ use crate::apu::Apu;
 use crate::cartridge::Cartridge;
 use crate::controller::Controller;
 use crate::ppu::Ppu;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -118,3 +120,24 @@ impl Bus {
         self.ppu.borrow_mut().write_to_oam_dma(&buffer);
     }
 }
+
+/// Covers the bus-owned state only, the PPU, APU and cartridge are captured separately by the `Emulator`
+impl SaveState for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        self.controller_1.save_state(writer);
+        self.controller_2.save_state(writer);
+        writer.write_bool(self.nmi_pending);
+        writer.write_bool(self.irq_pending);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.controller_1.load_state(reader)?;
+        self.controller_2.load_state(reader)?;
+        self.nmi_pending = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const WAVE_LENGTH: usize = 64;
+const MOD_TABLE_LENGTH: usize = 64;
+
+// The highest wave sample at volume 32, which peaks at about 2.4 times a pulse channel
+const FULL_SCALE: f32 = PULSE_FULL_VOLUME * 2.4 / (63.0 * 32.0);
+
+// Master volume from $4089, as fractions of full output
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+// Steps the modulation counter takes for each 3-bit table entry, `None` resets it to 0
+const MOD_ADJUSTMENTS: [Option<i8>; 8] = [Some(0), Some(1), Some(2), Some(4), None, Some(-4), Some(-2), Some(-1)];
+
+// The output goes through an RC low-pass filter with a cutoff of about 2kHz, this is its coefficient at the CPU rate
+const FILTER_ALPHA: f32 = 0.007_0;
+
+/// The volume and modulation envelopes, which ramp their gain up or down by one every few thousand CPU cycles
+#[derive(Default)]
+struct FdsEnvelope {
+    control: u8, // $4080/$4084: direct gain (bit 7), increase (bit 6), speed or gain (bits 0-5)
+    gain: u8,
+    timer: u32,
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, value: u8, master_speed: u8) {
+        self.control = value;
+        if value & 0x80 != 0 {
+            self.gain = value & 0x3F;
+        }
+        self.reload(master_speed);
+    }
+
+    fn reload(&mut self, master_speed: u8) {
+        self.timer = 8 * (master_speed as u32 + 1) * ((self.control & 0x3F) as u32 + 1);
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.control & 0x80 != 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.reload(master_speed);
+        if self.control & 0x40 != 0 {
+            self.gain = (self.gain + 1).min(32);
+        } else {
+            self.gain = self.gain.saturating_sub(1);
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.control);
+        writer.write_u8(self.gain);
+        writer.write_u32(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.control = reader.read_u8()?;
+        self.gain = reader.read_u8()?;
+        self.timer = reader.read_u32()?;
+
+        Ok(())
+    }
+}
+
+/// The Famicom Disk System's sound channel: a 64-step, 6-bit wavetable whose pitch is bent by a second wavetable of
+/// frequency modulation steps, each with its own gain envelope.
+pub struct FdsAudio {
+    wave: [u8; WAVE_LENGTH],
+    wave_write: bool,  // $4089 bit 7, holds the output while the CPU writes the wavetable
+    master_volume: u8, // $4089 bits 0-1
+    pitch: u16,        // $4082/$4083 bits 0-3
+    wave_halt: bool,   // $4083 bit 7
+    envelopes_halt: bool,
+    wave_accumulator: u32,
+    output_level: u8, // Wave sample latched at the last step
+
+    volume: FdsEnvelope,
+    modulation: FdsEnvelope,
+    master_speed: u8, // $408A
+
+    mod_table: [u8; MOD_TABLE_LENGTH],
+    mod_position: usize,
+    mod_pitch: u16,
+    mod_halt: bool,  // $4087 bit 7, also allows writes to the modulation table
+    mod_counter: i8, // 7-bit signed
+    mod_accumulator: u32,
+
+    filtered: f32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        Self {
+            wave: [0; WAVE_LENGTH],
+            wave_write: false,
+            master_volume: 0,
+            pitch: 0,
+            wave_halt: true,
+            envelopes_halt: true,
+            wave_accumulator: 0,
+            output_level: 0,
+
+            volume: FdsEnvelope::default(),
+            modulation: FdsEnvelope::default(),
+            master_speed: 0xE8,
+
+            mod_table: [0; MOD_TABLE_LENGTH],
+            mod_position: 0,
+            mod_pitch: 0,
+            mod_halt: true,
+            mod_counter: 0,
+            mod_accumulator: 0,
+
+            filtered: 0.0,
+        }
+    }
+
+    /// CPU reads from $4040-$4092
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => Some(self.wave[(address - 0x4040) as usize] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    /// CPU writes to $4040-$408A
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write => self.wave[(address - 0x4040) as usize] = value & 0x3F,
+            0x4080 => self.volume.write(value, self.master_speed),
+            0x4082 => self.pitch = (self.pitch & 0x0F00) | value as u16,
+            0x4083 => {
+                self.pitch = (self.pitch & 0x00FF) | (((value & 0x0F) as u16) << 8);
+                self.wave_halt = value & 0x80 != 0;
+                self.envelopes_halt = value & 0x40 != 0;
+
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_halt {
+                    self.volume.reload(self.master_speed);
+                    self.modulation.reload(self.master_speed);
+                }
+            }
+            0x4084 => self.modulation.write(value, self.master_speed),
+            0x4085 => self.mod_counter = sign_extend_7(value),
+            0x4086 => self.mod_pitch = (self.mod_pitch & 0x0F00) | value as u16,
+            0x4087 => {
+                self.mod_pitch = (self.mod_pitch & 0x00FF) | (((value & 0x0F) as u16) << 8);
+                self.mod_halt = value & 0x80 != 0;
+
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // Each write fills two consecutive entries, and only while modulation is halted
+            0x4088 if self.mod_halt => {
+                self.mod_table[self.mod_position] = value & 0x07;
+                self.mod_table[(self.mod_position + 1) % MOD_TABLE_LENGTH] = value & 0x07;
+                self.mod_position = (self.mod_position + 2) % MOD_TABLE_LENGTH;
+            }
+            0x4089 => {
+                self.wave_write = value & 0x80 != 0;
+                self.master_volume = value & 0x03;
+            }
+            0x408A => self.master_speed = value,
+            _ => {}
+        }
+    }
+
+    /// Pitch of the wave after modulation, following the multiply and odd rounding the hardware does
+    fn modulated_pitch(&self) -> u16 {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= self.pitch as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (self.pitch as i32 + temp).clamp(0, 0xFFFF) as u16
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        if !self.envelopes_halt && !self.wave_halt && self.master_speed != 0 {
+            self.volume.clock(self.master_speed);
+            self.modulation.clock(self.master_speed);
+        }
+
+        if !self.mod_halt {
+            self.mod_accumulator += self.mod_pitch as u32;
+            while self.mod_accumulator >= 0x10000 {
+                self.mod_accumulator -= 0x10000;
+                self.mod_counter = match MOD_ADJUSTMENTS[self.mod_table[self.mod_position] as usize] {
+                    Some(step) => sign_extend_7(self.mod_counter.wrapping_add(step) as u8),
+                    None => 0,
+                };
+                self.mod_position = (self.mod_position + 1) % MOD_TABLE_LENGTH;
+            }
+        }
+
+        if !self.wave_halt && !self.wave_write {
+            self.wave_accumulator = (self.wave_accumulator + self.modulated_pitch() as u32) & 0x3F_FFFF;
+            self.output_level = self.wave[(self.wave_accumulator >> 16) as usize];
+        }
+
+        let level = self.output_level as f32 * self.volume.gain.min(32) as f32 * MASTER_VOLUMES[self.master_volume as usize];
+        self.filtered += (level * FULL_SCALE - self.filtered) * FILTER_ALPHA;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.filtered
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        let pitch = if self.wave_halt { 0 } else { self.modulated_pitch() };
+
+        vec![ExpansionChannel {
+            name: "FDS Wave",
+            enabled: !self.wave_halt,
+            period: pitch,
+            frequency: NTSC_CPU_FREQUENCY * pitch as f64 / (1 << 22) as f64,
+            output: (self.output_level as f32 * self.volume.gain.min(32) as f32) / (63.0 * 32.0),
+        }]
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.wave);
+        writer.write_bool(self.wave_write);
+        writer.write_u8(self.master_volume);
+        writer.write_u16(self.pitch);
+        writer.write_bool(self.wave_halt);
+        writer.write_bool(self.envelopes_halt);
+        writer.write_u32(self.wave_accumulator);
+        writer.write_u8(self.output_level);
+
+        self.volume.save_state(writer);
+        self.modulation.save_state(writer);
+        writer.write_u8(self.master_speed);
+
+        writer.write_bytes(&self.mod_table);
+        writer.write_u8(self.mod_position as u8);
+        writer.write_u16(self.mod_pitch);
+        writer.write_bool(self.mod_halt);
+        writer.write_u8(self.mod_counter as u8);
+        writer.write_u32(self.mod_accumulator);
+
+        writer.write_f32(self.filtered);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.wave)?;
+        self.wave_write = reader.read_bool()?;
+        self.master_volume = reader.read_u8()? & 0x03;
+        self.pitch = reader.read_u16()?;
+        self.wave_halt = reader.read_bool()?;
+        self.envelopes_halt = reader.read_bool()?;
+        self.wave_accumulator = reader.read_u32()? & 0x3F_FFFF;
+        self.output_level = reader.read_u8()?;
+
+        self.volume.load_state(reader)?;
+        self.modulation.load_state(reader)?;
+        self.master_speed = reader.read_u8()?;
+
+        reader.read_bytes_into(&mut self.mod_table)?;
+        self.mod_position = reader.read_u8()? as usize % MOD_TABLE_LENGTH;
+        self.mod_pitch = reader.read_u16()?;
+        self.mod_halt = reader.read_bool()?;
+        self.mod_counter = sign_extend_7(reader.read_u8()?);
+        self.mod_accumulator = reader.read_u32()?;
+
+        self.filtered = reader.read_f32()?;
+
+        Ok(())
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The modulation counter is 7 bits wide and wraps around within -64..=63
+fn sign_extend_7(value: u8) -> i8 {
+    ((value << 1) as i8) >> 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_ramp() -> FdsAudio {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4089, 0x80);
+        for i in 0..64 {
+            audio.write(0x4040 + i, i as u8);
+        }
+        audio.write(0x4089, 0x00);
+        audio.write(0x4080, 0xA0); // Direct gain of 32
+        audio
+    }
+
+    #[test]
+    fn wavetable_only_writable_when_enabled() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4040, 0x15);
+        assert_eq!(audio.read(0x4040), Some(0x40));
+
+        audio.write(0x4089, 0x80);
+        audio.write(0x4040, 0x15);
+        assert_eq!(audio.read(0x4040), Some(0x55));
+    }
+
+    #[test]
+    fn wave_steps_at_pitch() {
+        let mut audio = with_ramp();
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x04); // Pitch $400, one step every 64 cycles
+
+        for _ in 0..64 {
+            audio.clock();
+        }
+        assert_eq!(audio.output_level, 1);
+
+        for _ in 0..64 * 9 {
+            audio.clock();
+        }
+        assert_eq!(audio.output_level, 10);
+        assert!(audio.output() > 0.0);
+    }
+
+    #[test]
+    fn modulation_bends_pitch() {
+        let mut audio = with_ramp();
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x04);
+        assert_eq!(audio.modulated_pitch(), 0x400);
+
+        audio.write(0x4084, 0x81); // Modulation gain 1
+        audio.write(0x4085, 0x10); // Counter +16
+        assert_eq!(audio.modulated_pitch(), 0x410);
+
+        audio.write(0x4085, 0x70); // Counter -16
+        assert_eq!(audio.modulated_pitch(), 0x3F0);
+    }
+
+    #[test]
+    fn modulation_table_steps_counter() {
+        let mut audio = FdsAudio::new();
+        audio.write(0x4087, 0x80);
+        for _ in 0..32 {
+            audio.write(0x4088, 0x03); // +4 per step
+        }
+        audio.write(0x4086, 0x00);
+        audio.write(0x4087, 0x01); // Mod pitch $100, one step every 256 cycles
+
+        for _ in 0..256 * 3 {
+            audio.clock();
+        }
+        assert_eq!(audio.mod_counter, 12);
+
+        for _ in 0..256 * 14 {
+            audio.clock();
+        }
+        assert_eq!(audio.mod_counter, -60); // 68 wraps around to -60
+    }
+
+    #[test]
+    fn volume_envelope_ramps() {
+        let mut audio = with_ramp();
+        audio.write(0x4083, 0x04);
+        audio.write(0x408A, 0x01);
+        audio.write(0x4080, 0x00); // Decrease at speed 0, every 16 cycles with a master speed of 1
+
+        for _ in 0..17 {
+            audio.clock();
+        }
+        assert_eq!(audio.volume.gain, 31);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::apu::PulseChannel;
+use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
+const FRAME_PERIOD: u16 = 7457; // CPU cycles between envelope and length counter clocks, about 240Hz
+
+const PULSE_STEP_SCALE: f32 = PULSE_FULL_VOLUME / 15.0;
+const PCM_SCALE: f32 = PULSE_FULL_VOLUME * 2.0 / 255.0; // Full-scale PCM at about the level of both pulses
+
+/// MMC5 audio: two pulse channels that behave like the 2A03's without the sweep unit, and an 8-bit PCM channel. The
+/// pulses' envelopes and length counters are clocked by the mapper's own 240Hz divider rather than the APU's frame counter.
+///
+/// Only the PCM channel's write mode is emulated, read mode samples bytes as the CPU reads them from $8000-$BFFF, which
+/// no released game relies on.
+pub struct Mmc5Audio {
+    pulse_1: PulseChannel,
+    pulse_2: PulseChannel,
+
+    pcm: u8,
+    pcm_read_mode: bool,   // $5010 bit 0
+    pcm_irq_enabled: bool, // $5010 bit 7
+
+    frame_divider: u16,
+    odd_cycle: bool, // Pulse timers are clocked every other CPU cycle
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse_1: PulseChannel::new(),
+            pulse_2: PulseChannel::new(),
+
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+
+            frame_divider: 0,
+            odd_cycle: false,
+        }
+    }
+
+    /// Reads from $5010 and $5015
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0x5010 => self.pcm_read_mode as u8,
+            0x5015 => (self.pulse_1.length_counter > 0) as u8 | (((self.pulse_2.length_counter > 0) as u8) << 1),
+            _ => 0,
+        }
+    }
+
+    /// Writes to $5000-$5015
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0x5000 => self.pulse_1.write_control(value),
+            0x5002 => self.pulse_1.write_timer_low(value),
+            0x5003 => self.pulse_1.write_timer_high(value),
+            0x5004 => self.pulse_2.write_control(value),
+            0x5006 => self.pulse_2.write_timer_low(value),
+            0x5007 => self.pulse_2.write_timer_high(value),
+            0x5010 => {
+                self.pcm_read_mode = value & 0x01 != 0;
+                self.pcm_irq_enabled = value & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm = value, // Writing 0 is ignored
+            0x5015 => {
+                self.pulse_1.set_enabled(value & 0x01 != 0);
+                self.pulse_2.set_enabled(value & 0x02 != 0);
+            }
+            _ => {} // $5001/$5005 would be the sweep registers, which the MMC5 doesn't have
+        }
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        if self.odd_cycle {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+
+            for pulse in [&mut self.pulse_1, &mut self.pulse_2] {
+                pulse.clock_envelope();
+                pulse.clock_length_counter();
+            }
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let pulses = (self.pulse_1.raw_output() + self.pulse_2.raw_output()) as f32;
+        pulses * PULSE_STEP_SCALE + self.pcm as f32 * PCM_SCALE
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        let pulse = |name, pulse: &PulseChannel| ExpansionChannel {
+            name,
+            enabled: pulse.enabled,
+            period: pulse.timer_period,
+            frequency: NTSC_CPU_FREQUENCY / (16.0 * (pulse.timer_period as f64 + 1.0)),
+            output: pulse.raw_output() as f32 / 15.0,
+        };
+
+        vec![
+            pulse("MMC5 Pulse 1", &self.pulse_1),
+            pulse("MMC5 Pulse 2", &self.pulse_2),
+            ExpansionChannel {
+                name: "MMC5 PCM",
+                enabled: !self.pcm_read_mode,
+                period: 0,
+                frequency: 0.0,
+                output: self.pcm as f32 / 255.0,
+            },
+        ]
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse_1.save_state(writer);
+        self.pulse_2.save_state(writer);
+
+        writer.write_u8(self.pcm);
+        writer.write_bool(self.pcm_read_mode);
+        writer.write_bool(self.pcm_irq_enabled);
+
+        writer.write_u16(self.frame_divider);
+        writer.write_bool(self.odd_cycle);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.pulse_1.load_state(reader)?;
+        self.pulse_2.load_state(reader)?;
+
+        self.pcm = reader.read_u8()?;
+        self.pcm_read_mode = reader.read_bool()?;
+        self.pcm_irq_enabled = reader.read_bool()?;
+
+        self.frame_divider = reader.read_u16()? % FRAME_PERIOD;
+        self.odd_cycle = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_plays_at_constant_volume() {
+        let mut audio = Mmc5Audio::new();
+        audio.write(0x5015, 0x01);
+        audio.write(0x5000, 0xBF); // 75% duty, halt, constant volume 15
+        audio.write(0x5002, 0x10);
+        audio.write(0x5003, 0x08);
+
+        // The envelope picks up the constant volume on its first clock
+        for _ in 0..FRAME_PERIOD {
+            audio.clock();
+        }
+
+        let mut outputs = Vec::new();
+        for _ in 0..(8 * 17 * 2) {
+            audio.clock();
+            outputs.push(audio.output());
+        }
+
+        assert!(outputs.contains(&(15.0 * PULSE_STEP_SCALE)));
+        assert!(outputs.contains(&0.0));
+        assert_eq!(audio.read(0x5015), 0x01);
+    }
+
+    #[test]
+    fn length_counter_runs_at_240hz() {
+        let mut audio = Mmc5Audio::new();
+        audio.write(0x5015, 0x02);
+        audio.write(0x5004, 0x10);
+        audio.write(0x5007, 0x18); // Length index 3, 2 clocks
+
+        for _ in 0..FRAME_PERIOD * 2 - 1 {
+            audio.clock();
+        }
+        assert_eq!(audio.read(0x5015), 0x02);
+
+        audio.clock();
+        assert_eq!(audio.read(0x5015), 0x00);
+    }
+
+    #[test]
+    fn pcm_write_mode_ignores_zero() {
+        let mut audio = Mmc5Audio::new();
+        audio.write(0x5011, 0x80);
+        audio.write(0x5011, 0x00);
+        assert_eq!(audio.output(), 0x80 as f32 * PCM_SCALE);
+
+        audio.write(0x5010, 0x01); // Read mode, writes to $5011 do nothing
+        audio.write(0x5011, 0x40);
+        assert_eq!(audio.output(), 0x80 as f32 * PCM_SCALE);
+    }
+}
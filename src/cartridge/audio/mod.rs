@@ -0,0 +1,26 @@
+mod fds;
+mod mmc5;
+mod namco_163;
+mod sunsoft_5b;
+mod vrc6;
+mod vrc7;
+
+pub use fds::FdsAudio;
+pub use mmc5::Mmc5Audio;
+pub use namco_163::Namco163Audio;
+pub use sunsoft_5b::Sunsoft5b;
+pub use vrc6::Vrc6Audio;
+pub use vrc7::Vrc7Audio;
+
+/// Output of a lone 2A03 pulse channel at full volume, from the mixer's pulse formula `95.88 / (8128 / n + 100)`. Each
+/// chip's output is scaled against this level so that they all sit at their usual loudness next to the APU.
+pub const PULSE_FULL_VOLUME: f32 = 95.88 / (8128.0 / 15.0 + 100.0);
+
+/// A snapshot of one expansion audio channel for the APU debug panel
+pub struct ExpansionChannel {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub period: u16,
+    pub frequency: f64,
+    pub output: f32, // 0.0-1.0 of the channel's maximum
+}
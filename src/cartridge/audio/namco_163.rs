@@ -0,0 +1,267 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const SOUND_RAM_SIZE: usize = 128;
+const CYCLES_PER_CHANNEL: u8 = 15; // One channel is updated every 15 CPU cycles
+
+// A full-range wave at volume 15 spans 15 * 15 units, about twice the swing of a pulse channel
+const UNIT_SCALE: f32 = PULSE_FULL_VOLUME * 2.0 / 225.0;
+
+/// Namco 163 wavetable audio. Up to 8 channels play 4-bit samples from the same 128 bytes of internal RAM that also
+/// holds their registers, channel 7 at $78-$7F down to channel 0 at $40-$47:
+///
+/// ```text
+/// +0 frequency low      +1 phase low
+/// +2 frequency mid      +3 phase mid
+/// +4 frequency high (bits 0-1), wave length 256 - (value & $FC)
+/// +5 phase high         +6 wave address in 4-bit samples
+/// +7 volume (bits 0-3), $7F bits 4-6 also hold the number of enabled channels minus 1
+/// ```
+///
+/// Like the hardware, the output is whichever channel was updated last, switching to the next every 15 cycles. With
+/// many channels enabled that switching falls into the audible range, `average_channels` mixes them evenly instead.
+pub struct Namco163Audio {
+    pub average_channels: bool,
+
+    ram: [u8; SOUND_RAM_SIZE],
+    address: u8, // $F800: RAM address (bits 0-6) and auto-increment (bit 7)
+
+    cycle_counter: u8,
+    current_channel: u8, // The channel last updated, which is the one being output
+    outputs: [i16; 8],   // Last output of each channel, (sample - 8) * volume
+}
+
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Self {
+            average_channels: false,
+
+            ram: [0; SOUND_RAM_SIZE],
+            address: 0,
+
+            cycle_counter: 0,
+            current_channel: 0,
+            outputs: [0; 8],
+        }
+    }
+
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value;
+    }
+
+    /// $4800 reads and writes go to the selected RAM address
+    pub fn read_data(&mut self) -> u8 {
+        let value = self.ram[(self.address & 0x7F) as usize];
+        self.auto_increment();
+        value
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.ram[(self.address & 0x7F) as usize] = value;
+        self.auto_increment();
+    }
+
+    fn auto_increment(&mut self) {
+        if self.address & 0x80 != 0 {
+            self.address = 0x80 | (self.address.wrapping_add(1) & 0x7F);
+        }
+    }
+
+    fn enabled_channels(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    fn channel_base(channel: u8) -> usize {
+        0x40 + channel as usize * 8
+    }
+
+    fn frequency(&self, channel: u8) -> u32 {
+        let base = Self::channel_base(channel);
+        ((self.ram[base + 4] as u32 & 0x03) << 16) | ((self.ram[base + 2] as u32) << 8) | self.ram[base] as u32
+    }
+
+    fn wave_length(&self, channel: u8) -> u32 {
+        256 - (self.ram[Self::channel_base(channel) + 4] & 0xFC) as u32
+    }
+
+    fn sample(&self, index: u8) -> u8 {
+        let byte = self.ram[(index >> 1) as usize];
+        if index & 0x01 == 0 { byte & 0x0F } else { byte >> 4 }
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.cycle_counter += 1;
+        if self.cycle_counter < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.cycle_counter = 0;
+
+        // Channels are updated from 7 down to the lowest enabled one
+        let lowest_channel = 8 - self.enabled_channels();
+        self.current_channel = if self.current_channel <= lowest_channel {
+            7
+        } else {
+            self.current_channel - 1
+        };
+
+        self.update_channel(self.current_channel);
+    }
+
+    fn update_channel(&mut self, channel: u8) {
+        let base = Self::channel_base(channel);
+
+        let phase = ((self.ram[base + 5] as u32) << 16) | ((self.ram[base + 3] as u32) << 8) | self.ram[base + 1] as u32;
+        let phase = (phase + self.frequency(channel)) % (self.wave_length(channel) << 16);
+
+        self.ram[base + 5] = (phase >> 16) as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 1] = phase as u8;
+
+        let sample = self.sample(((phase >> 16) as u8).wrapping_add(self.ram[base + 6]));
+        let volume = (self.ram[base + 7] & 0x0F) as i16;
+        self.outputs[channel as usize] = (sample as i16 - 8) * volume;
+    }
+
+    pub fn output(&self) -> f32 {
+        if !self.average_channels {
+            return self.outputs[self.current_channel as usize] as f32 * UNIT_SCALE;
+        }
+
+        let count = self.enabled_channels();
+        let sum: i16 = self.outputs[(8 - count) as usize..].iter().sum();
+
+        sum as f32 / count as f32 * UNIT_SCALE
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        const NAMES: [&str; 8] = ["N163 1", "N163 2", "N163 3", "N163 4", "N163 5", "N163 6", "N163 7", "N163 8"];
+        let count = self.enabled_channels();
+
+        // The channels are named in the order games enable them, channel 7 first
+        (0..8u8)
+            .map(|i| {
+                let channel = 7 - i;
+                let frequency = self.frequency(channel);
+                let cycles_per_update = CYCLES_PER_CHANNEL as f64 * count as f64;
+
+                ExpansionChannel {
+                    name: NAMES[i as usize],
+                    enabled: i < count,
+                    period: self.wave_length(channel) as u16,
+                    frequency: NTSC_CPU_FREQUENCY * frequency as f64 / (cycles_per_update * 65536.0 * self.wave_length(channel) as f64),
+                    output: (self.outputs[channel as usize].unsigned_abs() as f32 / 120.0).min(1.0),
+                }
+            })
+            .collect()
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_u8(self.address);
+        writer.write_u8(self.cycle_counter);
+        writer.write_u8(self.current_channel);
+        for output in &self.outputs {
+            writer.write_u16(*output as u16);
+        }
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.address = reader.read_u8()?;
+        self.cycle_counter = reader.read_u8()?;
+        self.current_channel = reader.read_u8()? & 0x07;
+        for output in &mut self.outputs {
+            *output = reader.read_u16()? as i16;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(audio: &mut Namco163Audio, address: u8, value: u8) {
+        audio.write_address(address);
+        audio.write_data(value);
+    }
+
+    #[test]
+    fn auto_increment_wraps_within_ram() {
+        let mut audio = Namco163Audio::new();
+        audio.write_address(0xFF);
+        audio.write_data(0x12);
+        audio.write_data(0x34);
+
+        assert_eq!(audio.ram[0x7F], 0x12);
+        assert_eq!(audio.ram[0x00], 0x34);
+    }
+
+    #[test]
+    fn plays_wave_from_sound_ram() {
+        let mut audio = Namco163Audio::new();
+        write(&mut audio, 0x00, 0xF0); // Samples 0 and 15
+        write(&mut audio, 0x7C, 0xFC); // Channel 7 wave length 4
+        write(&mut audio, 0x78, 0x00);
+        write(&mut audio, 0x7A, 0x00);
+        write(&mut audio, 0x7F, 0x0F); // One channel, volume 15
+
+        for _ in 0..CYCLES_PER_CHANNEL {
+            audio.clock();
+        }
+        assert_eq!(audio.outputs[7], -8 * 15);
+
+        // Step one sample per update
+        write(&mut audio, 0x7C, 0xFD);
+        for _ in 0..CYCLES_PER_CHANNEL {
+            audio.clock();
+        }
+        assert_eq!(audio.outputs[7], 7 * 15);
+    }
+
+    #[test]
+    fn updates_enabled_channels_in_turn() {
+        let mut audio = Namco163Audio::new();
+        write(&mut audio, 0x7F, 0x10); // Channels 7 and 6
+
+        let mut updated = vec![];
+        for _ in 0..4 {
+            for _ in 0..CYCLES_PER_CHANNEL {
+                audio.clock();
+            }
+            updated.push(audio.current_channel);
+        }
+
+        assert_eq!(updated, [7, 6, 7, 6]);
+    }
+
+    #[test]
+    fn outputs_one_channel_at_a_time() {
+        let mut audio = Namco163Audio::new();
+        write(&mut audio, 0x00, 0xFF); // Every sample 15
+        write(&mut audio, 0x74, 0xFC);
+        write(&mut audio, 0x77, 0x0A); // Channel 6 volume 10
+        write(&mut audio, 0x7C, 0xFC);
+        write(&mut audio, 0x7F, 0x1F); // Channels 7 and 6, channel 7 volume 15
+
+        for _ in 0..CYCLES_PER_CHANNEL {
+            audio.clock();
+        }
+        assert_eq!(audio.output(), 7.0 * 15.0 * UNIT_SCALE);
+
+        // The output stays on each channel until the next one is updated, at its full level
+        for _ in 0..CYCLES_PER_CHANNEL - 1 {
+            audio.clock();
+        }
+        assert_eq!(audio.output(), 7.0 * 15.0 * UNIT_SCALE);
+        audio.clock();
+        assert_eq!(audio.output(), 7.0 * 10.0 * UNIT_SCALE);
+
+        audio.average_channels = true;
+        assert_eq!(audio.output(), 7.0 * 12.5 * UNIT_SCALE);
+    }
+}
@@ -0,0 +1,345 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const CHANNELS: usize = 3;
+const PRESCALER_PERIOD: u8 = 16; // Tone, noise and envelope generators step once every 16 CPU cycles
+const CHANNEL_SCALE: f32 = PULSE_FULL_VOLUME;
+
+/// Amplitude of each of the 32 output levels, spaced 1.5dB apart with level 0 silent
+const LEVELS: [f32; 32] = build_levels();
+
+const fn build_levels() -> [f32; 32] {
+    let mut levels = [0.0; 32];
+    let mut amplitude = 1.0;
+    let mut level = 31;
+
+    while level > 0 {
+        levels[level] = amplitude;
+        amplitude /= 1.188_502_2; // 10^(1.5 / 20)
+        level -= 1;
+    }
+
+    levels
+}
+
+#[derive(Clone, Copy, Default)]
+struct Tone {
+    counter: u16,
+    output: bool,
+}
+
+/// The Sunsoft 5B, a YM2149F (AY-3-8910 compatible) inside the FME-7's package. Three square wave channels that can
+/// each mix in a shared noise generator, with a fixed 4-bit volume or a shared 32-step envelope per channel.
+///
+/// Registers are written by selecting one through $C000-$DFFF and then writing its value to $E000-$FFFF.
+pub struct Sunsoft5b {
+    address: u8,
+    registers: [u8; 16],
+
+    prescaler: u8,
+    tones: [Tone; CHANNELS],
+
+    noise_counter: u8,
+    noise_lfsr: u32, // 17-bit
+    noise_half: bool,
+
+    envelope_counter: u16,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Sunsoft5b {
+    pub fn new() -> Self {
+        Self {
+            address: 0,
+            registers: [0; 16],
+
+            prescaler: 0,
+            tones: [Tone::default(); CHANNELS],
+
+            noise_counter: 0,
+            noise_lfsr: 1,
+            noise_half: false,
+
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: false,
+        }
+    }
+
+    /// $C000-$DFFF, the upper 4 bits must be clear for the following data write to reach the chip
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value;
+    }
+
+    /// $E000-$FFFF
+    pub fn write_data(&mut self, value: u8) {
+        if self.address & 0xF0 != 0 {
+            return;
+        }
+
+        let register = (self.address & 0x0F) as usize;
+        self.registers[register] = value;
+
+        if register == 0x0D {
+            self.restart_envelope();
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let period = ((self.registers[channel * 2 + 1] as u16 & 0x0F) << 8) | self.registers[channel * 2] as u16;
+        period.max(1)
+    }
+
+    fn noise_period(&self) -> u8 {
+        (self.registers[0x06] & 0x1F).max(1)
+    }
+
+    fn envelope_period(&self) -> u16 {
+        (((self.registers[0x0C] as u16) << 8) | self.registers[0x0B] as u16).max(1)
+    }
+
+    fn restart_envelope(&mut self) {
+        self.envelope_counter = 0;
+        self.envelope_step = 0;
+        self.envelope_attack = self.registers[0x0D] & 0x04 != 0;
+        self.envelope_holding = false;
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER_PERIOD {
+            return;
+        }
+        self.prescaler = 0;
+
+        for channel in 0..CHANNELS {
+            let period = self.tone_period(channel);
+            let tone = &mut self.tones[channel];
+
+            tone.counter += 1;
+            if tone.counter >= period {
+                tone.counter = 0;
+                tone.output = !tone.output;
+            }
+        }
+
+        // The noise generator runs at half the rate of the tone generators for the same period
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period() {
+            self.noise_counter = 0;
+            self.noise_half = !self.noise_half;
+
+            if self.noise_half {
+                let feedback = (self.noise_lfsr ^ (self.noise_lfsr >> 3)) & 0x01;
+                self.noise_lfsr = (self.noise_lfsr >> 1) | (feedback << 16);
+            }
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period() {
+            self.envelope_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    /// Steps the envelope through its 32 levels, register $0D decides what happens at the end of each ramp:
+    /// bit 3 continue, bit 2 attack (ramp up), bit 1 alternate direction and bit 0 hold
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        let shape = self.registers[0x0D];
+        let (continue_, alternate, hold) = (shape & 0x08 != 0, shape & 0x02 != 0, shape & 0x01 != 0);
+
+        if !continue_ {
+            self.envelope_holding = true;
+            self.envelope_attack = false; // Stays silent
+        } else if hold {
+            self.envelope_holding = true;
+            self.envelope_attack ^= alternate;
+        } else {
+            self.envelope_attack ^= alternate;
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack { self.envelope_step } else { 31 - self.envelope_step }
+    }
+
+    /// The 5-bit output level of a channel, 0 if its tone and noise gates are currently closed
+    pub fn channel_level(&self, channel: usize) -> u8 {
+        let mixer = self.registers[0x07];
+        let tone_disabled = mixer & (0x01 << channel) != 0;
+        let noise_disabled = mixer & (0x08 << channel) != 0;
+
+        let tone = self.tones[channel].output || tone_disabled;
+        let noise = self.noise_lfsr & 0x01 != 0 || noise_disabled;
+        if !(tone && noise) {
+            return 0;
+        }
+
+        let volume = self.registers[0x08 + channel];
+        if volume & 0x10 != 0 {
+            self.envelope_level()
+        } else if volume & 0x0F == 0 {
+            0
+        } else {
+            (volume & 0x0F) * 2 + 1
+        }
+    }
+
+    /// Mixed output of the three channels, scaled relative to the 2A03's output
+    pub fn output(&self) -> f32 {
+        (0..CHANNELS).map(|channel| LEVELS[self.channel_level(channel) as usize]).sum::<f32>() * CHANNEL_SCALE
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        const NAMES: [&str; CHANNELS] = ["5B Channel A", "5B Channel B", "5B Channel C"];
+
+        (0..CHANNELS)
+            .map(|channel| {
+                let period = self.tone_period(channel);
+                ExpansionChannel {
+                    name: NAMES[channel],
+                    enabled: self.registers[0x07] & (0x09 << channel) != (0x09 << channel),
+                    period,
+                    frequency: NTSC_CPU_FREQUENCY / (32.0 * period as f64),
+                    output: LEVELS[self.channel_level(channel) as usize],
+                }
+            })
+            .collect()
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.address);
+        writer.write_bytes(&self.registers);
+
+        writer.write_u8(self.prescaler);
+        for tone in &self.tones {
+            writer.write_u16(tone.counter);
+            writer.write_bool(tone.output);
+        }
+
+        writer.write_u8(self.noise_counter);
+        writer.write_u32(self.noise_lfsr);
+        writer.write_bool(self.noise_half);
+
+        writer.write_u16(self.envelope_counter);
+        writer.write_u8(self.envelope_step);
+        writer.write_bool(self.envelope_attack);
+        writer.write_bool(self.envelope_holding);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.address = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.registers)?;
+
+        self.prescaler = reader.read_u8()?;
+        for tone in &mut self.tones {
+            tone.counter = reader.read_u16()?;
+            tone.output = reader.read_bool()?;
+        }
+
+        self.noise_counter = reader.read_u8()?;
+        self.noise_lfsr = reader.read_u32()?;
+        self.noise_half = reader.read_bool()?;
+
+        self.envelope_counter = reader.read_u16()?;
+        self.envelope_step = reader.read_u8()?.min(31);
+        self.envelope_attack = reader.read_bool()?;
+        self.envelope_holding = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(chip: &mut Sunsoft5b, register: u8, value: u8) {
+        chip.write_address(register);
+        chip.write_data(value);
+    }
+
+    #[test]
+    fn tone_toggles_at_period() {
+        let mut chip = Sunsoft5b::new();
+        write(&mut chip, 0x00, 0x02); // Channel A period 2
+        write(&mut chip, 0x07, 0x3E); // Only channel A tone enabled
+        write(&mut chip, 0x08, 0x0F);
+
+        assert_eq!(chip.channel_level(0), 0);
+
+        for _ in 0..2 * PRESCALER_PERIOD {
+            chip.clock();
+        }
+        assert_eq!(chip.channel_level(0), 31);
+
+        for _ in 0..2 * PRESCALER_PERIOD {
+            chip.clock();
+        }
+        assert_eq!(chip.channel_level(0), 0);
+    }
+
+    #[test]
+    fn disabled_tone_and_noise_output_constant_volume() {
+        let mut chip = Sunsoft5b::new();
+        write(&mut chip, 0x07, 0x3F);
+        write(&mut chip, 0x09, 0x07);
+
+        assert_eq!(chip.channel_level(1), 15);
+        assert!((chip.output() - LEVELS[15] * CHANNEL_SCALE).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ignores_writes_with_upper_address_bits_set() {
+        let mut chip = Sunsoft5b::new();
+        write(&mut chip, 0x07, 0x3F);
+        write(&mut chip, 0x18, 0x0F);
+
+        assert_eq!(chip.channel_level(0), 0);
+    }
+
+    #[test]
+    fn envelope_ramps_and_holds() {
+        let mut chip = Sunsoft5b::new();
+        write(&mut chip, 0x07, 0x3F);
+        write(&mut chip, 0x08, 0x10); // Channel A uses the envelope
+        write(&mut chip, 0x0B, 0x01);
+        write(&mut chip, 0x0D, 0x0D); // Continue, attack, hold: ramp up once and stay at the top
+
+        assert_eq!(chip.channel_level(0), 0);
+
+        for _ in 0..31 * PRESCALER_PERIOD as usize {
+            chip.clock();
+        }
+        assert_eq!(chip.channel_level(0), 31);
+
+        for _ in 0..64 * PRESCALER_PERIOD as usize {
+            chip.clock();
+        }
+        assert_eq!(chip.channel_level(0), 31);
+
+        write(&mut chip, 0x0D, 0x00); // Ramp down once then stay silent
+        assert_eq!(chip.channel_level(0), 31);
+
+        for _ in 0..40 * PRESCALER_PERIOD as usize {
+            chip.clock();
+        }
+        assert_eq!(chip.channel_level(0), 0);
+    }
+}
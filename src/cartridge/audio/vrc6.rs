@@ -0,0 +1,352 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::apu::ChannelVolumes;
+use crate::emulator::NTSC_CPU_FREQUENCY;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const STEP_SCALE: f32 = PULSE_FULL_VOLUME / 15.0;
+
+#[derive(Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    constant: bool, // Ignore the duty cycle and output the volume continuously
+    enabled: bool,
+    period: u16,
+
+    counter: u16,
+    step: u8, // Counts down from 15, the output is high while step <= duty
+}
+
+impl Vrc6Pulse {
+    fn write_control(&mut self, value: u8) {
+        self.volume = value & 0x0F;
+        self.duty = (value >> 4) & 0x07;
+        self.constant = value & 0x80 != 0;
+    }
+
+    fn write_period_low(&mut self, value: u8) {
+        self.period = (self.period & 0x0F00) | value as u16;
+    }
+
+    fn write_period_high(&mut self, value: u8) {
+        self.period = (self.period & 0x00FF) | (((value & 0x0F) as u16) << 8);
+        self.enabled = value & 0x80 != 0;
+
+        if !self.enabled {
+            self.step = 15;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.counter == 0 {
+            self.counter = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.counter -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.constant || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.volume);
+        writer.write_u8(self.duty);
+        writer.write_bool(self.constant);
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.period);
+        writer.write_u16(self.counter);
+        writer.write_u8(self.step);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.volume = reader.read_u8()?;
+        self.duty = reader.read_u8()?;
+        self.constant = reader.read_bool()?;
+        self.enabled = reader.read_bool()?;
+        self.period = reader.read_u16()?;
+        self.counter = reader.read_u16()?;
+        self.step = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+
+    counter: u16,
+    step: u8, // 14 timer clocks per cycle, the accumulator is added to on every second one
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write_rate(&mut self, value: u8) {
+        self.rate = value & 0x3F;
+    }
+
+    fn write_period_low(&mut self, value: u8) {
+        self.period = (self.period & 0x0F00) | value as u16;
+    }
+
+    fn write_period_high(&mut self, value: u8) {
+        self.period = (self.period & 0x00FF) | (((value & 0x0F) as u16) << 8);
+        self.enabled = value & 0x80 != 0;
+
+        if !self.enabled {
+            self.step = 0;
+            self.accumulator = 0;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.counter > 0 {
+            self.counter -= 1;
+            return;
+        }
+
+        self.counter = self.period >> shift;
+        self.step += 1;
+
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// The top 5 bits of the 8-bit accumulator
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rate);
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.period);
+        writer.write_u16(self.counter);
+        writer.write_u8(self.step);
+        writer.write_u8(self.accumulator);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.rate = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        self.period = reader.read_u16()?;
+        self.counter = reader.read_u16()?;
+        self.step = reader.read_u8()?;
+        self.accumulator = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+/// VRC6 expansion audio, two pulse channels with 8 duty cycles and a sawtooth channel. Register addresses are given as
+/// the chip sees them, after the board's address line swap.
+pub struct Vrc6Audio {
+    pulse_1: Vrc6Pulse,
+    pulse_2: Vrc6Pulse,
+    sawtooth: Vrc6Sawtooth,
+
+    halt: bool,
+    shift: u8, // $9003 frequency scaling, dividing every period by 16 or 256
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse_1: Vrc6Pulse {
+                step: 15,
+                ..Default::default()
+            },
+            pulse_2: Vrc6Pulse {
+                step: 15,
+                ..Default::default()
+            },
+            sawtooth: Vrc6Sawtooth::default(),
+
+            halt: false,
+            shift: 0,
+        }
+    }
+
+    /// Writes to $9000-$9003, $A000-$A002 and $B000-$B002
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0x9000 => self.pulse_1.write_control(value),
+            0x9001 => self.pulse_1.write_period_low(value),
+            0x9002 => self.pulse_1.write_period_high(value),
+            0x9003 => {
+                self.halt = value & 0x01 != 0;
+                self.shift = if value & 0x04 != 0 {
+                    8
+                } else if value & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0xA000 => self.pulse_2.write_control(value),
+            0xA001 => self.pulse_2.write_period_low(value),
+            0xA002 => self.pulse_2.write_period_high(value),
+            0xB000 => self.sawtooth.write_rate(value),
+            0xB001 => self.sawtooth.write_period_low(value),
+            0xB002 => self.sawtooth.write_period_high(value),
+            _ => {}
+        }
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+
+        self.pulse_1.clock(self.shift);
+        self.pulse_2.clock(self.shift);
+        self.sawtooth.clock(self.shift);
+    }
+
+    pub fn output(&self, volumes: &ChannelVolumes) -> f32 {
+        let pulse_1 = self.pulse_1.output() as f32 * volumes.vrc6_pulse_1;
+        let pulse_2 = self.pulse_2.output() as f32 * volumes.vrc6_pulse_2;
+        let sawtooth = self.sawtooth.output() as f32 * volumes.vrc6_sawtooth;
+
+        (pulse_1 + pulse_2 + sawtooth) * STEP_SCALE
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        let pulse = |name, pulse: &Vrc6Pulse| ExpansionChannel {
+            name,
+            enabled: pulse.enabled,
+            period: pulse.period,
+            frequency: NTSC_CPU_FREQUENCY / (16.0 * (pulse.period as f64 + 1.0)),
+            output: pulse.output() as f32 / 15.0,
+        };
+
+        vec![
+            pulse("VRC6 Pulse 1", &self.pulse_1),
+            pulse("VRC6 Pulse 2", &self.pulse_2),
+            ExpansionChannel {
+                name: "VRC6 Sawtooth",
+                enabled: self.sawtooth.enabled,
+                period: self.sawtooth.period,
+                frequency: NTSC_CPU_FREQUENCY / (14.0 * (self.sawtooth.period as f64 + 1.0)),
+                output: self.sawtooth.output() as f32 / 31.0,
+            },
+        ]
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse_1.save_state(writer);
+        self.pulse_2.save_state(writer);
+        self.sawtooth.save_state(writer);
+        writer.write_bool(self.halt);
+        writer.write_u8(self.shift);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.pulse_1.load_state(reader)?;
+        self.pulse_2.load_state(reader)?;
+        self.sawtooth.load_state(reader)?;
+        self.halt = reader.read_bool()?;
+        self.shift = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_duty_cycle() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0x9000, 0x3A); // Volume 10, duty 3 (4/16)
+        audio.write(0x9001, 0x00);
+        audio.write(0x9002, 0x80); // Period 0, enabled
+
+        let outputs: Vec<u8> = (0..16)
+            .map(|_| {
+                audio.clock();
+                audio.pulse_1.output()
+            })
+            .collect();
+
+        assert_eq!(outputs.iter().filter(|output| **output == 10).count(), 4);
+        assert_eq!(outputs.iter().filter(|output| **output == 0).count(), 12);
+    }
+
+    #[test]
+    fn pulse_constant_mode_ignores_duty() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xA000, 0x8F);
+        audio.write(0xA002, 0x80);
+
+        for _ in 0..16 {
+            audio.clock();
+            assert_eq!(audio.pulse_2.output(), 15);
+        }
+    }
+
+    #[test]
+    fn sawtooth_accumulates_and_resets() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xB000, 0x20);
+        audio.write(0xB002, 0x80);
+
+        let outputs: Vec<u8> = (0..14)
+            .map(|_| {
+                audio.clock();
+                audio.sawtooth.output()
+            })
+            .collect();
+
+        assert_eq!(outputs, [0, 4, 4, 8, 8, 12, 12, 16, 16, 20, 20, 24, 24, 0]);
+    }
+
+    #[test]
+    fn halt_stops_oscillators() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xB000, 0x20);
+        audio.write(0xB002, 0x80);
+        audio.write(0x9003, 0x01);
+
+        for _ in 0..4 {
+            audio.clock();
+        }
+        assert_eq!(audio.sawtooth.output(), 0);
+    }
+
+    #[test]
+    fn channel_volumes_scale_output() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0x9000, 0x8F);
+        audio.write(0x9002, 0x80);
+
+        let mut volumes = ChannelVolumes::default();
+        assert!(audio.output(&volumes) > 0.0);
+
+        volumes.vrc6_pulse_1 = 0.0;
+        assert_eq!(audio.output(&volumes), 0.0);
+    }
+}
@@ -0,0 +1,588 @@
+use super::{ExpansionChannel, PULSE_FULL_VOLUME};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+use std::f32::consts::TAU;
+
+const CHANNELS: usize = 6;
+const CPU_CYCLES_PER_SAMPLE: u8 = 36; // The chip's 3.58MHz clock divided by 72, about 49.7kHz
+const SAMPLE_RATE: f64 = 3_579_545.0 / 72.0;
+
+const PHASE_BITS: u32 = 19;
+const MAX_ATTENUATION: f32 = 127.0; // Envelope attenuation is in 0.375dB steps, 127 is treated as silent
+const ATTENUATION_STEP_DB: f32 = 0.375;
+
+const CHANNEL_SCALE: f32 = PULSE_FULL_VOLUME;
+
+// Frequency multipliers for MULT values 0-15, doubled so that MULT 0 (x0.5) stays an integer
+const MULTIPLIERS_X2: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// Key scale level attenuation in dB at block 7 for the top 4 bits of the F-number, 6dB less for each lower block
+const KEY_SCALE_LEVELS: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0,
+];
+
+// Vibrato offsets to the F-number over its 8 steps, scaled by the top 3 bits of the F-number
+const VIBRATO_PATTERN: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+const VIBRATO_STEP_SAMPLES: u16 = 1024; // About 6.1Hz over the 8 steps
+
+const TREMOLO_DEPTH: f32 = 4.8 / ATTENUATION_STEP_DB;
+const TREMOLO_FREQUENCY: f32 = 3.7;
+
+/// The VRC7's built-in instruments 1-15, instrument 0 is the custom patch written to registers $00-$07
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+/// One operator's half of an instrument patch:
+///
+/// ```text
+/// $00/$01: tremolo (bit 7), vibrato (bit 6), sustained envelope (bit 5), key scale rate (bit 4), multiplier (bits 0-3)
+/// $02:     modulator key scale level (bits 6-7), modulator total level (bits 0-5)
+/// $03:     carrier key scale level (bits 6-7), rectified carrier (bit 4) and modulator (bit 3), feedback (bits 0-2)
+/// $04/$05: attack rate (bits 4-7), decay rate (bits 0-3)
+/// $06/$07: sustain level (bits 4-7), release rate (bits 0-3)
+/// ```
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    rectified: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+
+impl OperatorPatch {
+    fn decode(patch: &[u8; 8], carrier: bool) -> Self {
+        let op = carrier as usize;
+
+        Self {
+            tremolo: patch[op] & 0x80 != 0,
+            vibrato: patch[op] & 0x40 != 0,
+            sustained: patch[op] & 0x20 != 0,
+            key_scale_rate: patch[op] & 0x10 != 0,
+            multiplier: patch[op] & 0x0F,
+            key_scale_level: patch[2 + op] >> 6,
+            rectified: patch[3] & if carrier { 0x10 } else { 0x08 } != 0,
+            attack_rate: patch[4 + op] >> 4,
+            decay_rate: patch[4 + op] & 0x0F,
+            sustain_level: patch[6 + op] >> 4,
+            release_rate: patch[6 + op] & 0x0F,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    #[default]
+    Off,
+}
+
+impl EnvelopeState {
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => EnvelopeState::Attack,
+            1 => EnvelopeState::Decay,
+            2 => EnvelopeState::Sustain,
+            3 => EnvelopeState::Release,
+            _ => EnvelopeState::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Operator {
+    phase: u32,       // 19-bit phase accumulator
+    attenuation: f32, // Envelope level, 0 is full volume
+    state: EnvelopeState,
+    output: f32,
+    previous_output: f32, // Modulator feedback averages the last two outputs
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    /// Advances the envelope by one sample. `rate` turns a 4-bit rate into the 0-63 rate adjusted for key scaling.
+    fn clock_envelope(&mut self, patch: &OperatorPatch, release_rate: u8, rate: impl Fn(u8) -> u8) {
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = rate(patch.attack_rate);
+                if rate >= 60 {
+                    self.attenuation = 0.0;
+                } else {
+                    self.attenuation -= (self.attenuation + 1.0) * envelope_step(rate) / 8.0;
+                }
+
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.attenuation += envelope_step(rate(patch.decay_rate));
+                if self.attenuation >= patch.sustain_level as f32 * 8.0 {
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain if !patch.sustained => self.attenuation += envelope_step(rate(patch.release_rate)),
+            EnvelopeState::Release => self.attenuation += envelope_step(rate(release_rate)),
+            _ => {}
+        }
+
+        if self.attenuation >= MAX_ATTENUATION {
+            self.attenuation = MAX_ATTENUATION;
+            if self.state == EnvelopeState::Release {
+                self.state = EnvelopeState::Off;
+            }
+        }
+    }
+
+    /// Advances the phase and produces the next output, `modulation` is a phase offset in whole cycles
+    fn clock(&mut self, increment: u32, modulation: f32, rectified: bool, attenuation: f32) -> f32 {
+        self.phase = (self.phase + increment) & ((1 << PHASE_BITS) - 1);
+
+        let phase = self.phase as f32 / (1 << PHASE_BITS) as f32 + modulation;
+        let wave = (phase * TAU).sin();
+        let wave = if rectified && wave < 0.0 { 0.0 } else { wave };
+
+        let attenuation = if self.state == EnvelopeState::Off {
+            f32::INFINITY
+        } else {
+            self.attenuation + attenuation
+        };
+
+        self.previous_output = self.output;
+        self.output = wave * gain(attenuation);
+        self.output
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Channel {
+    f_number: u16, // 9 bits, $10-$15 and bit 0 of $20-$25
+    block: u8,     // Octave
+    key_on: bool,
+    sustain: bool, // Release slowly on key off
+    instrument: u8,
+    volume: u8, // Attenuation in 3dB steps
+
+    modulator: Operator,
+    carrier: Operator,
+}
+
+impl Channel {
+    fn write_control(&mut self, value: u8) {
+        self.f_number = (self.f_number & 0x00FF) | (((value & 0x01) as u16) << 8);
+        self.block = (value >> 1) & 0x07;
+        self.sustain = value & 0x20 != 0;
+
+        let key_on = value & 0x10 != 0;
+        if key_on && !self.key_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key_on && self.key_on {
+            self.modulator.key_off();
+            self.carrier.key_off();
+        }
+        self.key_on = key_on;
+    }
+
+    fn clock(&mut self, patch: &[u8; 8], tremolo: f32, vibrato_step: usize) -> f32 {
+        let modulator = OperatorPatch::decode(patch, false);
+        let carrier = OperatorPatch::decode(patch, true);
+        let (f_number, block, sustain) = (self.f_number, self.block, self.sustain);
+
+        let key_scale = |patch: &OperatorPatch| {
+            let level = KEY_SCALE_LEVELS[(f_number >> 5) as usize] - 6.0 * (7 - block) as f32;
+            let level = match patch.key_scale_level {
+                0 => 0.0,
+                shift => level.max(0.0) / (1 << (3 - shift)) as f32,
+            };
+            level / ATTENUATION_STEP_DB + if patch.tremolo { tremolo } else { 0.0 }
+        };
+
+        let increment = |patch: &OperatorPatch| {
+            let vibrato = if patch.vibrato {
+                VIBRATO_PATTERN[vibrato_step] * (f_number >> 6) as i32 / 2
+            } else {
+                0
+            };
+            let f_number = (f_number as i32 + vibrato).max(0) as u32;
+            ((f_number * MULTIPLIERS_X2[patch.multiplier as usize]) << block) >> 1
+        };
+
+        // Key off releases at 7 for percussive patches, or 5 with the channel's sustain on
+        let release_rate = |patch: &OperatorPatch| match (sustain, patch.sustained) {
+            (true, _) => 5,
+            (false, true) => patch.release_rate,
+            (false, false) => 7,
+        };
+
+        let rate = |patch: &OperatorPatch| {
+            let key_scale_rate = if patch.key_scale_rate {
+                (block << 1) | (f_number >> 8) as u8
+            } else {
+                block >> 1
+            };
+            move |rate: u8| if rate == 0 { 0 } else { (rate * 4 + key_scale_rate).min(63) }
+        };
+
+        let (modulator_release, carrier_release) = (release_rate(&modulator), release_rate(&carrier));
+        self.modulator.clock_envelope(&modulator, modulator_release, rate(&modulator));
+        self.carrier.clock_envelope(&carrier, carrier_release, rate(&carrier));
+
+        // Feedback is a phase offset of up to 2 cycles (4 pi) at level 7, halving with each level below
+        let feedback = match patch[3] & 0x07 {
+            0 => 0.0,
+            level => (self.modulator.output + self.modulator.previous_output) / 2.0 * (1 << level) as f32 / 64.0,
+        };
+
+        let total_level = (patch[2] & 0x3F) as f32 * 2.0;
+        let modulation = self
+            .modulator
+            .clock(increment(&modulator), feedback, modulator.rectified, total_level + key_scale(&modulator));
+
+        let volume = self.volume as f32 * 8.0;
+        self.carrier
+            .clock(increment(&carrier), modulation * 2.0, carrier.rectified, volume + key_scale(&carrier))
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.f_number);
+        writer.write_u8(self.block);
+        writer.write_bool(self.key_on);
+        writer.write_bool(self.sustain);
+        writer.write_u8(self.instrument);
+        writer.write_u8(self.volume);
+
+        for operator in [&self.modulator, &self.carrier] {
+            writer.write_u32(operator.phase);
+            writer.write_u32(operator.attenuation.to_bits());
+            writer.write_u8(operator.state.to_u8());
+            writer.write_u32(operator.output.to_bits());
+            writer.write_u32(operator.previous_output.to_bits());
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.f_number = reader.read_u16()? & 0x01FF;
+        self.block = reader.read_u8()? & 0x07;
+        self.key_on = reader.read_bool()?;
+        self.sustain = reader.read_bool()?;
+        self.instrument = reader.read_u8()? & 0x0F;
+        self.volume = reader.read_u8()? & 0x0F;
+
+        for operator in [&mut self.modulator, &mut self.carrier] {
+            operator.phase = reader.read_u32()? & ((1 << PHASE_BITS) - 1);
+            operator.attenuation = f32::from_bits(reader.read_u32()?);
+            operator.state = EnvelopeState::from_u8(reader.read_u8()?);
+            operator.output = f32::from_bits(reader.read_u32()?);
+            operator.previous_output = f32::from_bits(reader.read_u32()?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Envelope change per sample for a 0-63 rate, doubling every 4 rates
+fn envelope_step(rate: u8) -> f32 {
+    if rate < 4 {
+        0.0
+    } else {
+        (4 + (rate & 0x03) as u32) as f32 * (1u32 << (rate >> 2)) as f32 / 65536.0
+    }
+}
+
+/// Linear gain for an attenuation in 0.375dB steps
+fn gain(attenuation: f32) -> f32 {
+    if attenuation >= MAX_ATTENUATION * 2.0 {
+        0.0
+    } else {
+        10f32.powf(-attenuation * ATTENUATION_STEP_DB / 20.0)
+    }
+}
+
+/// The VRC7's FM synthesiser, a cut-down YM2413 (OPLL) with 6 two-operator channels and its own set of built-in
+/// instruments. Registers are selected through $9010 and written through $9030:
+///
+/// ```text
+/// $00-$07 custom instrument       $10-$15 F-number low 8 bits
+/// $20-$25 sustain (bit 5), key on (bit 4), block (bits 1-3), F-number bit 8
+/// $30-$35 instrument (bits 4-7), volume (bits 0-3)
+/// ```
+///
+/// The chip produces a sample every 36 CPU cycles, which is interpolated up to the CPU rate for the `AudioProcessor`
+/// to downsample along with the rest of the mix.
+pub struct Vrc7Audio {
+    custom_patch: [u8; 8],
+    channels: [Channel; CHANNELS],
+    register: u8,
+    reset: bool, // $E000 bit 6 holds the chip in reset, silencing it
+
+    cycle_counter: u8,
+    previous_sample: f32,
+    sample: f32,
+
+    tremolo_phase: f32,
+    vibrato_counter: u16,
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Self {
+        Self {
+            custom_patch: [0; 8],
+            channels: [Channel::default(); CHANNELS],
+            register: 0,
+            reset: false,
+
+            cycle_counter: 0,
+            previous_sample: 0.0,
+            sample: 0.0,
+
+            tremolo_phase: 0.0,
+            vibrato_counter: 0,
+        }
+    }
+
+    /// $9010
+    pub fn select_register(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// $9030, writes to the register selected through $9010
+    pub fn write_data(&mut self, value: u8) {
+        if self.reset {
+            return;
+        }
+
+        let channel = (self.register & 0x0F) as usize;
+        match self.register {
+            0x00..=0x07 => self.custom_patch[self.register as usize] = value,
+            0x10..=0x15 => self.channels[channel].f_number = (self.channels[channel].f_number & 0x0100) | value as u16,
+            0x20..=0x25 => self.channels[channel].write_control(value),
+            0x30..=0x35 => {
+                self.channels[channel].instrument = value >> 4;
+                self.channels[channel].volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_reset(&mut self, reset: bool) {
+        if reset && !self.reset {
+            self.channels = [Channel::default(); CHANNELS];
+            self.custom_patch = [0; 8];
+            self.previous_sample = 0.0;
+            self.sample = 0.0;
+        }
+
+        self.reset = reset;
+    }
+
+    /// Called once per CPU cycle
+    pub fn clock(&mut self) {
+        if self.reset {
+            return;
+        }
+
+        self.cycle_counter += 1;
+        if self.cycle_counter == CPU_CYCLES_PER_SAMPLE {
+            self.cycle_counter = 0;
+            self.previous_sample = self.sample;
+            self.sample = self.generate_sample();
+        }
+    }
+
+    fn generate_sample(&mut self) -> f32 {
+        self.tremolo_phase = (self.tremolo_phase + TREMOLO_FREQUENCY / SAMPLE_RATE as f32).fract();
+        self.vibrato_counter = (self.vibrato_counter + 1) % (VIBRATO_STEP_SAMPLES * 8);
+
+        // Tremolo is a triangle between 0 and its full depth
+        let tremolo = (1.0 - (self.tremolo_phase * 2.0 - 1.0).abs()) * TREMOLO_DEPTH;
+        let vibrato_step = (self.vibrato_counter / VIBRATO_STEP_SAMPLES) as usize;
+
+        let custom_patch = self.custom_patch;
+        self.channels
+            .iter_mut()
+            .map(|channel| {
+                let patch = if channel.instrument == 0 {
+                    &custom_patch
+                } else {
+                    &PATCHES[channel.instrument as usize - 1]
+                };
+                channel.clock(patch, tremolo, vibrato_step)
+            })
+            .sum()
+    }
+
+    pub fn output(&self) -> f32 {
+        let position = self.cycle_counter as f32 / CPU_CYCLES_PER_SAMPLE as f32;
+        (self.previous_sample + (self.sample - self.previous_sample) * position) * CHANNEL_SCALE
+    }
+
+    pub fn channels(&self) -> Vec<ExpansionChannel> {
+        const NAMES: [&str; CHANNELS] = ["VRC7 FM 1", "VRC7 FM 2", "VRC7 FM 3", "VRC7 FM 4", "VRC7 FM 5", "VRC7 FM 6"];
+
+        self.channels
+            .iter()
+            .zip(NAMES)
+            .map(|(channel, name)| ExpansionChannel {
+                name,
+                enabled: channel.key_on,
+                period: channel.f_number,
+                frequency: SAMPLE_RATE * channel.f_number as f64 * (1u32 << channel.block) as f64 / (1u32 << PHASE_BITS) as f64,
+                output: channel.carrier.output.abs(),
+            })
+            .collect()
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.custom_patch);
+        for channel in &self.channels {
+            channel.save_state(writer);
+        }
+        writer.write_u8(self.register);
+        writer.write_bool(self.reset);
+
+        writer.write_u8(self.cycle_counter);
+        writer.write_u32(self.previous_sample.to_bits());
+        writer.write_u32(self.sample.to_bits());
+
+        writer.write_u32(self.tremolo_phase.to_bits());
+        writer.write_u16(self.vibrato_counter);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.custom_patch)?;
+        for channel in &mut self.channels {
+            channel.load_state(reader)?;
+        }
+        self.register = reader.read_u8()?;
+        self.reset = reader.read_bool()?;
+
+        self.cycle_counter = reader.read_u8()? % CPU_CYCLES_PER_SAMPLE;
+        self.previous_sample = f32::from_bits(reader.read_u32()?);
+        self.sample = f32::from_bits(reader.read_u32()?);
+
+        self.tremolo_phase = f32::from_bits(reader.read_u32()?).fract();
+        self.vibrato_counter = reader.read_u16()? % (VIBRATO_STEP_SAMPLES * 8);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(audio: &mut Vrc7Audio, register: u8, value: u8) {
+        audio.select_register(register);
+        audio.write_data(value);
+    }
+
+    fn run_samples(audio: &mut Vrc7Audio, samples: usize) -> Vec<f32> {
+        (0..samples * CPU_CYCLES_PER_SAMPLE as usize)
+            .map(|_| {
+                audio.clock();
+                audio.output()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn silent_until_key_on() {
+        let mut audio = Vrc7Audio::new();
+        write(&mut audio, 0x10, 0xAC);
+        write(&mut audio, 0x30, 0x30); // Wurly, full volume
+
+        assert!(run_samples(&mut audio, 100).iter().all(|sample| *sample == 0.0));
+
+        write(&mut audio, 0x20, 0x18); // Key on, block 4
+        assert!(run_samples(&mut audio, 1000).iter().any(|sample| sample.abs() > 0.01));
+    }
+
+    #[test]
+    fn plays_at_the_programmed_pitch() {
+        // A plain sine on the carrier: no modulation, instant attack and no decay
+        let mut audio = Vrc7Audio::new();
+        for (register, value) in [0x21, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x0F, 0x0F].into_iter().enumerate() {
+            write(&mut audio, register as u8, value);
+        }
+
+        // F-number 288 in block 4 is about 437Hz
+        write(&mut audio, 0x10, 0x20);
+        write(&mut audio, 0x30, 0x00);
+        write(&mut audio, 0x20, 0x19);
+
+        let samples = run_samples(&mut audio, SAMPLE_RATE as usize);
+        let crossings = samples.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        assert!((430..=445).contains(&crossings), "{} cycles", crossings);
+    }
+
+    #[test]
+    fn key_off_releases() {
+        let mut audio = Vrc7Audio::new();
+        write(&mut audio, 0x10, 0xAC);
+        write(&mut audio, 0x30, 0x10); // Buzzy bell
+        write(&mut audio, 0x20, 0x18);
+        run_samples(&mut audio, 1000);
+
+        // Both operators release, so the modulator doesn't keep its level into the next note
+        write(&mut audio, 0x20, 0x08);
+        assert!(audio.channels[0].modulator.state == EnvelopeState::Release);
+        assert!(audio.channels[0].carrier.state == EnvelopeState::Release);
+
+        run_samples(&mut audio, SAMPLE_RATE as usize);
+        assert!(audio.channels[0].modulator.state == EnvelopeState::Off);
+        assert!(audio.channels[0].carrier.state == EnvelopeState::Off);
+        assert_eq!(audio.output(), 0.0);
+    }
+
+    #[test]
+    fn reset_silences_and_ignores_writes() {
+        let mut audio = Vrc7Audio::new();
+        write(&mut audio, 0x10, 0xAC);
+        write(&mut audio, 0x20, 0x18);
+        run_samples(&mut audio, 100);
+
+        audio.set_reset(true);
+        write(&mut audio, 0x20, 0x18);
+        assert!(!audio.channels[0].key_on);
+        assert_eq!(audio.output(), 0.0);
+    }
+}
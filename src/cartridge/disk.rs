@@ -0,0 +1,239 @@
+use super::CartridgeError;
+use anyhow::{Result, bail, ensure};
+
+pub const SIDE_SIZE: usize = 65500;
+const FDS_TAG: [u8; 4] = [0x46, 0x44, 0x53, 0x1A]; // FDS^Z
+const FWNES_HEADER_SIZE: usize = 16;
+const DISK_INFO_TAG: &[u8] = b"\x01*NINTENDO-HVC*";
+
+// Gaps of zero bits the drive has to pass before the first block and between blocks, rounded to whole bytes
+const LEADING_GAP_SIZE: usize = 28300 / 8;
+const BLOCK_GAP_SIZE: usize = 976 / 8;
+
+// .fds images store the blocks back to back, so a side as the drive sees it is longer once the gaps, start marks and CRCs
+// are added. Every side is padded out to at least this size to leave room for files that games append.
+const RAW_SIDE_SIZE: usize = LEADING_GAP_SIZE + SIDE_SIZE + 8192;
+
+const DIFF_MAGIC: [u8; 4] = [0x46, 0x44, 0x49, 0x46]; // FDIF
+const DIFF_MERGE_DISTANCE: usize = 16; // Runs of changed bytes closer than this are written as one record
+
+/// Splits a .fds image into its 65500-byte sides, skipping the fwNES header if there is one
+pub fn parse_disk_image(data: &[u8]) -> Result<Vec<&[u8]>, CartridgeError> {
+    let (sides, side_count) = if data.starts_with(&FDS_TAG) {
+        let Some(header) = data.first_chunk::<FWNES_HEADER_SIZE>() else {
+            return Err(CartridgeError::TruncatedHeader { length: data.len() });
+        };
+        (&data[FWNES_HEADER_SIZE..], header[4] as usize)
+    } else {
+        (data, data.len().div_ceil(SIDE_SIZE))
+    };
+
+    let expected = side_count.max(1) * SIDE_SIZE;
+    if sides.len() < expected {
+        return Err(CartridgeError::TruncatedDiskImage {
+            expected,
+            available: sides.len(),
+        });
+    }
+
+    let sides: Vec<&[u8]> = sides[..expected].chunks_exact(SIDE_SIZE).collect();
+    if let Some(side) = sides.iter().position(|side| !side.starts_with(DISK_INFO_TAG)) {
+        return Err(CartridgeError::InvalidDiskSide { side });
+    }
+
+    Ok(sides)
+}
+
+/// Lays a side out as the drive reads it: each block is preceded by a gap and a start mark of $80 and followed by its CRC
+pub fn build_raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP_SIZE];
+    let mut push_block = |block: &[u8]| {
+        let mut crc = update_crc(0, 0x80);
+        for &byte in block {
+            crc = update_crc(crc, byte);
+        }
+        crc = update_crc(update_crc(crc, 0), 0);
+
+        raw.push(0x80);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&crc.to_le_bytes());
+        raw.resize(raw.len() + BLOCK_GAP_SIZE, 0);
+    };
+
+    // The disk info block (1) and file amount block (2), followed by a file header block (3) and file data block (4) for
+    // each file. Games often have more files than the file amount says, so this keeps going while file headers are found.
+    push_block(&side[..56]);
+    push_block(&side[56..58]);
+
+    let mut position = 58;
+    while position + 16 <= side.len() && side[position] == 3 {
+        let header = &side[position..position + 16];
+        let file_size = u16::from_le_bytes([header[13], header[14]]) as usize;
+        push_block(header);
+        position += 16;
+
+        if position >= side.len() || side[position] != 4 {
+            break;
+        }
+        let end = (position + 1 + file_size).min(side.len());
+        push_block(&side[position..end]);
+        position = end;
+    }
+
+    raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+    raw
+}
+
+/// Shifts a byte into the CRC the drive calculates over each block, least significant bit first. Running the block's
+/// own CRC through afterwards leaves 0 when it matches.
+pub fn update_crc(mut crc: u16, value: u8) -> u16 {
+    for bit in 0..8 {
+        let carry = crc & 0x01 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if value & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+
+    crc
+}
+
+/// Records the bytes that differ between the sides as loaded and as they are now. The diff is a 4-byte tag followed by
+/// records of side (u8), offset (u32), length (u32) and the new bytes.
+pub fn encode_diff(original: &[Vec<u8>], current: &[Vec<u8>]) -> Vec<u8> {
+    let mut diff = DIFF_MAGIC.to_vec();
+
+    for (side, (original, current)) in original.iter().zip(current).enumerate() {
+        let mut offset = 0;
+        while offset < current.len() {
+            if original[offset] == current[offset] {
+                offset += 1;
+                continue;
+            }
+
+            let start = offset;
+            let mut end = offset + 1;
+            let mut scan = end;
+            while scan < current.len() && scan - end < DIFF_MERGE_DISTANCE {
+                if original[scan] != current[scan] {
+                    end = scan + 1;
+                }
+                scan += 1;
+            }
+
+            diff.push(side as u8);
+            diff.extend_from_slice(&(start as u32).to_le_bytes());
+            diff.extend_from_slice(&((end - start) as u32).to_le_bytes());
+            diff.extend_from_slice(&current[start..end]);
+            offset = end;
+        }
+    }
+
+    diff
+}
+
+/// Applies a diff written by `encode_diff` on top of freshly loaded sides
+pub fn apply_diff(sides: &mut [Vec<u8>], diff: &[u8]) -> Result<()> {
+    ensure!(diff.starts_with(&DIFF_MAGIC), "Not a disk diff file");
+
+    let mut remaining = &diff[DIFF_MAGIC.len()..];
+    while !remaining.is_empty() {
+        let Some((&[side, o0, o1, o2, o3, l0, l1, l2, l3], rest)) = remaining.split_first_chunk::<9>() else {
+            bail!("Disk diff is truncated");
+        };
+
+        let side = side as usize;
+        let offset = u32::from_le_bytes([o0, o1, o2, o3]) as usize;
+        let length = u32::from_le_bytes([l0, l1, l2, l3]) as usize;
+
+        ensure!(rest.len() >= length, "Disk diff is truncated");
+        let Some(target) = sides.get_mut(side).and_then(|side| side.get_mut(offset..offset + length)) else {
+            bail!("Disk diff doesn't match the disk image (side {}, offset {}, length {})", side, offset, length);
+        };
+
+        target.copy_from_slice(&rest[..length]);
+        remaining = &rest[length..];
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A side holding one 4-byte file
+    fn test_side() -> Vec<u8> {
+        let mut side = vec![0; SIDE_SIZE];
+        side[..15].copy_from_slice(DISK_INFO_TAG);
+        side[56..58].copy_from_slice(&[0x02, 0x01]);
+        side[58] = 0x03;
+        side[58 + 13] = 0x04;
+        side[74..79].copy_from_slice(&[0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+        side
+    }
+
+    #[test]
+    fn parses_headered_and_headerless_images() {
+        let side = test_side();
+        let headerless = [side.clone(), side.clone()].concat();
+        assert_eq!(parse_disk_image(&headerless).unwrap().len(), 2);
+
+        let mut headered = vec![0x46, 0x44, 0x53, 0x1A, 0x01];
+        headered.resize(FWNES_HEADER_SIZE, 0);
+        headered.extend_from_slice(&side);
+        assert_eq!(parse_disk_image(&headered).unwrap().len(), 1);
+
+        assert!(matches!(
+            parse_disk_image(&side[..1000]),
+            Err(CartridgeError::TruncatedDiskImage {
+                expected: SIDE_SIZE,
+                available: 1000
+            })
+        ));
+        assert!(matches!(
+            parse_disk_image(&vec![0; SIDE_SIZE]),
+            Err(CartridgeError::InvalidDiskSide { side: 0 })
+        ));
+    }
+
+    #[test]
+    fn raw_side_has_gaps_marks_and_crcs() {
+        let raw = build_raw_side(&test_side());
+        assert_eq!(raw.len(), RAW_SIDE_SIZE);
+        assert!(raw[..LEADING_GAP_SIZE].iter().all(|byte| *byte == 0));
+        assert_eq!(raw[LEADING_GAP_SIZE], 0x80);
+
+        // The 4 blocks with their CRCs each check out to 0
+        let mut position = LEADING_GAP_SIZE;
+        for length in [56, 2, 16, 5] {
+            let block = &raw[position..position + 1 + length + 2];
+            assert_eq!(block[0], 0x80);
+            assert_eq!(block.iter().fold(0, |crc, byte| update_crc(crc, *byte)), 0);
+            position += block.len() + BLOCK_GAP_SIZE;
+        }
+
+        assert_eq!(&raw[position - BLOCK_GAP_SIZE - 6..position - BLOCK_GAP_SIZE - 2], [0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+
+    #[test]
+    fn diff_round_trip() {
+        let original = vec![build_raw_side(&test_side()), build_raw_side(&test_side())];
+        let mut current = original.clone();
+        current[1][100] = 0x11;
+        current[1][105] = 0x22;
+        current[1][5000] = 0x33;
+
+        let diff = encode_diff(&original, &current);
+        assert_eq!(diff.len(), 4 + (9 + 6) + (9 + 1));
+
+        let mut restored = original.clone();
+        apply_diff(&mut restored, &diff).unwrap();
+        assert_eq!(restored, current);
+
+        assert!(apply_diff(&mut restored, &diff[..diff.len() - 1]).is_err());
+    }
+}
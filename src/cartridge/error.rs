@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// Reasons a ROM image can fail to load
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(std::io::Error),
+    TruncatedHeader { length: usize },
+    BadMagic([u8; 4]),
+    TruncatedTrainer,
+    TruncatedPrgRom { expected: usize, available: usize },
+    TruncatedChrRom { expected: usize, available: usize },
+    UnsupportedMapper { mapper: u16, submapper: u8 },
+    TruncatedDiskImage { expected: usize, available: usize },
+    InvalidDiskSide { side: usize },
+    InvalidFdsBios { length: usize },
+    InvalidNsf(String),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "Failed to read ROM file: {}", error),
+            CartridgeError::TruncatedHeader { length } => write!(f, "ROM data is too small for an iNES header ({} bytes)", length),
+            CartridgeError::BadMagic(tag) => write!(f, "Invalid iNES header, expected \"NES\\x1A\" but found {:02X?}", tag),
+            CartridgeError::TruncatedTrainer => write!(f, "ROM data is too small for the trainer indicated by the header"),
+            CartridgeError::TruncatedPrgRom { expected, available } => {
+                write!(f, "ROM data is too small for expected PRG-ROM ({} bytes, {} available)", expected, available)
+            }
+            CartridgeError::TruncatedChrRom { expected, available } => {
+                write!(f, "ROM data is too small for expected CHR-ROM ({} bytes, {} available)", expected, available)
+            }
+            CartridgeError::UnsupportedMapper { mapper, submapper } => write!(f, "Unsupported mapper: {} (submapper {})", mapper, submapper),
+            CartridgeError::TruncatedDiskImage { expected, available } => {
+                write!(f, "Disk image is too small for its disk sides ({} bytes, {} available)", expected, available)
+            }
+            CartridgeError::InvalidDiskSide { side } => write!(f, "Disk side {} doesn't start with a disk info block", side),
+            CartridgeError::InvalidFdsBios { length } => write!(f, "FDS BIOS must be 8192 bytes, found {}", length),
+            CartridgeError::InvalidNsf(reason) => write!(f, "Invalid NSF file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(error: std::io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
@@ -0,0 +1,271 @@
+use super::{CHR_ROM_BANK_SIZE, CartridgeError, Mirroring, PRG_ROM_BANK_SIZE};
+
+pub const HEADER_SIZE: usize = 16;
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // NES^Z
+
+const DEFAULT_PRG_RAM_SIZE: usize = 8192; // iNES 1.0 boards are assumed to have 8KB of PRG-RAM
+const DEFAULT_CHR_RAM_SIZE: usize = 8192;
+
+pub const FDS_BIOS_SIZE: usize = 8192;
+const FDS_PRG_RAM_SIZE: usize = 32768;
+const FDS_CHR_RAM_SIZE: usize = 8192;
+const NSF_PRG_RAM_SIZE: usize = 8192;
+const NSF_CHR_RAM_SIZE: usize = 8192;
+
+/// The 16-byte iNES or NES 2.0 header, only constructed once the "NES" + MS-DOS EOF tag has been checked
+pub struct INesHeader {
+    pub prg_rom_size: u8, // Size of PRG-ROM in 16KB units (LSB for NES 2.0)
+    pub chr_rom_size: u8, // Size of CHR-ROM in 8KB units (LSB for NES 2.0)
+    pub flags_6: u8,      // Lower nybble of mapper, mirroring, battery and trainer
+    pub flags_7: u8,      // Upper nybble of mapper, console type and NES 2.0 identifier
+    pub flags_8: u8,      // iNES: PRG-RAM size, NES 2.0: mapper MSB and submapper
+    pub flags_9: u8,      // iNES: TV system, NES 2.0: PRG-ROM and CHR-ROM size MSB
+    pub flags_10: u8,     // NES 2.0: PRG-RAM and PRG-NVRAM shift counts
+    pub flags_11: u8,     // NES 2.0: CHR-RAM and CHR-NVRAM shift counts
+    pub flags_12: u8,     // NES 2.0: CPU/PPU timing
+    pub flags_13: u8,     // NES 2.0: Vs. System type or extended console type
+    pub flags_14: u8,     // NES 2.0: miscellaneous ROMs
+    pub flags_15: u8,     // NES 2.0: default expansion device
+}
+
+impl INesHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        let Some(bytes) = data.first_chunk::<HEADER_SIZE>() else {
+            return Err(CartridgeError::TruncatedHeader { length: data.len() });
+        };
+
+        let tag = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if tag != NES_TAG {
+            return Err(CartridgeError::BadMagic(tag));
+        }
+
+        Ok(Self {
+            prg_rom_size: bytes[4],
+            chr_rom_size: bytes[5],
+            flags_6: bytes[6],
+            flags_7: bytes[7],
+            flags_8: bytes[8],
+            flags_9: bytes[9],
+            flags_10: bytes[10],
+            flags_11: bytes[11],
+            flags_12: bytes[12],
+            flags_13: bytes[13],
+            flags_14: bytes[14],
+            flags_15: bytes[15],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+    Fds, // Famicom Disk System image, with or without a fwNES header
+    Nsf, // NSF or NSFe music file, played on a board built for it
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingRegion {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    Extended(u8), // NES 2.0 extended console type from byte 13
+}
+
+/// Everything the header tells us about the cartridge board. RAM sizes are in bytes, with 0 meaning not present.
+#[derive(Debug, Clone)]
+pub struct CartridgeInfo {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+
+    pub timing: TimingRegion,
+    pub console_type: ConsoleType,
+}
+
+impl CartridgeInfo {
+    pub fn from_header(header: &INesHeader) -> Self {
+        let flags_6 = header.flags_6;
+        let flags_7 = header.flags_7;
+
+        let mirroring = if flags_6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags_6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let console_type = match flags_7 & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(header.flags_13 & 0x0F),
+        };
+
+        let has_battery = flags_6 & 0x02 != 0;
+        let has_trainer = flags_6 & 0x04 != 0;
+
+        if flags_7 & 0x0C == 0x08 {
+            let chr_rom_size = rom_size(header.chr_rom_size, header.flags_9 >> 4, CHR_ROM_BANK_SIZE);
+
+            Self {
+                format: HeaderFormat::Nes2,
+                mapper: ((header.flags_8 as u16 & 0x0F) << 8) | (flags_7 & 0xF0) as u16 | (flags_6 >> 4) as u16,
+                submapper: header.flags_8 >> 4,
+                mirroring,
+                has_battery,
+                has_trainer,
+
+                prg_rom_size: rom_size(header.prg_rom_size, header.flags_9 & 0x0F, PRG_ROM_BANK_SIZE),
+                chr_rom_size,
+                prg_ram_size: ram_size(header.flags_10 & 0x0F),
+                prg_nvram_size: ram_size(header.flags_10 >> 4),
+                chr_ram_size: ram_size(header.flags_11 & 0x0F),
+                chr_nvram_size: ram_size(header.flags_11 >> 4),
+
+                timing: match header.flags_12 & 0x03 {
+                    0 => TimingRegion::Ntsc,
+                    1 => TimingRegion::Pal,
+                    2 => TimingRegion::MultiRegion,
+                    _ => TimingRegion::Dendy,
+                },
+                console_type,
+            }
+        } else {
+            // Headers written by old tools can have junk such as "DiskDude!" in bytes 7-15, in which case the upper
+            // nybble of the mapper number can't be trusted
+            let junk = header.flags_12 != 0 || header.flags_13 != 0 || header.flags_14 != 0 || header.flags_15 != 0;
+            let mapper_high = if junk { 0 } else { flags_7 & 0xF0 };
+
+            let prg_ram_size = if header.flags_8 == 0 {
+                DEFAULT_PRG_RAM_SIZE
+            } else {
+                header.flags_8 as usize * DEFAULT_PRG_RAM_SIZE
+            };
+            let chr_rom_size = header.chr_rom_size as usize * CHR_ROM_BANK_SIZE;
+
+            Self {
+                format: HeaderFormat::INes,
+                mapper: (mapper_high | (flags_6 >> 4)) as u16,
+                submapper: 0,
+                mirroring,
+                has_battery,
+                has_trainer,
+
+                prg_rom_size: header.prg_rom_size as usize * PRG_ROM_BANK_SIZE,
+                chr_rom_size,
+                prg_ram_size: if has_battery { 0 } else { prg_ram_size },
+                prg_nvram_size: if has_battery { prg_ram_size } else { 0 },
+                chr_ram_size: if chr_rom_size == 0 { DEFAULT_CHR_RAM_SIZE } else { 0 },
+                chr_nvram_size: 0,
+
+                timing: if header.flags_9 & 0x01 != 0 { TimingRegion::Pal } else { TimingRegion::Ntsc },
+                console_type,
+            }
+        }
+    }
+
+    /// The FDS RAM adapter, with the BIOS in place of PRG-ROM. Disk images carry none of this, the adapter is always the same.
+    pub fn fds() -> Self {
+        Self {
+            format: HeaderFormat::Fds,
+            mapper: 20,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            has_battery: false,
+            has_trainer: false,
+
+            prg_rom_size: FDS_BIOS_SIZE,
+            chr_rom_size: 0,
+            prg_ram_size: FDS_PRG_RAM_SIZE,
+            prg_nvram_size: 0,
+            chr_ram_size: FDS_CHR_RAM_SIZE,
+            chr_nvram_size: 0,
+
+            timing: TimingRegion::Ntsc,
+            console_type: ConsoleType::Nes,
+        }
+    }
+
+    /// The board NSF tunes are played on. There's no mapper number for it, the cartridge is built directly.
+    pub fn nsf(prg_rom_size: usize) -> Self {
+        Self {
+            format: HeaderFormat::Nsf,
+            mapper: 0,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            has_battery: false,
+            has_trainer: false,
+
+            prg_rom_size,
+            chr_rom_size: 0,
+            prg_ram_size: NSF_PRG_RAM_SIZE,
+            prg_nvram_size: 0,
+            chr_ram_size: NSF_CHR_RAM_SIZE,
+            chr_nvram_size: 0,
+
+            timing: TimingRegion::Ntsc,
+            console_type: ConsoleType::Nes,
+        }
+    }
+
+    /// Total PRG-RAM on the board, volatile and battery-backed
+    pub fn total_prg_ram_size(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+
+    /// Total CHR-RAM on the board, volatile and battery-backed
+    pub fn total_chr_ram_size(&self) -> usize {
+        self.chr_ram_size + self.chr_nvram_size
+    }
+
+    /// Whether a discrete-logic board (UxROM, CNROM) has bus conflicts. NES 2.0 submapper 1 marks a conflict-free board
+    /// and 2 one with conflicts; iNES headers can't say, so conflicts are assumed as on most original boards.
+    pub fn has_bus_conflicts(&self) -> bool {
+        self.submapper != 1
+    }
+
+    /// Size of whichever CHR memory the board uses, ROM if present otherwise RAM
+    pub fn chr_size(&self) -> usize {
+        if self.chr_rom_size > 0 {
+            self.chr_rom_size
+        } else {
+            self.total_chr_ram_size()
+        }
+    }
+}
+
+/// NES 2.0 ROM sizes, an MSB nybble of $F switches the LSB to an exponent-multiplier form of 2^E * (MM * 2 + 1)
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        2usize.saturating_pow(exponent).saturating_mul(multiplier)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+/// NES 2.0 RAM sizes are given as a shift count of 64 bytes, 0 meaning none
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
@@ -1,18 +1,20 @@
-use super::{MappedRead, MappedWrite, Mapper, Mirroring};
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
 use crate::cartridge::{CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
 
 pub struct Mapper000 {
-    prg_banks: u8,
-    chr_banks: u8,
+    prg_banks: usize,
+    chr_banks: usize,
     mirroring: Mirroring,
 }
 
 impl Mapper000 {
-    pub fn new(prg_rom_size: usize, chr_rom_size: usize, mirroring: Mirroring) -> Self {
+    pub fn new(info: &CartridgeInfo) -> Self {
         Self {
-            prg_banks: (prg_rom_size / PRG_ROM_BANK_SIZE) as u8,
-            chr_banks: (chr_rom_size / CHR_ROM_BANK_SIZE) as u8,
-            mirroring
+            prg_banks: info.prg_rom_size / PRG_ROM_BANK_SIZE,
+            chr_banks: info.chr_rom_size / CHR_ROM_BANK_SIZE,
+            mirroring: info.mirroring,
         }
     }
 }
@@ -44,4 +46,10 @@ impl Mapper for Mapper000 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,329 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::cartridge::PRG_ROM_BANK_SIZE;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const CHR_BANK_SIZE: usize = 4096; // 4KB
+
+pub struct Mapper001 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+
+    shift_register: u8, // 5-bit serial load register, a set bit 0x10 marks the final write
+    control: u8,        // $8000-$9FFF: mirroring, PRG mode and CHR mode
+    chr_bank_0: u8,     // $A000-$BFFF
+    chr_bank_1: u8,     // $C000-$DFFF
+    prg_bank: u8,       // $E000-$FFFF: PRG bank and PRG-RAM disable
+
+    cpu_cycle: u64,
+    last_write_cycle: Option<u64>, // CPU cycle of the last serial write the MMC1 accepted
+}
+
+impl Mapper001 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_ROM_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+
+            shift_register: 0x10,
+            control: 0x0C, // PRG mode 3 (fix last bank at $C000) on power-up
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+
+            cpu_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            0xE000..=0xFFFF => self.prg_bank = value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    /// SUROM/SXROM boards use bit 4 of the CHR bank register as PRG-ROM A18 to reach 512KB
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_banks > 16 { (self.chr_bank_0 & 0x10) as usize } else { 0 }
+    }
+
+    fn prg_bank_for(&self, address: u16) -> usize {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let last_bank = self.prg_banks.saturating_sub(1).min(0x0F);
+
+        let bank = match ((self.control >> 2) & 0x03, address) {
+            (0 | 1, 0x8000..=0xBFFF) => bank & !1, // 32KB mode, lower half
+            (0 | 1, _) => bank | 1,                // 32KB mode, upper half
+            (2, 0x8000..=0xBFFF) => 0,             // First bank fixed at $8000
+            (2, _) => bank,                        // Switchable bank at $C000
+            (3, 0x8000..=0xBFFF) => bank,          // Switchable bank at $8000
+            (3, _) => last_bank,                   // Last bank fixed at $C000
+            _ => unreachable!(),
+        };
+
+        (self.prg_outer_bank() | bank) % self.prg_banks.max(1)
+    }
+
+    fn chr_bank_for(&self, address: u16) -> usize {
+        let bank = if self.control & 0x10 == 0 {
+            // 8KB mode, ignore the low bit of CHR bank 0
+            (self.chr_bank_0 & !1) as usize | (address as usize / CHR_BANK_SIZE)
+        } else if address < 0x1000 {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+
+        bank % self.chr_banks.max(1)
+    }
+}
+
+impl Mapper for Mapper001 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank_for(address);
+                MappedRead::PrgRom(bank * PRG_ROM_BANK_SIZE + (address & 0x3FFF) as usize)
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => MappedWrite::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => {
+                // The MMC1 ignores a write on the cycle after another, like the real write after a read-modify-write
+                // instruction's dummy write. Writes within one instruction all land before the cartridge is clocked.
+                if matches!(self.last_write_cycle, Some(last) if self.cpu_cycle - last < 2) {
+                    return MappedWrite::None;
+                }
+                self.last_write_cycle = Some(self.cpu_cycle);
+
+                if value & 0x80 != 0 {
+                    // Reset the shift register and lock PRG mode 3
+                    self.shift_register = 0x10;
+                    self.control |= 0x0C;
+                } else {
+                    let complete = self.shift_register & 0x01 != 0;
+                    self.shift_register = (self.shift_register >> 1) | ((value & 0x01) << 4);
+
+                    if complete {
+                        self.write_register(address, self.shift_register);
+                        self.shift_register = 0x10;
+                    }
+                }
+
+                MappedWrite::None
+            }
+            _ => MappedWrite::None,
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        self.chr_bank_for(address) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => unreachable!(),
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.cpu_cycle += 1;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.shift_register);
+        writer.write_u8(self.control);
+        writer.write_u8(self.chr_bank_0);
+        writer.write_u8(self.chr_bank_1);
+        writer.write_u8(self.prg_bank);
+        writer.write_u64(self.cpu_cycle);
+        writer.write_bool(self.last_write_cycle.is_some());
+        writer.write_u64(self.last_write_cycle.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.shift_register = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.chr_bank_0 = reader.read_u8()?;
+        self.chr_bank_1 = reader.read_u8()?;
+        self.prg_bank = reader.read_u8()?;
+        self.cpu_cycle = reader.read_u64()?;
+        let has_written = reader.read_bool()?;
+        let last_write_cycle = reader.read_u64()?;
+        self.last_write_cycle = has_written.then_some(last_write_cycle);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    /// Writes as a store instruction would, leaving the cycles of the next instruction's fetch after it
+    fn write(mapper: &mut Mapper001, address: u16, value: u8) {
+        mapper.cpu_write(address, value);
+        mapper.clock_cpu();
+        mapper.clock_cpu();
+    }
+
+    /// Loads a register through the shift register, one bit per write starting with the lowest
+    fn write_serial(mapper: &mut Mapper001, address: u16, value: u8) {
+        for bit in 0..5 {
+            write(mapper, address, (value >> bit) & 0x01);
+        }
+    }
+
+    #[test]
+    fn shift_register() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 131072));
+        for _ in 0..4 {
+            write(&mut mapper, 0x8000, 0x00);
+        }
+        assert_eq!(mapper.control, 0x0C);
+
+        // A write with bit 7 set throws away the partial value
+        write(&mut mapper, 0x8000, 0x80);
+        write_serial(&mut mapper, 0x8000, 0x02);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        assert_eq!(mapper.control, 0x02);
+
+        // and locks PRG mode 3 without touching the other control bits
+        write(&mut mapper, 0x8000, 0x80);
+        assert_eq!(mapper.control, 0x0E);
+    }
+
+    #[test]
+    fn ignores_consecutive_writes() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 131072));
+        write_serial(&mut mapper, 0x8000, 0x02);
+
+        // A read-modify-write instruction's dummy write resets the shift register, its real write one cycle later is
+        // dropped and the serial load carries on from scratch
+        mapper.cpu_write(0x8000, 0xFF);
+        mapper.clock_cpu();
+        mapper.cpu_write(0x8000, 0x01);
+        mapper.clock_cpu();
+        assert_eq!(mapper.shift_register, 0x10);
+        assert_eq!(mapper.control, 0x0E);
+
+        // Writes within one instruction, before the cartridge is clocked, count as consecutive too
+        mapper.cpu_write(0x8000, 0x01);
+        mapper.cpu_write(0x8000, 0x01);
+        assert_eq!(mapper.shift_register, 0x18);
+
+        mapper.clock_cpu();
+        mapper.clock_cpu();
+        for bit in [0, 1, 1, 0] {
+            write(&mut mapper, 0x8000, bit);
+        }
+        assert_eq!(mapper.control, 0x0D);
+    }
+
+    #[test]
+    fn prg_banking_modes() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 131072));
+        write_serial(&mut mapper, 0xE000, 0x05);
+
+        // Mode 3 on power-up: switchable bank at $8000, last bank fixed at $C000
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x14000)));
+        assert!(matches!(mapper.cpu_read(0xFFFF), MappedRead::PrgRom(0x3FFFF)));
+
+        // Mode 2: first bank fixed at $8000, switchable bank at $C000
+        write_serial(&mut mapper, 0x8000, 0x08);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x14000)));
+
+        // 32KB mode ignores the low bit of the bank number
+        write_serial(&mut mapper, 0x8000, 0x00);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x10000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x14000)));
+    }
+
+    #[test]
+    fn chr_banking_modes() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 131072));
+        write_serial(&mut mapper, 0xA000, 0x05);
+        write_serial(&mut mapper, 0xC000, 0x09);
+
+        // 8KB mode uses CHR bank 0 without its low bit for both halves
+        assert_eq!(mapper.ppu_read(0x0010), 0x4010);
+        assert_eq!(mapper.ppu_read(0x1010), 0x5010);
+        assert_eq!(mapper.ppu_write(0x0010, 0x00), None);
+
+        write_serial(&mut mapper, 0x8000, 0x1C);
+        assert_eq!(mapper.ppu_read(0x0010), 0x5010);
+        assert_eq!(mapper.ppu_read(0x1010), 0x9010);
+    }
+
+    #[test]
+    fn prg_ram_disable() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 0));
+        assert!(matches!(mapper.cpu_read(0x6123), MappedRead::PrgRam(0x0123)));
+        assert!(matches!(mapper.cpu_write(0x7FFF, 0x00), MappedWrite::PrgRam(0x1FFF)));
+
+        write_serial(&mut mapper, 0xE000, 0x10);
+        assert!(matches!(mapper.cpu_read(0x6123), MappedRead::None));
+        assert!(matches!(mapper.cpu_write(0x7FFF, 0x00), MappedWrite::None));
+        assert_eq!(mapper.ppu_write(0x1234, 0x00), Some(0x1234));
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut mapper = Mapper001::new(&test_info(1, 262144, 131072));
+        let modes = [
+            Mirroring::SingleScreenLower,
+            Mirroring::SingleScreenUpper,
+            Mirroring::Vertical,
+            Mirroring::Horizontal,
+        ];
+
+        for (value, mirroring) in modes.into_iter().enumerate() {
+            write_serial(&mut mapper, 0x9FFF, 0x0C | value as u8);
+            assert_eq!(mapper.mirroring(), mirroring);
+        }
+    }
+
+    #[test]
+    fn surom_outer_bank() {
+        let mut mapper = Mapper001::new(&test_info(1, 524288, 0));
+        write_serial(&mut mapper, 0xE000, 0x03);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0C000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x3C000)));
+
+        // Bit 4 of CHR bank 0 selects the upper 256KB, including the fixed bank
+        write_serial(&mut mapper, 0xA000, 0x10);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x4C000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x7C000)));
+    }
+
+    #[test]
+    fn no_prg_rom() {
+        let mut mapper = Mapper001::new(&test_info(1, 0, 0));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x0000)));
+    }
+}
@@ -1,26 +1,24 @@
-﻿use super::{MappedRead, MappedWrite, Mapper, Mirroring};
+﻿use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
 use crate::cartridge::{CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
 
 pub struct Mapper002 {
-    prg_banks: u8,
-    chr_banks: u8,
+    prg_banks: usize,
+    chr_banks: usize,
     mirroring: Mirroring,
     prg_bank_select: u8, // Which 16KB PRG ROM bank is in lower slot
-    bank_mask: u8,       // Mask for valid bank selection
+    bus_conflicts: bool,
 }
 
 impl Mapper002 {
-    pub fn new(prg_rom_size: usize, chr_rom_size: usize, mirroring: Mirroring) -> Self {
-        let prg_banks = (prg_rom_size / PRG_ROM_BANK_SIZE) as u8;
-        let chr_banks = (chr_rom_size / CHR_ROM_BANK_SIZE) as u8;
-        let bank_mask = prg_banks.next_power_of_two() - 1;
-
+    pub fn new(info: &CartridgeInfo) -> Self {
         Self {
-            prg_banks,
-            chr_banks,
-            mirroring,
+            prg_banks: info.prg_rom_size / PRG_ROM_BANK_SIZE,
+            chr_banks: info.chr_rom_size / CHR_ROM_BANK_SIZE,
+            mirroring: info.mirroring,
             prg_bank_select: 0,
-            bank_mask,
+            bus_conflicts: info.has_bus_conflicts(),
         }
     }
 }
@@ -29,11 +27,11 @@ impl Mapper for Mapper002 {
     fn cpu_read(&mut self, address: u16) -> MappedRead {
         match address {
             0x8000..=0xBFFF => {
-                let rom_addr = (self.prg_bank_select as usize * 0x4000) + (address - 0x8000) as usize;
+                let rom_addr = ((self.prg_bank_select as usize % self.prg_banks.max(1)) * 0x4000) + (address - 0x8000) as usize;
                 MappedRead::PrgRom(rom_addr)
             },
             0xC000..=0xFFFF => {
-                let rom_addr = ((self.prg_banks - 1) as usize * 0x4000) + (address - 0xC000) as usize;
+                let rom_addr = (self.prg_banks.saturating_sub(1) * 0x4000) + (address - 0xC000) as usize;
                 MappedRead::PrgRom(rom_addr)
             },
             _ => MappedRead::None,
@@ -43,7 +41,7 @@ impl Mapper for Mapper002 {
     fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
         match address {
             0x8000..=0xFFFF => {
-                self.prg_bank_select = value & self.bank_mask;
+                self.prg_bank_select = value;
                 MappedWrite::None
             },
             _ => MappedWrite::None,
@@ -61,4 +59,18 @@ impl Mapper for Mapper002 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_bank_select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_bank_select = reader.read_u8()?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,117 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::cartridge::{CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+/// CNROM: fixed 16KB or 32KB of PRG-ROM with a switchable 8KB CHR bank selected by writes to $8000-$FFFF
+pub struct Mapper003 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank_select: u8, // Which 8KB CHR bank is mapped to $0000-$1FFF
+}
+
+impl Mapper003 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_ROM_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_ROM_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            mirroring: info.mirroring,
+            bus_conflicts: info.has_bus_conflicts(),
+            chr_bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper003 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x8000..=0xFFFF => MappedRead::PrgRom((address - 0x8000) as usize % (self.prg_banks.max(1) * PRG_ROM_BANK_SIZE)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x8000..=0xFFFF = address {
+            self.chr_bank_select = value;
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = self.chr_bank_select as usize % self.chr_banks.max(1);
+        bank * CHR_ROM_BANK_SIZE + (address as usize & (CHR_ROM_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.chr_bank_select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.chr_bank_select = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn selects_8kb_chr_bank() {
+        let mut mapper = Mapper003::new(&test_info(3, 32768, 32768));
+        assert_eq!(mapper.ppu_read(0x0010), 0x0010);
+
+        mapper.cpu_write(0x8000, 0x02);
+        assert_eq!(mapper.ppu_read(0x0010), 2 * 8192 + 0x0010);
+        assert_eq!(mapper.ppu_read(0x1FFF), 3 * 8192 - 1);
+
+        // Bank numbers wrap around the amount of CHR-ROM present
+        mapper.cpu_write(0xFFFF, 0x05);
+        assert_eq!(mapper.ppu_read(0x0000), 8192);
+        assert_eq!(mapper.ppu_write(0x0000, 0), None);
+    }
+
+    #[test]
+    fn prg_rom_is_fixed() {
+        let mut mapper = Mapper003::new(&test_info(3, 16384, 8192));
+        mapper.cpu_write(0x8000, 0x03);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0000)));
+        assert!(matches!(mapper.cpu_read(0xC123), MappedRead::PrgRom(0x0123)));
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::None));
+
+        let mut mapper = Mapper003::new(&test_info(3, 32768, 8192));
+        assert!(matches!(mapper.cpu_read(0xC123), MappedRead::PrgRom(0x4123)));
+    }
+
+    #[test]
+    fn submapper_1_has_no_bus_conflicts() {
+        let mut info = test_info(3, 32768, 32768);
+        assert!(Mapper003::new(&info).bus_conflicts());
+
+        info.submapper = 1;
+        assert!(!Mapper003::new(&info).bus_conflicts());
+
+        info.submapper = 2;
+        assert!(Mapper003::new(&info).bus_conflicts());
+    }
+}
@@ -0,0 +1,311 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring, PpuRenderState};
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+
+// A12 has to have been low for this many PPU dots (about 3 CPU cycles) before a rise clocks the IRQ counter. This
+// ignores the short lows between sprite pattern fetches, so the counter is clocked once per scanline.
+const A12_FILTER_DOTS: u16 = 10;
+
+pub struct Mapper004 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    four_screen: bool,
+
+    bank_select: u8,    // $8000: target register, PRG mode (bit 6) and CHR inversion (bit 7)
+    registers: [u8; 8], // R0-R7 written through $8001
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    last_a12: bool,
+    a12_low_dots: u16, // PPU dots since A12 last went low
+}
+
+impl Mapper004 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            four_screen: info.mirroring == Mirroring::FourScreen,
+
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: info.mirroring,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            last_a12: false,
+            a12_low_dots: 0,
+        }
+    }
+
+    fn prg_bank_for(&self, address: u16) -> usize {
+        let second_last = self.prg_banks.saturating_sub(2);
+        let last = self.prg_banks.saturating_sub(1);
+        let prg_mode = self.bank_select & 0x40 != 0;
+
+        let bank = match (address, prg_mode) {
+            (0x8000..=0x9FFF, false) => self.registers[6] as usize,
+            (0x8000..=0x9FFF, true) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize,
+            (0xC000..=0xDFFF, false) => second_last,
+            (0xC000..=0xDFFF, true) => self.registers[6] as usize,
+            (0xE000..=0xFFFF, _) => last,
+            _ => unreachable!(),
+        };
+
+        bank % self.prg_banks.max(1)
+    }
+
+    fn chr_bank_for(&self, address: u16) -> usize {
+        // CHR inversion swaps the 2KB and 1KB halves of the pattern tables
+        let address = if self.bank_select & 0x80 != 0 { address ^ 0x1000 } else { address };
+
+        let bank = match address {
+            0x0000..=0x07FF => (self.registers[0] & 0xFE) as usize | ((address as usize >> 10) & 1),
+            0x0800..=0x0FFF => (self.registers[1] & 0xFE) as usize | ((address as usize >> 10) & 1),
+            0x1000..=0x13FF => self.registers[2] as usize,
+            0x1400..=0x17FF => self.registers[3] as usize,
+            0x1800..=0x1BFF => self.registers[4] as usize,
+            0x1C00..=0x1FFF => self.registers[5] as usize,
+            _ => unreachable!(),
+        };
+
+        bank % self.chr_banks.max(1)
+    }
+
+    /// Clocked on each filtered rising edge of PPU A12, normally once per scanline
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mapper004 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank_for(address);
+                MappedRead::PrgRom(bank * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match (address, address & 0x01) {
+            (0x6000..=0x7FFF, _) if self.prg_ram_enabled && !self.prg_ram_write_protect => return MappedWrite::PrgRam(address - 0x6000),
+            (0x8000..=0x9FFF, 0) => self.bank_select = value,
+            (0x8000..=0x9FFF, _) => self.registers[(self.bank_select & 0x07) as usize] = value,
+            (0xA000..=0xBFFF, 0) if !self.four_screen => {
+                self.mirroring = if value & 0x01 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            (0xA000..=0xBFFF, 1) => {
+                self.prg_ram_enabled = value & 0x80 != 0;
+                self.prg_ram_write_protect = value & 0x40 != 0;
+            }
+            (0xC000..=0xDFFF, 0) => self.irq_latch = value,
+            (0xC000..=0xDFFF, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, _) => self.irq_enabled = true,
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        self.chr_bank_for(address) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_address(&mut self, address: u16) {
+        let a12 = address & 0x1000 != 0;
+        if a12 && !self.last_a12 && self.a12_low_dots >= A12_FILTER_DOTS {
+            self.clock_irq_counter();
+        }
+        if a12 != self.last_a12 {
+            self.a12_low_dots = 0;
+        }
+
+        self.last_a12 = a12;
+    }
+
+    fn notify_ppu_state(&mut self, _state: PpuRenderState) {
+        if !self.last_a12 {
+            self.a12_low_dots = self.a12_low_dots.saturating_add(1);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bank_select);
+        writer.write_bytes(&self.registers);
+        self.mirroring.save_state(writer);
+        writer.write_bool(self.prg_ram_enabled);
+        writer.write_bool(self.prg_ram_write_protect);
+
+        writer.write_u8(self.irq_latch);
+        writer.write_u8(self.irq_counter);
+        writer.write_bool(self.irq_reload);
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_pending);
+        writer.write_bool(self.last_a12);
+        writer.write_u16(self.a12_low_dots);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.bank_select = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.registers)?;
+        self.mirroring.load_state(reader)?;
+        self.prg_ram_enabled = reader.read_bool()?;
+        self.prg_ram_write_protect = reader.read_bool()?;
+
+        self.irq_latch = reader.read_u8()?;
+        self.irq_counter = reader.read_u8()?;
+        self.irq_reload = reader.read_bool()?;
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+        self.last_a12 = reader.read_bool()?;
+        self.a12_low_dots = reader.read_u16()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    /// Holds A12 low for `low_dots` PPU dots, then raises it
+    fn pulse_a12(mapper: &mut Mapper004, low_dots: u16) {
+        mapper.notify_ppu_address(0x0000);
+        for _ in 0..low_dots {
+            mapper.notify_ppu_state(PpuRenderState::default());
+        }
+        mapper.notify_ppu_address(0x1000);
+    }
+
+    #[test]
+    fn prg_banking_modes() {
+        let mut mapper = Mapper004::new(&test_info(4, 262144, 262144));
+        mapper.cpu_write(0x8000, 0x06);
+        mapper.cpu_write(0x8001, 0x03);
+        mapper.cpu_write(0x8000, 0x07);
+        mapper.cpu_write(0x8001, 0x05);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x6000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0xA000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x3C000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+
+        // PRG mode 1 swaps $8000 and $C000
+        mapper.cpu_write(0x8000, 0x40);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x3C000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x6000)));
+    }
+
+    #[test]
+    fn chr_banking_and_inversion() {
+        let mut mapper = Mapper004::new(&test_info(4, 262144, 262144));
+        for register in 0..6 {
+            mapper.cpu_write(0x8000, register);
+            mapper.cpu_write(0x8001, 0x11 + register);
+        }
+
+        // R0/R1 select 2KB banks, ignoring their low bit
+        assert_eq!(mapper.ppu_read(0x0000), 0x10 * 1024);
+        assert_eq!(mapper.ppu_read(0x0400), 0x11 * 1024);
+        assert_eq!(mapper.ppu_read(0x0800), 0x12 * 1024);
+        assert_eq!(mapper.ppu_read(0x1C00), 0x16 * 1024);
+
+        mapper.cpu_write(0x8000, 0x80);
+        assert_eq!(mapper.ppu_read(0x0000), 0x13 * 1024);
+        assert_eq!(mapper.ppu_read(0x1000), 0x10 * 1024);
+        assert_eq!(mapper.ppu_read(0x1C00), 0x13 * 1024);
+    }
+
+    #[test]
+    fn irq_reload_and_acknowledge() {
+        let mut mapper = Mapper004::new(&test_info(4, 262144, 262144));
+        mapper.cpu_write(0xC000, 2);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // Reload to 2, then 1, then 0 which raises the IRQ
+        for _ in 0..2 {
+            pulse_a12(&mut mapper, A12_FILTER_DOTS);
+            assert!(!mapper.irq_pending());
+        }
+        pulse_a12(&mut mapper, A12_FILTER_DOTS);
+        assert!(mapper.irq_pending());
+
+        mapper.cpu_write(0xE000, 0);
+        assert!(!mapper.irq_pending());
+
+        // Disabled, the counter keeps running without raising the IRQ
+        pulse_a12(&mut mapper, A12_FILTER_DOTS);
+        pulse_a12(&mut mapper, A12_FILTER_DOTS);
+        pulse_a12(&mut mapper, A12_FILTER_DOTS);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn filters_short_a12_lows() {
+        let mut mapper = Mapper004::new(&test_info(4, 262144, 262144));
+        mapper.cpu_write(0xC000, 1);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // One scanline: the rise into the sprite fetches, then rises between fetches that were only briefly low
+        pulse_a12(&mut mapper, 80);
+        for _ in 0..7 {
+            pulse_a12(&mut mapper, 4);
+        }
+        assert!(!mapper.irq_pending());
+
+        pulse_a12(&mut mapper, 80);
+        assert!(mapper.irq_pending());
+    }
+}
@@ -0,0 +1,651 @@
+use super::{CartridgeInfo, MappedNametable, MappedRead, MappedWrite, Mapper, Mirroring, PpuRenderState};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, Mmc5Audio};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const SPLIT_BANK_SIZE: usize = 4096; // 4KB, also the size of extended attribute banks
+const EXRAM_SIZE: usize = 1024;
+
+// Nametable bytes the mapper makes up itself (fill mode, the split region and extended attributes) are passed back
+// through `MappedNametable::Mapper` with this bit set and the byte in the low 8 bits
+const GENERATED: u16 = 0x8000;
+
+/// MMC5. Follows rendering through `notify_ppu_state` to count scanlines, to pick the sprite or background CHR set for
+/// 8x16 sprites, and to substitute the split region and extended attributes into background fetches.
+///
+/// ExRAM modes ($5104):
+///
+/// ```text
+/// 0: nametable       1: nametable and extended attributes
+/// 2: CPU RAM         3: CPU read-only RAM
+/// ```
+pub struct Mapper005 {
+    prg_banks: usize,
+    prg_ram_banks: usize,
+    chr_size: usize,
+    chr_is_ram: bool,
+
+    prg_mode: u8,              // $5100
+    chr_mode: u8,              // $5101, 8KB, 4KB, 2KB or 1KB banks
+    prg_ram_protect: [u8; 2],  // $5102/$5103, PRG-RAM is only writable with 2 and 1 written to them
+    exram_mode: u8,            // $5104
+    nametable_mapping: u8,     // $5105, 2 bits per nametable: CIRAM page 0, CIRAM page 1, ExRAM or fill mode
+    fill_tile: u8,             // $5106
+    fill_attribute: u8,        // $5107
+    prg_banks_select: [u8; 5], // $5113-$5117, bit 7 selects ROM over RAM in $5114-$5116
+
+    // CHR banks with the $5130 upper bits they were written with. Set A ($5120-$5127) is used for sprites and set B
+    // ($5128-$512B, repeated over both pattern tables) for the background when 8x16 sprites are enabled, otherwise
+    // whichever set was written last is used for everything.
+    chr_banks_a: [u16; 8],
+    chr_banks_b: [u16; 4],
+    chr_upper: u8, // $5130
+    last_set_b: bool,
+
+    split_control: u8, // $5200: enabled (bit 7), right side (bit 6), tile threshold (bits 0-4)
+    split_scroll: u8,  // $5201
+    split_bank: u8,    // $5202
+
+    irq_compare: u8, // $5203
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline_counter: u8,
+
+    multiplicand: u8, // $5205
+    multiplier: u8,   // $5206
+
+    exram: [u8; EXRAM_SIZE],
+    ppu: PpuRenderState,
+
+    // Latched by each background nametable fetch for the attribute and pattern fetches of the same tile
+    tile_exram: u8,
+    tile_in_split: bool,
+
+    audio: Mmc5Audio,
+}
+
+impl Mapper005 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            prg_ram_banks: info.total_prg_ram_size() / PRG_BANK_SIZE,
+            chr_size: info.chr_size(),
+            chr_is_ram: info.chr_rom_size == 0,
+
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks_select: [0, 0, 0, 0, 0xFF],
+
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_set_b: false,
+
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline_counter: 0,
+
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            exram: [0; EXRAM_SIZE],
+            ppu: PpuRenderState::default(),
+
+            tile_exram: 0,
+            tile_in_split: false,
+
+            audio: Mmc5Audio::new(),
+        }
+    }
+
+    /// The 8KB bank mapped at a CPU address in $8000-$FFFF, and whether it comes from ROM rather than RAM
+    fn prg_bank(&self, address: u16) -> (usize, bool) {
+        let slot = ((address - 0x8000) as usize) / PRG_BANK_SIZE;
+
+        // Index into `prg_banks_select` and the bits of the bank number replaced by the slot in 16KB and 32KB banks
+        let (register, size_mask) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0x03),
+            (1, 0 | 1) | (2, 0 | 1) => (2, 0x01),
+            (1, _) => (4, 0x01),
+            _ => (slot + 1, 0x00),
+        };
+
+        let value = self.prg_banks_select[register];
+        let bank = (value as usize & 0x7F & !size_mask) | (slot & size_mask);
+
+        (bank, register == 4 || value & 0x80 != 0)
+    }
+
+    fn prg_ram_offset(&self, bank: usize, address: u16) -> u16 {
+        ((bank % self.prg_ram_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))) as u16
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    fn chr_offset(&self, address: u16, set_b: bool) -> usize {
+        let bank_size = 0x2000 >> self.chr_mode;
+
+        // Each bank takes the last register of its group, e.g. $5123 and $5127 for 4KB banks
+        let group = 8 >> self.chr_mode;
+        let register = (address as usize / bank_size + 1) * group - 1;
+        let bank = if set_b {
+            self.chr_banks_b[register & 0x03]
+        } else {
+            self.chr_banks_a[register]
+        };
+
+        (bank as usize * bank_size + address as usize % bank_size) % self.chr_size.max(1)
+    }
+
+    /// The step of the 8-dot tile fetch the PPU is on, if it's fetching background tiles
+    fn background_fetch(&self) -> Option<u16> {
+        match self.ppu.dot {
+            1..=256 | 321..=336 if self.ppu.rendering => Some((self.ppu.dot - 1) % 8),
+            _ => None,
+        }
+    }
+
+    fn sprite_fetch(&self) -> bool {
+        self.ppu.rendering && (257..=320).contains(&self.ppu.dot)
+    }
+
+    /// Column of the tile being fetched, dots 321-336 prefetch the first two tiles of the next scanline
+    fn tile_column(&self) -> u16 {
+        if self.ppu.dot >= 321 {
+            (self.ppu.dot - 321) / 8
+        } else {
+            (self.ppu.dot - 1) / 8 + 2
+        }
+    }
+
+    /// The split region has its own vertical scroll, starting at $5201 at the top of the screen
+    fn split_y(&self) -> u16 {
+        let line = match (self.ppu.scanline, self.ppu.dot) {
+            (261, 321..) => 0,
+            (scanline, 321..) => scanline + 1,
+            (scanline, _) => scanline,
+        };
+
+        (self.split_scroll as u16 + line) % 240
+    }
+
+    fn in_split(&self, column: u16) -> bool {
+        let threshold = (self.split_control & 0x1F) as u16;
+        let right_side = self.split_control & 0x40 != 0;
+
+        self.split_control & 0x80 != 0 && self.exram_mode < 2 && (column < threshold) != right_side
+    }
+
+    /// An attribute byte with the same palette in all four quadrants, so it applies whichever quadrant the PPU picks
+    fn attribute(palette: u8) -> MappedNametable {
+        MappedNametable::Mapper(GENERATED | ((palette & 0x03) as u16 * 0x55))
+    }
+}
+
+impl Mapper for Mapper005 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x5010 | 0x5015 => MappedRead::Data(self.audio.read(address)),
+            0x5204 => {
+                let status = ((self.irq_pending as u8) << 7) | ((self.in_frame as u8) << 6);
+                self.irq_pending = false;
+                MappedRead::Data(status)
+            }
+            0x5205 => MappedRead::Data((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => MappedRead::Data(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => MappedRead::Data(self.exram[(address - 0x5C00) as usize]),
+            0x6000..=0x7FFF => MappedRead::PrgRam(self.prg_ram_offset(self.prg_banks_select[0] as usize, address)),
+            0x8000..=0xFFFF => match self.prg_bank(address) {
+                (bank, true) => MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))),
+                (bank, false) => MappedRead::PrgRam(self.prg_ram_offset(bank, address)),
+            },
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x5000..=0x5015 => self.audio.write(address, value),
+            0x5100 => self.prg_mode = value & 0x03,
+            0x5101 => self.chr_mode = value & 0x03,
+            0x5102 | 0x5103 => self.prg_ram_protect[(address - 0x5102) as usize] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113..=0x5117 => self.prg_banks_select[(address - 0x5113) as usize] = value,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[(address - 0x5120) as usize] = ((self.chr_upper as u16) << 8) | value as u16;
+                self.last_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[(address - 0x5128) as usize] = ((self.chr_upper as u16) << 8) | value as u16;
+                self.last_set_b = true;
+            }
+            0x5130 => self.chr_upper = value & 0x03,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let offset = (address - 0x5C00) as usize;
+                match self.exram_mode {
+                    // While ExRAM is in use as a nametable the CPU can only write to it during rendering, 0 is written otherwise
+                    0 | 1 => self.exram[offset] = if self.in_frame { value } else { 0 },
+                    2 => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable() => {
+                return MappedWrite::PrgRam(self.prg_ram_offset(self.prg_banks_select[0] as usize, address));
+            }
+            0x8000..=0xDFFF if self.prg_ram_writable() => {
+                if let (bank, false) = self.prg_bank(address) {
+                    return MappedWrite::PrgRam(self.prg_ram_offset(bank, address));
+                }
+            }
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        if self.background_fetch().is_some() {
+            if self.tile_in_split {
+                let offset = (address as usize & 0x0FF8) | (self.split_y() as usize & 0x07);
+                return (self.split_bank as usize * SPLIT_BANK_SIZE + offset) % self.chr_size.max(1);
+            }
+
+            if self.exram_mode == 1 {
+                let bank = ((self.chr_upper as usize) << 6) | (self.tile_exram & 0x3F) as usize;
+                return (bank * SPLIT_BANK_SIZE + (address as usize & 0x0FFF)) % self.chr_size.max(1);
+            }
+        }
+
+        let set_b = if self.ppu.large_sprites && self.ppu.rendering {
+            !self.sprite_fetch()
+        } else {
+            self.last_set_b
+        };
+        self.chr_offset(address, set_b)
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        // Only used as a fallback, nametables are mapped by `ppu_nametable`
+        match self.nametable_mapping {
+            0x44 => Mirroring::Vertical,
+            0x50 => Mirroring::Horizontal,
+            0x55 => Mirroring::SingleScreenUpper,
+            _ => Mirroring::SingleScreenLower,
+        }
+    }
+
+    fn ppu_nametable(&mut self, address: u16) -> MappedNametable {
+        let offset = address & 0x03FF;
+
+        match self.background_fetch() {
+            Some(0) => {
+                let column = self.tile_column();
+                self.tile_exram = self.exram[offset as usize];
+                self.tile_in_split = self.in_split(column);
+
+                if self.tile_in_split {
+                    let tile = (self.split_y() / 8) * 32 + (column & 0x1F);
+                    return MappedNametable::Mapper(GENERATED | self.exram[tile as usize] as u16);
+                }
+            }
+            Some(2) if self.tile_in_split => {
+                let row = self.split_y() / 8;
+                let column = self.tile_column() & 0x1F;
+                let attribute = self.exram[(0x3C0 + (row / 4) * 8 + column / 4) as usize];
+                return Self::attribute(attribute >> (((row & 0x02) << 1) | (column & 0x02)));
+            }
+            Some(2) if self.exram_mode == 1 => return Self::attribute(self.tile_exram >> 6),
+            _ => {}
+        }
+
+        match (self.nametable_mapping >> (((address >> 10) & 0x03) * 2)) & 0x03 {
+            0 => MappedNametable::Ciram(offset),
+            1 => MappedNametable::Ciram(0x0400 | offset),
+            2 if self.exram_mode < 2 => MappedNametable::Mapper(offset),
+            2 => MappedNametable::Mapper(GENERATED), // ExRAM reads as 0 while the CPU is using it
+            _ if offset >= 0x03C0 => Self::attribute(self.fill_attribute),
+            _ => MappedNametable::Mapper(GENERATED | self.fill_tile as u16),
+        }
+    }
+
+    fn read_nametable(&mut self, offset: u16) -> u8 {
+        if offset & GENERATED != 0 {
+            offset as u8
+        } else {
+            self.exram[offset as usize & (EXRAM_SIZE - 1)]
+        }
+    }
+
+    fn write_nametable(&mut self, offset: u16, value: u8) {
+        if offset & GENERATED == 0 {
+            self.exram[offset as usize & (EXRAM_SIZE - 1)] = value;
+        }
+    }
+
+    /// The hardware spots the start of each scanline from the repeated nametable fetches at its end, counting from 0 on
+    /// the first visible scanline. The frame ends once the PPU stops fetching, in vblank or with rendering disabled.
+    fn notify_ppu_state(&mut self, state: PpuRenderState) {
+        self.ppu = state;
+
+        if !state.rendering || state.scanline >= 240 {
+            if self.in_frame {
+                self.in_frame = false;
+                self.irq_pending = false;
+            }
+            return;
+        }
+
+        if state.dot == 1 {
+            if !self.in_frame {
+                self.in_frame = true;
+                self.scanline_counter = 0;
+            } else {
+                self.scanline_counter = self.scanline_counter.wrapping_add(1);
+                if self.scanline_counter == self.irq_compare {
+                    self.irq_pending = true;
+                }
+            }
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_mode);
+        writer.write_u8(self.chr_mode);
+        writer.write_bytes(&self.prg_ram_protect);
+        writer.write_u8(self.exram_mode);
+        writer.write_u8(self.nametable_mapping);
+        writer.write_u8(self.fill_tile);
+        writer.write_u8(self.fill_attribute);
+        writer.write_bytes(&self.prg_banks_select);
+
+        for bank in self.chr_banks_a.iter().chain(&self.chr_banks_b) {
+            writer.write_u16(*bank);
+        }
+        writer.write_u8(self.chr_upper);
+        writer.write_bool(self.last_set_b);
+
+        writer.write_u8(self.split_control);
+        writer.write_u8(self.split_scroll);
+        writer.write_u8(self.split_bank);
+
+        writer.write_u8(self.irq_compare);
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_pending);
+        writer.write_bool(self.in_frame);
+        writer.write_u8(self.scanline_counter);
+
+        writer.write_u8(self.multiplicand);
+        writer.write_u8(self.multiplier);
+
+        writer.write_bytes(&self.exram);
+        writer.write_u8(self.tile_exram);
+        writer.write_bool(self.tile_in_split);
+
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_mode = reader.read_u8()? & 0x03;
+        self.chr_mode = reader.read_u8()? & 0x03;
+        reader.read_bytes_into(&mut self.prg_ram_protect)?;
+        self.exram_mode = reader.read_u8()? & 0x03;
+        self.nametable_mapping = reader.read_u8()?;
+        self.fill_tile = reader.read_u8()?;
+        self.fill_attribute = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.prg_banks_select)?;
+
+        for bank in self.chr_banks_a.iter_mut().chain(&mut self.chr_banks_b) {
+            *bank = reader.read_u16()?;
+        }
+        self.chr_upper = reader.read_u8()?;
+        self.last_set_b = reader.read_bool()?;
+
+        self.split_control = reader.read_u8()?;
+        self.split_scroll = reader.read_u8()?;
+        self.split_bank = reader.read_u8()?;
+
+        self.irq_compare = reader.read_u8()?;
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+        self.in_frame = reader.read_bool()?;
+        self.scanline_counter = reader.read_u8()?;
+
+        self.multiplicand = reader.read_u8()?;
+        self.multiplier = reader.read_u8()?;
+
+        reader.read_bytes_into(&mut self.exram)?;
+        self.tile_exram = reader.read_u8()?;
+        self.tile_in_split = reader.read_bool()?;
+
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    fn mmc5() -> Mapper005 {
+        Mapper005::new(&test_info(5, 131072, 131072))
+    }
+
+    fn render_state(scanline: u16, dot: u16, large_sprites: bool) -> PpuRenderState {
+        PpuRenderState {
+            rendering: true,
+            scanline,
+            dot,
+            large_sprites,
+        }
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut mapper = mmc5();
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x1E000))); // $5117 starts at $FF
+
+        mapper.cpu_write(0x5100, 0x00); // 32KB
+        mapper.cpu_write(0x5117, 0x05);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x8000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0xE000)));
+
+        mapper.cpu_write(0x5100, 0x01); // 16KB + 16KB, $8000 from RAM
+        mapper.cpu_write(0x5115, 0x01);
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRam(0x0000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x8000)));
+
+        mapper.cpu_write(0x5100, 0x03); // 8KB x 4
+        mapper.cpu_write(0x5114, 0x83);
+        mapper.cpu_write(0x5116, 0x84);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x6000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x8000)));
+    }
+
+    #[test]
+    fn prg_ram_needs_both_protect_registers() {
+        let mut mapper = mmc5();
+        assert!(matches!(mapper.cpu_write(0x6000, 0), MappedWrite::None));
+
+        mapper.cpu_write(0x5102, 0x02);
+        mapper.cpu_write(0x5103, 0x01);
+        assert!(matches!(mapper.cpu_write(0x6001, 0), MappedWrite::PrgRam(0x0001)));
+    }
+
+    #[test]
+    fn chr_sets_with_large_sprites() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5101, 0x03); // 1KB banks
+        mapper.cpu_write(0x5120, 0x10);
+        mapper.cpu_write(0x5128, 0x20);
+
+        // 8x8 sprites use the last written set for everything
+        mapper.notify_ppu_state(render_state(10, 261, false));
+        assert_eq!(mapper.ppu_read(0x0000), 0x20 * 1024);
+
+        // 8x16 sprites use set A for sprites and set B for the background
+        mapper.notify_ppu_state(render_state(10, 261, true));
+        assert_eq!(mapper.ppu_read(0x0000), 0x10 * 1024);
+        mapper.notify_ppu_state(render_state(10, 5, true));
+        assert_eq!(mapper.ppu_read(0x0000), 0x20 * 1024);
+        assert_eq!(mapper.ppu_read(0x1000), 0x20 * 1024);
+    }
+
+    #[test]
+    fn fill_mode_and_exram_nametables() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5105, 0b11_10_01_00);
+        mapper.cpu_write(0x5106, 0x42);
+        mapper.cpu_write(0x5107, 0x02);
+
+        assert_eq!(mapper.ppu_nametable(0x2010), MappedNametable::Ciram(0x0010));
+        assert_eq!(mapper.ppu_nametable(0x2410), MappedNametable::Ciram(0x0410));
+        assert_eq!(mapper.ppu_nametable(0x2810), MappedNametable::Mapper(0x0010));
+
+        let tile = mapper.ppu_nametable(0x2C10);
+        let attribute = mapper.ppu_nametable(0x2FC0);
+        assert_eq!(
+            mapper.read_nametable(match tile {
+                MappedNametable::Mapper(offset) => offset,
+                _ => unreachable!(),
+            }),
+            0x42
+        );
+        assert_eq!(
+            mapper.read_nametable(match attribute {
+                MappedNametable::Mapper(offset) => offset,
+                _ => unreachable!(),
+            }),
+            0xAA
+        );
+    }
+
+    #[test]
+    fn extended_attributes() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5104, 0x02);
+        mapper.cpu_write(0x5C05, 0xC3); // Palette 3, 4KB bank 3
+        mapper.cpu_write(0x5104, 0x01);
+
+        mapper.notify_ppu_state(render_state(0, 1, false));
+        mapper.ppu_nametable(0x2005);
+        mapper.notify_ppu_state(render_state(0, 3, false));
+        assert_eq!(mapper.ppu_nametable(0x23C1), MappedNametable::Mapper(GENERATED | 0xFF));
+        mapper.notify_ppu_state(render_state(0, 5, false));
+        assert_eq!(mapper.ppu_read(0x1123), 3 * 4096 + 0x0123);
+    }
+
+    #[test]
+    fn vertical_split() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5200, 0x82); // Left of tile 2
+        mapper.cpu_write(0x5201, 0x08); // Scrolled down one row of tiles
+        mapper.cpu_write(0x5202, 0x01);
+
+        // ExRAM can only be written as nametable data while the PPU is rendering
+        mapper.notify_ppu_state(render_state(0, 1, false));
+        mapper.cpu_write(0x5C21, 0x77); // Row 1, column 1
+
+        // Dots 329-336 of the previous scanline fetch column 1
+        mapper.notify_ppu_state(render_state(261, 329, false));
+        assert_eq!(mapper.ppu_nametable(0x2000), MappedNametable::Mapper(GENERATED | 0x77));
+        mapper.notify_ppu_state(render_state(261, 333, false));
+        assert_eq!(mapper.ppu_read(0x0775), 4096 + 0x0770);
+
+        // Column 2 onwards is outside the split
+        mapper.notify_ppu_state(render_state(0, 1, false));
+        assert_eq!(mapper.ppu_nametable(0x2002), MappedNametable::Ciram(0x0002));
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5203, 2);
+        mapper.cpu_write(0x5204, 0x80);
+        assert!(matches!(mapper.cpu_read(0x5204), MappedRead::Data(0x00)));
+
+        for scanline in 0..3 {
+            assert!(!mapper.irq_pending());
+            mapper.notify_ppu_state(render_state(scanline, 1, false));
+            mapper.notify_ppu_state(render_state(scanline, 2, false));
+        }
+        assert!(mapper.irq_pending());
+        assert!(matches!(mapper.cpu_read(0x5204), MappedRead::Data(0xC0)));
+        assert!(!mapper.irq_pending());
+
+        // Turning rendering off mid-frame ends the frame, and the count starts over once it's back on
+        mapper.notify_ppu_state(PpuRenderState {
+            rendering: false,
+            ..render_state(100, 1, false)
+        });
+        assert!(matches!(mapper.cpu_read(0x5204), MappedRead::Data(0x00)));
+
+        for scanline in 101..103 {
+            mapper.notify_ppu_state(render_state(scanline, 1, false));
+            assert!(!mapper.irq_pending());
+        }
+        assert!(matches!(mapper.cpu_read(0x5204), MappedRead::Data(0x40)));
+        mapper.notify_ppu_state(render_state(103, 1, false));
+        assert!(mapper.irq_pending());
+
+        // Vblank ends the frame and acknowledges the IRQ
+        mapper.notify_ppu_state(render_state(240, 1, false));
+        assert!(!mapper.irq_pending());
+        assert!(matches!(mapper.cpu_read(0x5204), MappedRead::Data(0x00)));
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut mapper = mmc5();
+        mapper.cpu_write(0x5205, 200);
+        mapper.cpu_write(0x5206, 100);
+
+        assert!(matches!(mapper.cpu_read(0x5205), MappedRead::Data(0x20)));
+        assert!(matches!(mapper.cpu_read(0x5206), MappedRead::Data(0x4E)));
+    }
+}
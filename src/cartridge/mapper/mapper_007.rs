@@ -0,0 +1,121 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 32768; // 32KB
+
+/// AxROM: a switchable 32KB PRG bank plus single-screen mirroring, both selected by writes to $8000-$FFFF
+pub struct Mapper007 {
+    prg_banks: usize,
+    chr_is_ram: bool,
+    bus_conflicts: bool,
+    bank_select: u8, // PRG bank (bits 0-2) and nametable select (bit 4)
+}
+
+impl Mapper007 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            bus_conflicts: info.submapper == 2, // Only AMROM has conflicts, ANROM and AOROM disable the ROM on writes
+            bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper007 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.bank_select & 0x07) as usize % self.prg_banks.max(1);
+                MappedRead::PrgRom(bank * PRG_BANK_SIZE + (address - 0x8000) as usize)
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x8000..=0xFFFF = address {
+            self.bank_select = value;
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        address as usize
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(address as usize) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank_select & 0x10 == 0 {
+            Mirroring::SingleScreenLower
+        } else {
+            Mirroring::SingleScreenUpper
+        }
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.bank_select = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn selects_32kb_prg_bank() {
+        let mut mapper = Mapper007::new(&test_info(7, 131072, 0));
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0000)));
+
+        mapper.cpu_write(0x8000, 0x03);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x18000)));
+        assert!(matches!(mapper.cpu_read(0xFFFF), MappedRead::PrgRom(0x1FFFF)));
+
+        // Only 4 banks are present, so bank 6 wraps to bank 2
+        mapper.cpu_write(0xC000, 0x06);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x10000)));
+    }
+
+    #[test]
+    fn selects_single_screen_nametable() {
+        let mut mapper = Mapper007::new(&test_info(7, 131072, 0));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.cpu_write(0x8000, 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+
+        mapper.cpu_write(0x8000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn only_amrom_has_bus_conflicts() {
+        let mut info = test_info(7, 131072, 0);
+        assert!(!Mapper007::new(&info).bus_conflicts());
+
+        info.submapper = 2;
+        assert!(Mapper007::new(&info).bus_conflicts());
+    }
+
+    #[test]
+    fn chr_ram_is_writable() {
+        let mut mapper = Mapper007::new(&test_info(7, 32768, 0));
+        assert_eq!(mapper.ppu_write(0x1234, 0xAB), Some(0x1234));
+    }
+}
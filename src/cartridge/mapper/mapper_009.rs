@@ -0,0 +1,214 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 4096; // 4KB
+
+/// The pair of CHR latches shared by MMC2 and MMC4. Each 4KB pattern table has two bank registers, one used after the
+/// PPU fetches tile $FD and the other after tile $FE, so games can switch CHR part way through a frame by placing
+/// those tiles on screen.
+pub(super) struct ChrLatches {
+    pub banks: [[u8; 2]; 2], // [pattern table][$FD, $FE]
+    pub latches: [bool; 2],  // Per pattern table, true once $FE has been fetched
+}
+
+impl ChrLatches {
+    pub fn new() -> Self {
+        Self {
+            banks: [[0; 2]; 2],
+            latches: [true; 2],
+        }
+    }
+
+    pub fn chr_offset(&self, address: u16, chr_banks: usize) -> usize {
+        let table = (address >> 12) as usize & 0x01;
+        let bank = self.banks[table][self.latches[table] as usize] as usize;
+        (bank % chr_banks.max(1)) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    /// Updates the latches after a pattern read. MMC2 only reacts to exactly $0FD8/$0FE8 in the first pattern table,
+    /// MMC4 to the whole 8 byte range there, and both to $xFD8-$xFDF/$xFE8-$xFEF in the second.
+    pub fn notify_read(&mut self, address: u16, exact_low_table: bool) {
+        let table = (address >> 12) as usize & 0x01;
+        let trigger = if table == 0 && exact_low_table { address & 0x1FFF } else { address & 0x1FF8 };
+
+        match trigger & 0x0FFF {
+            0x0FD8 => self.latches[table] = false,
+            0x0FE8 => self.latches[table] = true,
+            _ => {}
+        }
+    }
+
+    pub fn write_bank(&mut self, address: u16, value: u8) {
+        match address {
+            0xB000..=0xBFFF => self.banks[0][0] = value & 0x1F,
+            0xC000..=0xCFFF => self.banks[0][1] = value & 0x1F,
+            0xD000..=0xDFFF => self.banks[1][0] = value & 0x1F,
+            0xE000..=0xEFFF => self.banks[1][1] = value & 0x1F,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        for table in &self.banks {
+            writer.write_u8(table[0]);
+            writer.write_u8(table[1]);
+        }
+        writer.write_bool(self.latches[0]);
+        writer.write_bool(self.latches[1]);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        for table in &mut self.banks {
+            table[0] = reader.read_u8()?;
+            table[1] = reader.read_u8()?;
+        }
+        self.latches[0] = reader.read_bool()?;
+        self.latches[1] = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+/// MMC2 (PxROM): a switchable 8KB PRG bank at $8000 with the last three fixed, and two latched 4KB CHR banks
+pub struct Mapper009 {
+    prg_banks: usize,
+    chr_banks: usize,
+
+    prg_bank: u8, // $A000-$AFFF
+    chr: ChrLatches,
+    mirroring: Mirroring,
+}
+
+impl Mapper009 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+
+            prg_bank: 0,
+            chr: ChrLatches::new(),
+            mirroring: info.mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper009 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        let bank = match address {
+            0x6000..=0x7FFF => return MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0x9FFF => self.prg_bank as usize,
+            0xA000..=0xFFFF => self.prg_banks.saturating_sub(3) + ((address - 0xA000) as usize / PRG_BANK_SIZE),
+            _ => return MappedRead::None,
+        };
+
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x6000..=0x7FFF => return MappedWrite::PrgRam(address - 0x6000),
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xEFFF => self.chr.write_bank(address, value),
+            0xF000..=0xFFFF => self.mirroring = if value & 0x01 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal },
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        self.chr.chr_offset(address, self.chr_banks)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) -> Option<usize> {
+        None
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_read(&mut self, address: u16) {
+        self.chr.notify_read(address, true);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_bank);
+        self.chr.save_state(writer);
+        writer.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_bank = reader.read_u8()?;
+        self.chr.load_state(reader)?;
+        self.mirroring = if reader.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn prg_banking_fixes_last_three_banks() {
+        let mut mapper = Mapper009::new(&test_info(9, 131072, 131072));
+        mapper.cpu_write(0xA000, 0x05);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0xA000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x1A000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x1C000)));
+        assert!(matches!(mapper.cpu_read(0xFFFF), MappedRead::PrgRom(0x1FFFF)));
+    }
+
+    #[test]
+    fn latches_switch_after_fd_and_fe_fetches() {
+        let mut mapper = Mapper009::new(&test_info(9, 131072, 131072));
+        mapper.cpu_write(0xB000, 0x01); // $FD, $0000
+        mapper.cpu_write(0xC000, 0x02); // $FE, $0000
+        mapper.cpu_write(0xD000, 0x03); // $FD, $1000
+        mapper.cpu_write(0xE000, 0x04); // $FE, $1000
+
+        assert_eq!(mapper.ppu_read(0x0000), 2 * 4096);
+        assert_eq!(mapper.ppu_read(0x1000), 4 * 4096);
+
+        // The fetch that trips the latch still comes from the old bank
+        assert_eq!(mapper.ppu_read(0x0FD8), 2 * 4096 + 0x0FD8);
+        mapper.notify_ppu_read(0x0FD8);
+        assert_eq!(mapper.ppu_read(0x0000), 4096);
+        assert_eq!(mapper.ppu_read(0x1000), 4 * 4096);
+
+        mapper.notify_ppu_read(0x1FDD);
+        assert_eq!(mapper.ppu_read(0x1000), 3 * 4096);
+
+        mapper.notify_ppu_read(0x1FE8);
+        mapper.notify_ppu_read(0x0FE8);
+        assert_eq!(mapper.ppu_read(0x0000), 2 * 4096);
+        assert_eq!(mapper.ppu_read(0x1000), 4 * 4096);
+    }
+
+    #[test]
+    fn low_table_latch_only_reacts_to_exact_address() {
+        let mut mapper = Mapper009::new(&test_info(9, 131072, 131072));
+        mapper.cpu_write(0xB000, 0x01);
+        mapper.cpu_write(0xC000, 0x02);
+
+        mapper.notify_ppu_read(0x0FD9);
+        assert_eq!(mapper.ppu_read(0x0000), 2 * 4096);
+    }
+
+    #[test]
+    fn selects_mirroring() {
+        let mut mapper = Mapper009::new(&test_info(9, 131072, 131072));
+
+        mapper.cpu_write(0xF000, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        mapper.cpu_write(0xF000, 0x01);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+}
@@ -0,0 +1,115 @@
+use super::mapper_009::ChrLatches;
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::cartridge::PRG_ROM_BANK_SIZE;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const CHR_BANK_SIZE: usize = 4096; // 4KB
+
+/// MMC4 (FxROM): a switchable 16KB PRG bank at $8000 with the last fixed, and the same latched CHR banks as MMC2
+pub struct Mapper010 {
+    prg_banks: usize,
+    chr_banks: usize,
+
+    prg_bank: u8, // $A000-$AFFF
+    chr: ChrLatches,
+    mirroring: Mirroring,
+}
+
+impl Mapper010 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_ROM_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+
+            prg_bank: 0,
+            chr: ChrLatches::new(),
+            mirroring: info.mirroring,
+        }
+    }
+}
+
+impl Mapper for Mapper010 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        let bank = match address {
+            0x6000..=0x7FFF => return MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            0xC000..=0xFFFF => self.prg_banks.saturating_sub(1),
+            _ => return MappedRead::None,
+        };
+
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_ROM_BANK_SIZE + (address as usize & (PRG_ROM_BANK_SIZE - 1)))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x6000..=0x7FFF => return MappedWrite::PrgRam(address - 0x6000),
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xEFFF => self.chr.write_bank(address, value),
+            0xF000..=0xFFFF => self.mirroring = if value & 0x01 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal },
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        self.chr.chr_offset(address, self.chr_banks)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) -> Option<usize> {
+        None
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_read(&mut self, address: u16) {
+        self.chr.notify_read(address, false);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_bank);
+        self.chr.save_state(writer);
+        writer.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_bank = reader.read_u8()?;
+        self.chr.load_state(reader)?;
+        self.mirroring = if reader.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn prg_banking_fixes_last_bank() {
+        let mut mapper = Mapper010::new(&test_info(10, 131072, 131072));
+        mapper.cpu_write(0xA000, 0x03);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0xC000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x1C000)));
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::PrgRam(0x0000)));
+    }
+
+    #[test]
+    fn low_table_latch_reacts_to_whole_row_range() {
+        let mut mapper = Mapper010::new(&test_info(10, 131072, 131072));
+        mapper.cpu_write(0xB000, 0x01);
+        mapper.cpu_write(0xC000, 0x02);
+        assert_eq!(mapper.ppu_read(0x0000), 2 * 4096);
+
+        mapper.notify_ppu_read(0x0FDF);
+        assert_eq!(mapper.ppu_read(0x0000), 4096);
+
+        mapper.notify_ppu_read(0x0FEA);
+        assert_eq!(mapper.ppu_read(0x0000), 2 * 4096);
+    }
+}
@@ -0,0 +1,263 @@
+use super::{CartridgeInfo, MappedNametable, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, Namco163Audio};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+const CIRAM_BANKS: u8 = 0xE0; // Nametable bank numbers from here up select CIRAM instead of CHR-ROM
+
+/// Namco 163. Each of the 4 nametables can be either CIRAM or any 1KB of CHR-ROM. Pattern table banks of $E0 and up
+/// can select CIRAM on hardware too, which isn't emulated, those banks are treated as CHR-ROM.
+pub struct Mapper019 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+
+    chr_banks_select: [u8; 8],       // $8000-$BFFF, $800 apart
+    nametable_banks_select: [u8; 4], // $C000-$DFFF, $800 apart
+    prg_banks_select: [u8; 3],       // $E000, $E800 and $F000, bits 0-5
+    sound_disabled: bool,            // $E000 bit 6
+    write_protect: u8,               // $F800
+
+    irq_counter: u16, // 15-bit, $5000 low and $5800 high
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    audio: Namco163Audio,
+}
+
+impl Mapper019 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+
+            chr_banks_select: [0; 8],
+            nametable_banks_select: [CIRAM_BANKS; 4],
+            prg_banks_select: [0; 3],
+            sound_disabled: false,
+            write_protect: 0,
+
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+
+            audio: Namco163Audio::new(),
+        }
+    }
+
+    fn chr_offset(&self, bank: u8, address: u16) -> usize {
+        (bank as usize % self.chr_banks.max(1)) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    /// PRG-RAM writes need $4x in the upper nybble of $F800, with bits 0-3 then protecting each 2KB
+    fn prg_ram_writable(&self, address: u16) -> bool {
+        let region = (address - 0x6000) >> 11;
+        self.write_protect & 0xF0 == 0x40 && self.write_protect & (1 << region) == 0
+    }
+}
+
+impl Mapper for Mapper019 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        let bank = match address {
+            0x4800..=0x4FFF => return MappedRead::Data(self.audio.read_data()),
+            0x5000..=0x57FF => return MappedRead::Data(self.irq_counter as u8),
+            0x5800..=0x5FFF => return MappedRead::Data(((self.irq_counter >> 8) as u8) | ((self.irq_enabled as u8) << 7)),
+            0x6000..=0x7FFF => return MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xDFFF => self.prg_banks_select[((address - 0x8000) / 0x2000) as usize] as usize,
+            0xE000..=0xFFFF => self.prg_banks.saturating_sub(1),
+            _ => return MappedRead::None,
+        };
+
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x4800..=0x4FFF => self.audio.write_data(value),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (((value & 0x7F) as u16) << 8);
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(address) => return MappedWrite::PrgRam(address - 0x6000),
+            0x8000..=0xBFFF => self.chr_banks_select[((address - 0x8000) >> 11) as usize] = value,
+            0xC000..=0xDFFF => self.nametable_banks_select[((address - 0xC000) >> 11) as usize] = value,
+            0xE000..=0xE7FF => {
+                self.prg_banks_select[0] = value & 0x3F;
+                self.sound_disabled = value & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks_select[1] = value & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks_select[2] = value & 0x3F,
+            0xF800..=0xFFFF => {
+                // Shared between PRG-RAM write protection and the sound RAM address port
+                self.write_protect = value;
+                self.audio.write_address(value);
+            }
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        self.chr_offset(self.chr_banks_select[(address as usize >> 10) & 0x07], address)
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical // Only used as a fallback, nametables are mapped by `ppu_nametable`
+    }
+
+    fn ppu_nametable(&mut self, address: u16) -> MappedNametable {
+        let bank = self.nametable_banks_select[((address >> 10) & 0x03) as usize];
+
+        if bank >= CIRAM_BANKS {
+            MappedNametable::Ciram(((bank as u16 & 0x01) << 10) | (address & 0x03FF))
+        } else {
+            MappedNametable::Chr(self.chr_offset(bank, address))
+        }
+    }
+
+    /// The IRQ counter counts up every CPU cycle and stops at $7FFF, raising an IRQ when it gets there
+    fn clock_cpu(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        if self.sound_disabled { 0.0 } else { self.audio.output() }
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn set_average_multiplexed_channels(&mut self, enabled: bool) {
+        self.audio.average_channels = enabled;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.chr_banks_select);
+        writer.write_bytes(&self.nametable_banks_select);
+        writer.write_bytes(&self.prg_banks_select);
+        writer.write_bool(self.sound_disabled);
+        writer.write_u8(self.write_protect);
+
+        writer.write_u16(self.irq_counter);
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_pending);
+
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.chr_banks_select)?;
+        reader.read_bytes_into(&mut self.nametable_banks_select)?;
+        reader.read_bytes_into(&mut self.prg_banks_select)?;
+        self.sound_disabled = reader.read_bool()?;
+        self.write_protect = reader.read_u8()?;
+
+        self.irq_counter = reader.read_u16()? & 0x7FFF;
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn prg_and_chr_banking() {
+        let mut mapper = Mapper019::new(&test_info(19, 262144, 262144));
+        mapper.cpu_write(0xE000, 0x01);
+        mapper.cpu_write(0xE800, 0x02);
+        mapper.cpu_write(0xF000, 0x03);
+        mapper.cpu_write(0xB800, 0x42);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x2000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x4000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x6000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+        assert_eq!(mapper.ppu_read(0x1C05), 0x42 * 1024 + 0x05);
+    }
+
+    #[test]
+    fn nametables_from_ciram_or_chr_rom() {
+        let mut mapper = Mapper019::new(&test_info(19, 262144, 262144));
+        mapper.cpu_write(0xC000, 0xE0);
+        mapper.cpu_write(0xC800, 0xE1);
+        mapper.cpu_write(0xD000, 0x10);
+
+        assert_eq!(mapper.ppu_nametable(0x2010), MappedNametable::Ciram(0x0010));
+        assert_eq!(mapper.ppu_nametable(0x2410), MappedNametable::Ciram(0x0410));
+        assert_eq!(mapper.ppu_nametable(0x2810), MappedNametable::Chr(0x10 * 1024 + 0x10));
+    }
+
+    #[test]
+    fn irq_counts_up_to_7fff() {
+        let mut mapper = Mapper019::new(&test_info(19, 262144, 262144));
+        mapper.cpu_write(0x5000, 0xFD);
+        mapper.cpu_write(0x5800, 0xFF);
+
+        mapper.clock_cpu();
+        assert!(!mapper.irq_pending());
+
+        mapper.clock_cpu();
+        assert!(mapper.irq_pending());
+
+        // The counter stops at $7FFF
+        mapper.clock_cpu();
+        assert!(matches!(mapper.cpu_read(0x5000), MappedRead::Data(0xFF)));
+        assert!(matches!(mapper.cpu_read(0x5800), MappedRead::Data(0xFF)));
+
+        mapper.cpu_write(0x5000, 0x00);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn prg_ram_write_protection() {
+        let mut mapper = Mapper019::new(&test_info(19, 262144, 262144));
+        assert!(matches!(mapper.cpu_write(0x6000, 0), MappedWrite::None));
+
+        mapper.cpu_write(0xF800, 0x42); // Writes enabled except $6800-$6FFF
+        assert!(matches!(mapper.cpu_write(0x6000, 0), MappedWrite::PrgRam(0x0000)));
+        assert!(matches!(mapper.cpu_write(0x6800, 0), MappedWrite::None));
+    }
+
+    #[test]
+    fn sound_ram_port() {
+        let mut mapper = Mapper019::new(&test_info(19, 262144, 262144));
+        mapper.cpu_write(0xF800, 0x80 | 0x10);
+        mapper.cpu_write(0x4800, 0xAB);
+        mapper.cpu_write(0x4800, 0xCD);
+
+        mapper.cpu_write(0xF800, 0x80 | 0x10);
+        assert!(matches!(mapper.cpu_read(0x4800), MappedRead::Data(0xAB)));
+        assert!(matches!(mapper.cpu_read(0x4800), MappedRead::Data(0xCD)));
+    }
+}
@@ -0,0 +1,588 @@
+use super::{MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, FdsAudio};
+use crate::cartridge::disk::{apply_diff, encode_diff, update_crc};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const BYTE_TRANSFER_CYCLES: u32 = 150; // The drive moves about 96.4kbit/s past the head, roughly one byte every 150 CPU cycles
+const SPIN_UP_CYCLES: u32 = 50000; // Delay between the motor starting and the head reaching the start of the disk
+const DISK_SWAP_CYCLES: u32 = 1_789_773; // Keep the drive empty for a second when switching sides so the BIOS notices
+
+// $4025 control bits
+const MOTOR_ON: u8 = 0x01;
+const TRANSFER_RESET: u8 = 0x02;
+const READ_MODE: u8 = 0x04;
+const HORIZONTAL_MIRRORING: u8 = 0x08;
+const CRC_CONTROL: u8 = 0x10;
+const TRANSFER_START: u8 = 0x40; // Wait for the end of a gap when reading, write data rather than zeroes when writing
+const TRANSFER_IRQ: u8 = 0x80;
+
+/// Famicom Disk System RAM adapter. iNES set mapper 20 aside for FDS images, which are loaded from .fds files through
+/// `Cartridge::from_fds` rather than an iNES header. The adapter has 32KB of PRG-RAM at $6000-$DFFF, 8KB of CHR-RAM, the
+/// BIOS at $E000, a CPU cycle timer IRQ, the disk drive and the FDS sound channel.
+///
+/// Sides are held as the drive sees them, with the gaps, start marks and CRCs that .fds images leave out. The drive
+/// reads or writes one byte every `BYTE_TRANSFER_CYCLES` while its motor runs, raising an IRQ for each when enabled.
+pub struct Mapper020 {
+    sides: Vec<Vec<u8>>,
+    original_sides: Vec<Vec<u8>>,
+    disk_dirty: bool,
+
+    irq_reload: u16, // $4020/$4021
+    irq_counter: u16,
+    irq_repeat: bool,  // $4022 bit 0
+    irq_enabled: bool, // $4022 bit 1
+    timer_irq: bool,
+
+    disk_io_enabled: bool,  // $4023 bit 0
+    sound_io_enabled: bool, // $4023 bit 1
+    control: u8,            // $4025
+    write_data: u8,         // $4024
+    read_data: u8,          // $4031
+    external: u8,           // $4026
+    transfer_complete: bool,
+    disk_irq: bool,
+
+    side: Option<usize>, // Side in the drive, `None` while it's empty
+    next_side: Option<usize>,
+    swap_delay: u32,
+    motor_on: bool,
+    scanning: bool,
+    end_of_head: bool,
+    gap_ended: bool,
+    position: usize,
+    delay: u32,
+    crc: u16,
+    previous_crc_control: bool,
+
+    audio: FdsAudio,
+}
+
+impl Mapper020 {
+    /// Takes the raw disk sides, the first is inserted to begin with
+    pub fn new(sides: Vec<Vec<u8>>) -> Self {
+        Self {
+            original_sides: sides.clone(),
+            sides,
+            disk_dirty: false,
+
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+
+            disk_io_enabled: false,
+            sound_io_enabled: false,
+            control: 0,
+            write_data: 0,
+            read_data: 0,
+            external: 0,
+            transfer_complete: false,
+            disk_irq: false,
+
+            side: Some(0),
+            next_side: None,
+            swap_delay: 0,
+            motor_on: false,
+            scanning: false,
+            end_of_head: true,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            crc: 0,
+            previous_crc_control: false,
+
+            audio: FdsAudio::new(),
+        }
+    }
+
+    fn control_set(&self, bit: u8) -> bool {
+        self.control & bit != 0
+    }
+
+    fn read_register(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4030 => {
+                let mut value = 0;
+                value |= if self.timer_irq { 0x01 } else { 0 };
+                value |= if self.transfer_complete { 0x02 } else { 0 };
+                value |= if self.control_set(CRC_CONTROL) && self.crc != 0 { 0x10 } else { 0 };
+                value |= if self.end_of_head { 0x40 } else { 0 };
+
+                self.timer_irq = false;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(value)
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(self.read_data)
+            }
+            0x4032 => {
+                let mut value = 0x40;
+                value |= if self.side.is_none() { 0x05 } else { 0 }; // No disk, so also not writable
+                value |= if self.side.is_none() || !self.scanning { 0x02 } else { 0 };
+                Some(value)
+            }
+            0x4033 => Some(0x80 | (self.external & 0x7F)), // Battery is good
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | ((value as u16) << 8),
+            0x4022 => {
+                self.irq_repeat = value & 0x01 != 0;
+                self.irq_enabled = value & 0x02 != 0 && self.disk_io_enabled;
+
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_io_enabled = value & 0x01 != 0;
+                self.sound_io_enabled = value & 0x02 != 0;
+
+                if !self.disk_io_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_io_enabled => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_io_enabled => {
+                self.control = value;
+                self.motor_on = value & MOTOR_ON != 0;
+                self.disk_irq = false;
+            }
+            0x4026 if self.disk_io_enabled => self.external = value,
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            self.irq_enabled = self.irq_repeat;
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        if self.swap_delay > 0 {
+            self.swap_delay -= 1;
+            if self.swap_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+
+        let Some(side) = self.side.filter(|_| self.motor_on) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+
+        if self.control_set(TRANSFER_RESET) && !self.scanning {
+            return;
+        }
+
+        // The head goes back to the start of the disk, which takes a while to come round
+        if self.end_of_head {
+            self.delay = SPIN_UP_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let transfer_irq = self.control_set(TRANSFER_IRQ);
+        let crc_control = self.control_set(CRC_CONTROL);
+
+        if self.control_set(READ_MODE) {
+            let value = self.sides[side][self.position];
+            if !self.previous_crc_control {
+                self.crc = update_crc(self.crc, value);
+            }
+
+            // Bytes are only handed over once the first non-zero byte after the gap, the block's start mark, has passed
+            let mut raise_irq = transfer_irq;
+            if !self.control_set(TRANSFER_START) {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if value != 0 && !self.gap_ended {
+                self.gap_ended = true;
+                raise_irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = value;
+                self.disk_irq |= raise_irq;
+            }
+        } else {
+            let mut value = 0;
+            if !crc_control {
+                self.transfer_complete = true;
+                value = self.write_data;
+                self.disk_irq |= transfer_irq;
+            }
+
+            if !self.control_set(TRANSFER_START) {
+                value = 0;
+            }
+
+            if !crc_control {
+                self.crc = update_crc(self.crc, value);
+            } else {
+                // The CRC is written out low byte first once the block's data is done
+                if !self.previous_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0), 0);
+                }
+                value = self.crc as u8;
+                self.crc >>= 8;
+            }
+
+            if self.sides[side][self.position] != value {
+                self.sides[side][self.position] = value;
+                self.disk_dirty = true;
+            }
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = crc_control;
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_TRANSFER_CYCLES;
+        }
+    }
+}
+
+impl Mapper for Mapper020 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x4030..=0x4033 if self.disk_io_enabled => self.read_register(address).map_or(MappedRead::None, MappedRead::Data),
+            0x4040..=0x4092 if self.sound_io_enabled => self.audio.read(address).map_or(MappedRead::None, MappedRead::Data),
+            0x6000..=0xDFFF => MappedRead::PrgRam(address - 0x6000),
+            0xE000..=0xFFFF => MappedRead::PrgRom((address - 0xE000) as usize),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x4020..=0x4026 => self.write_register(address, value),
+            0x4040..=0x408A if self.sound_io_enabled => self.audio.write(address, value),
+            0x6000..=0xDFFF => return MappedWrite::PrgRam(address - 0x6000),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        address as usize
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        Some(address as usize)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.control_set(HORIZONTAL_MIRRORING) {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn switch_disk_side(&mut self) -> Option<usize> {
+        let next = match self.next_side.or(self.side) {
+            Some(side) => (side + 1) % self.sides.len(),
+            None => 0,
+        };
+
+        self.side = None;
+        self.next_side = Some(next);
+        self.swap_delay = DISK_SWAP_CYCLES;
+
+        Some(next)
+    }
+
+    fn save_persistent_data(&mut self) -> Option<Vec<u8>> {
+        self.disk_dirty = false;
+        Some(encode_diff(&self.original_sides, &self.sides))
+    }
+
+    fn persistent_data_dirty(&self) -> bool {
+        self.disk_dirty
+    }
+
+    fn restore_persistent_data(&mut self, data: &[u8]) -> Result<()> {
+        let mut sides = self.original_sides.clone();
+        apply_diff(&mut sides, data)?;
+        self.sides = sides;
+        self.disk_dirty = false;
+
+        Ok(())
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&encode_diff(&self.original_sides, &self.sides));
+
+        writer.write_u16(self.irq_reload);
+        writer.write_u16(self.irq_counter);
+        writer.write_bool(self.irq_repeat);
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.timer_irq);
+
+        writer.write_bool(self.disk_io_enabled);
+        writer.write_bool(self.sound_io_enabled);
+        writer.write_u8(self.control);
+        writer.write_u8(self.write_data);
+        writer.write_u8(self.read_data);
+        writer.write_u8(self.external);
+        writer.write_bool(self.transfer_complete);
+        writer.write_bool(self.disk_irq);
+
+        // Sides are stored off by one so that 0 means no disk
+        writer.write_u8(self.side.map_or(0, |side| side as u8 + 1));
+        writer.write_u8(self.next_side.map_or(0, |side| side as u8 + 1));
+        writer.write_u32(self.swap_delay);
+        writer.write_bool(self.motor_on);
+        writer.write_bool(self.scanning);
+        writer.write_bool(self.end_of_head);
+        writer.write_bool(self.gap_ended);
+        writer.write_u32(self.position as u32);
+        writer.write_u32(self.delay);
+        writer.write_u16(self.crc);
+        writer.write_bool(self.previous_crc_control);
+
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let diff = reader.read_bytes()?;
+        self.restore_persistent_data(diff)?;
+        self.disk_dirty = true; // The restored disk differs from what was last saved
+
+        self.irq_reload = reader.read_u16()?;
+        self.irq_counter = reader.read_u16()?;
+        self.irq_repeat = reader.read_bool()?;
+        self.irq_enabled = reader.read_bool()?;
+        self.timer_irq = reader.read_bool()?;
+
+        self.disk_io_enabled = reader.read_bool()?;
+        self.sound_io_enabled = reader.read_bool()?;
+        self.control = reader.read_u8()?;
+        self.write_data = reader.read_u8()?;
+        self.read_data = reader.read_u8()?;
+        self.external = reader.read_u8()?;
+        self.transfer_complete = reader.read_bool()?;
+        self.disk_irq = reader.read_bool()?;
+
+        let side_count = self.sides.len();
+        let read_side = |value: u8| (value as usize).checked_sub(1).filter(|side| *side < side_count);
+        self.side = read_side(reader.read_u8()?);
+        self.next_side = read_side(reader.read_u8()?);
+        self.swap_delay = reader.read_u32()?;
+        self.motor_on = reader.read_bool()?;
+        self.scanning = reader.read_bool()?;
+        self.end_of_head = reader.read_bool()?;
+        self.gap_ended = reader.read_bool()?;
+        self.position = reader.read_u32()? as usize;
+        self.delay = reader.read_u32()?;
+        self.crc = reader.read_u16()?;
+        self.previous_crc_control = reader.read_bool()?;
+
+        if let Some(side) = self.side {
+            self.position = self.position.min(self.sides[side].len() - 1);
+        }
+
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::disk::{SIDE_SIZE, build_raw_side};
+
+    fn test_disk() -> Mapper020 {
+        let mut side = vec![0; SIDE_SIZE];
+        side[..15].copy_from_slice(b"\x01*NINTENDO-HVC*");
+        side[56] = 0x02;
+        Mapper020::new(vec![build_raw_side(&side), build_raw_side(&side)])
+    }
+
+    /// Runs the drive until the IRQ for a transferred byte, allowing for the leading gap. Like the BIOS this relies on
+    /// the start mark not raising an IRQ.
+    fn next_byte(mapper: &mut Mapper020) -> u8 {
+        for _ in 0..SPIN_UP_CYCLES + 4000 * (BYTE_TRANSFER_CYCLES + 1) {
+            mapper.clock_cpu();
+            if mapper.irq_pending() {
+                return read_port(mapper, 0x4031);
+            }
+        }
+        panic!("No byte was transferred");
+    }
+
+    fn read_port(mapper: &mut Mapper020, address: u16) -> u8 {
+        match mapper.cpu_read(address) {
+            MappedRead::Data(value) => value,
+            _ => panic!("${:04X} isn't readable", address),
+        }
+    }
+
+    #[test]
+    fn memory_map() {
+        let mut mapper = test_disk();
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::PrgRam(0x0000)));
+        assert!(matches!(mapper.cpu_write(0xDFFF, 0), MappedWrite::PrgRam(0x7FFF)));
+        assert!(matches!(mapper.cpu_read(0xFFFC), MappedRead::PrgRom(0x1FFC)));
+        assert_eq!(mapper.ppu_write(0x1234, 0), Some(0x1234));
+
+        // Disk registers need enabling through $4023
+        assert!(matches!(mapper.cpu_read(0x4032), MappedRead::None));
+        mapper.cpu_write(0x4023, 0x01);
+        assert_eq!(read_port(&mut mapper, 0x4032), 0x42); // Inserted but not ready
+
+        mapper.cpu_write(0x4025, 0x2E);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn timer_irq() {
+        let mut mapper = test_disk();
+        mapper.cpu_write(0x4023, 0x01);
+        mapper.cpu_write(0x4020, 0x02);
+        mapper.cpu_write(0x4021, 0x00);
+        mapper.cpu_write(0x4022, 0x03); // Enabled, repeating
+
+        for _ in 0..3 {
+            assert!(!mapper.irq_pending());
+            mapper.clock_cpu();
+        }
+        assert!(mapper.irq_pending());
+
+        assert_eq!(read_port(&mut mapper, 0x4030) & 0x01, 0x01);
+        assert!(!mapper.irq_pending());
+
+        for _ in 0..3 {
+            mapper.clock_cpu();
+        }
+        assert!(mapper.irq_pending());
+    }
+
+    #[test]
+    fn reads_block_after_gap() {
+        let mut mapper = test_disk();
+        mapper.cpu_write(0x4023, 0x01);
+        mapper.cpu_write(0x4025, 0xE5); // Motor on, read mode, transfer start, IRQ on transfer
+
+        assert_eq!(next_byte(&mut mapper), 0x01); // The start mark is swallowed
+        assert_eq!(next_byte(&mut mapper), b'*');
+        assert_eq!(read_port(&mut mapper, 0x4032) & 0x03, 0x00);
+
+        mapper.clock_cpu();
+        for _ in 0..BYTE_TRANSFER_CYCLES {
+            mapper.clock_cpu();
+        }
+        assert!(mapper.irq_pending());
+        assert_eq!(read_port(&mut mapper, 0x4031), b'N');
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn block_crc_checks_out() {
+        let mut mapper = test_disk();
+        mapper.cpu_write(0x4023, 0x01);
+        mapper.cpu_write(0x4025, 0xE5);
+
+        // 56 bytes of disk info and the 2 CRC bytes
+        for _ in 0..58 {
+            next_byte(&mut mapper);
+        }
+
+        mapper.cpu_write(0x4025, 0xF5);
+        assert_eq!(read_port(&mut mapper, 0x4030) & 0x10, 0x00);
+    }
+
+    #[test]
+    fn writes_are_saved_as_diff() {
+        let mut mapper = test_disk();
+        mapper.cpu_write(0x4023, 0x01);
+        mapper.cpu_write(0x4024, 0x5A);
+        mapper.cpu_write(0x4025, 0x61); // Motor on, write mode, transfer start
+
+        for _ in 0..SPIN_UP_CYCLES + 2 {
+            mapper.clock_cpu();
+        }
+        assert!(mapper.persistent_data_dirty());
+        assert_eq!(mapper.sides[0][0], 0x5A);
+
+        let diff = mapper.save_persistent_data().unwrap();
+        assert!(!mapper.persistent_data_dirty());
+
+        let mut restored = test_disk();
+        restored.restore_persistent_data(&diff).unwrap();
+        assert_eq!(restored.sides, mapper.sides);
+    }
+
+    #[test]
+    fn switching_sides_ejects_first() {
+        let mut mapper = test_disk();
+        mapper.cpu_write(0x4023, 0x01);
+
+        assert_eq!(mapper.switch_disk_side(), Some(1));
+        assert_eq!(read_port(&mut mapper, 0x4032) & 0x01, 0x01);
+
+        for _ in 0..DISK_SWAP_CYCLES {
+            mapper.clock_cpu();
+        }
+        assert_eq!(mapper.side, Some(1));
+        assert_eq!(read_port(&mut mapper, 0x4032) & 0x01, 0x00);
+    }
+}
@@ -0,0 +1,403 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+
+/// The IRQ counter found on VRC4, VRC6 and VRC7. An 8-bit counter counts up to $FF and reloads from the latch,
+/// clocked either every CPU cycle or roughly once per scanline by a prescaler that divides CPU cycles by 113⅔.
+pub(super) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enable_after_ack: bool,
+    enabled: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enable_after_ack: false,
+            enabled: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xF0) | (value & 0x0F);
+    }
+
+    pub fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0F) | ((value & 0x0F) << 4);
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 0x01 != 0;
+        self.enabled = value & 0x02 != 0;
+        self.cycle_mode = value & 0x04 != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Called once per CPU cycle. In scanline mode the prescaler drops by 3 each cycle and clocks the counter every
+    /// time it passes 0, which works out at one clock per 341 PPU dots.
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.latch);
+        writer.write_u8(self.counter);
+        writer.write_u16(self.prescaler as u16);
+        writer.write_bool(self.enable_after_ack);
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.cycle_mode);
+        writer.write_bool(self.pending);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.latch = reader.read_u8()?;
+        self.counter = reader.read_u8()?;
+        self.prescaler = reader.read_u16()? as i16;
+        self.enable_after_ack = reader.read_bool()?;
+        self.enabled = reader.read_bool()?;
+        self.cycle_mode = reader.read_bool()?;
+        self.pending = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+/// Konami VRC2 and VRC4, covering mappers 21, 22, 23 and 25. The boards differ in which CPU address lines are wired to
+/// the chip's register select pins A0 and A1, the NES 2.0 submapper picks one wiring and without it both wirings a
+/// mapper number was used for are decoded at once.
+pub struct Mapper021 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+
+    vrc4: bool,
+    a0_lines: u16, // CPU address lines connected to the chip's A0
+    a1_lines: u16, // CPU address lines connected to the chip's A1
+    chr_shift: u8, // VRC2a ignores the low bit of CHR bank numbers
+
+    prg_banks_select: [u8; 2],
+    prg_swap: bool, // VRC4 only: $8000 becomes fixed to the second last bank and $C000 switchable
+    mirroring: u8,
+    chr_banks_select: [u16; 8],
+    irq: VrcIrq,
+}
+
+impl Mapper021 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        let (vrc4, a0_lines, a1_lines, chr_shift) = match (info.mapper, info.submapper) {
+            (21, 1) => (true, 0x02, 0x04, 0), // VRC4a
+            (21, 2) => (true, 0x40, 0x80, 0), // VRC4c
+            (21, _) => (true, 0x42, 0x84, 0),
+            (22, _) => (false, 0x02, 0x01, 1), // VRC2a
+            (23, 1) => (true, 0x01, 0x02, 0),  // VRC4f
+            (23, 2) => (true, 0x04, 0x08, 0),  // VRC4e
+            (23, 3) => (false, 0x01, 0x02, 0), // VRC2b
+            (23, _) => (true, 0x05, 0x0A, 0),
+            (25, 1) => (true, 0x02, 0x01, 0),  // VRC4b
+            (25, 2) => (true, 0x08, 0x04, 0),  // VRC4d
+            (25, 3) => (false, 0x02, 0x01, 0), // VRC2c
+            (_, _) => (true, 0x0A, 0x05, 0),
+        };
+
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+
+            vrc4,
+            a0_lines,
+            a1_lines,
+            chr_shift,
+
+            prg_banks_select: [0, 0],
+            prg_swap: false,
+            mirroring: 0,
+            chr_banks_select: [0; 8],
+            irq: VrcIrq::new(),
+        }
+    }
+
+    /// Reduces a CPU address to the chip's view of it, $x000-$x003
+    fn register(&self, address: u16) -> u16 {
+        let a0 = (address & self.a0_lines != 0) as u16;
+        let a1 = (address & self.a1_lines != 0) as u16;
+        (address & 0xF000) | (a1 << 1) | a0
+    }
+
+    fn prg_bank_for(&self, address: u16) -> usize {
+        let second_last = self.prg_banks.saturating_sub(2);
+
+        let bank = match (address, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks_select[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks_select[1] as usize,
+            _ => self.prg_banks.saturating_sub(1),
+        };
+
+        bank % self.prg_banks.max(1)
+    }
+
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let index = (((register - 0xB000) >> 12) * 2 + ((register >> 1) & 0x01)) as usize;
+        let bank = &mut self.chr_banks_select[index];
+
+        if register & 0x01 == 0 {
+            *bank = (*bank & 0x1F0) | (value & 0x0F) as u16;
+        } else {
+            let high_mask = if self.vrc4 { 0x1F } else { 0x0F };
+            *bank = (*bank & 0x0F) | (((value & high_mask) as u16) << 4);
+        }
+    }
+}
+
+impl Mapper for Mapper021 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x6000..=0x7FFF => MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_bank_for(address) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x6000..=0x7FFF = address {
+            return MappedWrite::PrgRam(address - 0x6000);
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_banks_select[0] = value & 0x1F,
+            0x9000..=0x9001 => self.mirroring = if self.vrc4 { value & 0x03 } else { value & 0x01 },
+            0x9002..=0x9003 if self.vrc4 => self.prg_swap = value & 0x02 != 0,
+            0xA000..=0xA003 => self.prg_banks_select[1] = value & 0x1F,
+            register @ 0xB000..=0xEFFF => self.write_chr_bank(register, value),
+            0xF000 if self.vrc4 => self.irq.write_latch_low(value),
+            0xF001 if self.vrc4 => self.irq.write_latch_high(value),
+            0xF002 if self.vrc4 => self.irq.write_control(value),
+            0xF003 if self.vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = (self.chr_banks_select[(address as usize >> 10) & 0x07] >> self.chr_shift) as usize;
+        (bank % self.chr_banks.max(1)) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_banks_select[0]);
+        writer.write_u8(self.prg_banks_select[1]);
+        writer.write_bool(self.prg_swap);
+        writer.write_u8(self.mirroring);
+        for bank in &self.chr_banks_select {
+            writer.write_u16(*bank);
+        }
+        self.irq.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_banks_select[0] = reader.read_u8()?;
+        self.prg_banks_select[1] = reader.read_u8()?;
+        self.prg_swap = reader.read_bool()?;
+        self.mirroring = reader.read_u8()?;
+        for bank in &mut self.chr_banks_select {
+            *bank = reader.read_u16()?;
+        }
+        self.irq.load_state(reader)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    fn with_submapper(mapper: u16, submapper: u8) -> Mapper021 {
+        let mut info = test_info(mapper, 262144, 262144);
+        info.submapper = submapper;
+        Mapper021::new(&info)
+    }
+
+    #[test]
+    fn decodes_register_lines_per_board() {
+        // CHR bank 1 low nibble is the chip's $B002
+        for (mapper, submapper, address) in [
+            (21, 1, 0xB004),
+            (21, 2, 0xB080),
+            (23, 1, 0xB002),
+            (23, 2, 0xB008),
+            (25, 1, 0xB001),
+            (25, 2, 0xB004),
+        ] {
+            let mut vrc = with_submapper(mapper, submapper);
+            vrc.cpu_write(address, 0x05);
+            assert_eq!(vrc.chr_banks_select[1], 0x05, "mapper {} submapper {}", mapper, submapper);
+        }
+    }
+
+    #[test]
+    fn prg_banking_and_swap_mode() {
+        let mut vrc = with_submapper(23, 1);
+        vrc.cpu_write(0x8000, 0x04);
+        vrc.cpu_write(0xA000, 0x05);
+
+        assert!(matches!(vrc.cpu_read(0x8000), MappedRead::PrgRom(0x8000)));
+        assert!(matches!(vrc.cpu_read(0xA000), MappedRead::PrgRom(0xA000)));
+        assert!(matches!(vrc.cpu_read(0xC000), MappedRead::PrgRom(0x3C000)));
+        assert!(matches!(vrc.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+
+        vrc.cpu_write(0x9002, 0x02);
+        assert!(matches!(vrc.cpu_read(0x8000), MappedRead::PrgRom(0x3C000)));
+        assert!(matches!(vrc.cpu_read(0xC000), MappedRead::PrgRom(0x8000)));
+    }
+
+    #[test]
+    fn chr_banks_combine_nibbles() {
+        let mut vrc = with_submapper(23, 1);
+        vrc.cpu_write(0xE002, 0x03);
+        vrc.cpu_write(0xE003, 0x01);
+        assert_eq!(vrc.ppu_read(0x1C10), 0x13 * 1024 + 0x10);
+
+        // VRC2a drops the low bit of the bank number
+        let mut vrc = with_submapper(22, 0);
+        vrc.cpu_write(0xB000, 0x05);
+        assert_eq!(vrc.ppu_read(0x0000), 2 * 1024);
+    }
+
+    #[test]
+    fn vrc4_mirroring_includes_single_screen() {
+        let mut vrc = with_submapper(21, 1);
+        vrc.cpu_write(0x9000, 0x03);
+        assert_eq!(vrc.mirroring(), Mirroring::SingleScreenUpper);
+
+        let mut vrc = with_submapper(23, 3);
+        vrc.cpu_write(0x9000, 0x03);
+        assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn cycle_mode_irq() {
+        let mut vrc = with_submapper(23, 1);
+        vrc.cpu_write(0xF000, 0x0C);
+        vrc.cpu_write(0xF001, 0x0F); // Latch $FC
+        vrc.cpu_write(0xF002, 0x07); // Enabled in cycle mode, re-enabled on acknowledge
+
+        for _ in 0..3 {
+            vrc.clock_cpu();
+        }
+        assert!(!vrc.irq_pending());
+
+        vrc.clock_cpu();
+        assert!(vrc.irq_pending());
+
+        vrc.cpu_write(0xF003, 0x00);
+        assert!(!vrc.irq_pending());
+
+        for _ in 0..4 {
+            vrc.clock_cpu();
+        }
+        assert!(vrc.irq_pending());
+    }
+
+    #[test]
+    fn scanline_mode_irq_uses_prescaler() {
+        let mut vrc = with_submapper(23, 1);
+        vrc.cpu_write(0xF000, 0x0F);
+        vrc.cpu_write(0xF001, 0x0F);
+        vrc.cpu_write(0xF002, 0x02); // Enabled in scanline mode with the counter at $FF
+
+        for _ in 0..113 {
+            vrc.clock_cpu();
+        }
+        assert!(!vrc.irq_pending());
+
+        vrc.clock_cpu();
+        assert!(vrc.irq_pending());
+    }
+
+    #[test]
+    fn vrc2_has_no_irq() {
+        let mut vrc = with_submapper(22, 0);
+        vrc.cpu_write(0xF000, 0x0F);
+        vrc.cpu_write(0xF001, 0x0F);
+        vrc.cpu_write(0xF002, 0x06);
+
+        vrc.clock_cpu();
+        assert!(!vrc.irq_pending());
+    }
+}
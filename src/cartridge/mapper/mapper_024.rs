@@ -0,0 +1,238 @@
+use super::mapper_021::VrcIrq;
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, Vrc6Audio};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+
+/// Konami VRC6, covering mapper 24 (VRC6a) and mapper 26 (VRC6b) which has the chip's A0 and A1 lines swapped.
+/// Nametables always come from CIRAM, the CHR-ROM nametable modes of $B003 bit 4 aren't used by any released game.
+pub struct Mapper024 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    swap_lines: bool,
+
+    prg_bank_16k: u8, // $8000-$BFFF
+    prg_bank_8k: u8,  // $C000-$DFFF
+    banking_mode: u8, // $B003: CHR mode (bits 0-1), A10 from PPU (bit 5), mirroring (bits 2-3), PRG-RAM enable (bit 7)
+    chr_banks_select: [u8; 8],
+    irq: VrcIrq,
+
+    audio: Vrc6Audio,
+}
+
+impl Mapper024 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            swap_lines: info.mapper == 26,
+
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            banking_mode: 0,
+            chr_banks_select: [0; 8],
+            irq: VrcIrq::new(),
+
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    /// Reduces a CPU address to the chip's view of it, $x000-$x003
+    fn register(&self, address: u16) -> u16 {
+        let lines = address & 0x03;
+        let lines = if self.swap_lines { ((lines & 0x01) << 1) | (lines >> 1) } else { lines };
+        (address & 0xF000) | lines
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking_mode & 0x80 != 0
+    }
+
+    /// The 1KB CHR bank for one of the eight 1KB slots of the pattern tables. Outside of mode 0 some registers select
+    /// 2KB banks, where the low bit comes from either the register or PPU A10.
+    fn chr_bank_for(&self, slot: usize) -> usize {
+        let two_kb = |register: u8| {
+            if self.banking_mode & 0x20 != 0 {
+                (register & 0xFE) | (slot as u8 & 0x01)
+            } else {
+                register
+            }
+        };
+
+        let bank = match (self.banking_mode & 0x03, slot) {
+            (0, _) => self.chr_banks_select[slot],
+            (1, _) => two_kb(self.chr_banks_select[slot >> 1]),
+            (_, 0..=3) => self.chr_banks_select[slot],
+            (_, _) => two_kb(self.chr_banks_select[4 + ((slot - 4) >> 1)]),
+        };
+
+        bank as usize % self.chr_banks.max(1)
+    }
+}
+
+impl Mapper for Mapper024 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        let bank = match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => return MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xBFFF => (self.prg_bank_16k as usize * 2) | ((address as usize >> 13) & 0x01),
+            0xC000..=0xDFFF => self.prg_bank_8k as usize,
+            0xE000..=0xFFFF => self.prg_banks.saturating_sub(1),
+            _ => return MappedRead::None,
+        };
+
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x6000..=0x7FFF = address {
+            return if self.prg_ram_enabled() {
+                MappedWrite::PrgRam(address - 0x6000)
+            } else {
+                MappedWrite::None
+            };
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_bank_16k = value & 0x0F,
+            register @ (0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002) => self.audio.write(register, value),
+            0xB003 => self.banking_mode = value,
+            0xC000..=0xC003 => self.prg_bank_8k = value & 0x1F,
+            register @ (0xD000..=0xD003 | 0xE000..=0xE003) => {
+                let index = (((register >> 12) - 0xD) * 4 + (register & 0x03)) as usize;
+                self.chr_banks_select[index] = value;
+            }
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = self.chr_bank_for((address as usize >> 10) & 0x07);
+        bank * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_mode >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self, volumes: &ChannelVolumes) -> f32 {
+        self.audio.output(volumes)
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_bank_16k);
+        writer.write_u8(self.prg_bank_8k);
+        writer.write_u8(self.banking_mode);
+        writer.write_bytes(&self.chr_banks_select);
+        self.irq.save_state(writer);
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_bank_16k = reader.read_u8()?;
+        self.prg_bank_8k = reader.read_u8()?;
+        self.banking_mode = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.chr_banks_select)?;
+        self.irq.load_state(reader)?;
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = Mapper024::new(&test_info(24, 262144, 262144));
+        mapper.cpu_write(0x8000, 0x03);
+        mapper.cpu_write(0xC000, 0x05);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0xC000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0xE000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0xA000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+    }
+
+    #[test]
+    fn mapper_26_swaps_address_lines() {
+        let mut mapper = Mapper024::new(&test_info(26, 262144, 262144));
+        mapper.cpu_write(0xD001, 0x11); // Chip register $D002
+        mapper.cpu_write(0xB003, 0x8C);
+
+        assert_eq!(mapper.ppu_read(0x0800), 0x11 * 1024);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::PrgRam(0x0000)));
+    }
+
+    #[test]
+    fn chr_banking_modes() {
+        let mut mapper = Mapper024::new(&test_info(24, 262144, 262144));
+        for (i, address) in [0xD000, 0xD001, 0xD002, 0xD003, 0xE000, 0xE001, 0xE002, 0xE003].into_iter().enumerate() {
+            mapper.cpu_write(address, 0x10 + i as u8);
+        }
+
+        assert_eq!(mapper.ppu_read(0x1C00), 0x17 * 1024);
+
+        // 2KB banks from R0-R3 with A10 from the PPU
+        mapper.cpu_write(0xB003, 0x21);
+        assert_eq!(mapper.ppu_read(0x0000), 0x10 * 1024);
+        assert_eq!(mapper.ppu_read(0x0400), 0x11 * 1024);
+        assert_eq!(mapper.ppu_read(0x1C00), 0x13 * 1024);
+
+        // 1KB banks below $1000 and 2KB banks from R4-R5 above
+        mapper.cpu_write(0xB003, 0x22);
+        assert_eq!(mapper.ppu_read(0x0C00), 0x13 * 1024);
+        assert_eq!(mapper.ppu_read(0x1400), 0x15 * 1024);
+        assert_eq!(mapper.ppu_read(0x1800), 0x14 * 1024);
+    }
+
+    #[test]
+    fn irq_uses_full_latch() {
+        let mut mapper = Mapper024::new(&test_info(24, 262144, 262144));
+        mapper.cpu_write(0xF000, 0xFE);
+        mapper.cpu_write(0xF001, 0x06);
+
+        mapper.clock_cpu();
+        assert!(!mapper.irq_pending());
+
+        mapper.clock_cpu();
+        assert!(mapper.irq_pending());
+
+        mapper.cpu_write(0xF002, 0x00);
+        assert!(!mapper.irq_pending());
+    }
+}
@@ -0,0 +1,171 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 32768; // 32KB
+const CHR_BANK_SIZE: usize = 4096; // 4KB
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Board {
+    Bnrom,   // 32KB PRG bank selected by writes to $8000-$FFFF, CHR-RAM
+    Nina001, // 32KB PRG bank and two 4KB CHR banks selected by writes to $7FFD-$7FFF, with PRG-RAM
+}
+
+/// Mapper 34 covers two unrelated boards. NES 2.0 submapper 1 is NINA-001 and 2 is BNROM, otherwise the board is
+/// guessed from the amount of CHR-ROM as BNROM only ever shipped with CHR-RAM.
+pub struct Mapper034 {
+    board: Board,
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+
+    prg_bank: u8,
+    chr_bank_0: u8, // NINA-001 $7FFE, 4KB at $0000
+    chr_bank_1: u8, // NINA-001 $7FFF, 4KB at $1000
+}
+
+impl Mapper034 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        let board = match info.submapper {
+            1 => Board::Nina001,
+            2 => Board::Bnrom,
+            _ if info.chr_rom_size > 8192 => Board::Nina001,
+            _ => Board::Bnrom,
+        };
+
+        Self {
+            board,
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            mirroring: info.mirroring,
+
+            prg_bank: 0,
+            chr_bank_0: 0,
+            chr_bank_1: 1,
+        }
+    }
+}
+
+impl Mapper for Mapper034 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x6000..=0x7FFF if self.board == Board::Nina001 => MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize % self.prg_banks.max(1);
+                MappedRead::PrgRom(bank * PRG_BANK_SIZE + (address - 0x8000) as usize)
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match (self.board, address) {
+            (Board::Bnrom, 0x8000..=0xFFFF) => {
+                self.prg_bank = value;
+                MappedWrite::None
+            }
+            (Board::Nina001, 0x6000..=0x7FFF) => {
+                // The registers sit on top of PRG-RAM, writes to them land in both
+                match address {
+                    0x7FFD => self.prg_bank = value & 0x01,
+                    0x7FFE => self.chr_bank_0 = value & 0x0F,
+                    0x7FFF => self.chr_bank_1 = value & 0x0F,
+                    _ => {}
+                }
+
+                MappedWrite::PrgRam(address - 0x6000)
+            }
+            _ => MappedWrite::None,
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        match self.board {
+            Board::Bnrom => address as usize,
+            Board::Nina001 => {
+                let bank = if address < 0x1000 { self.chr_bank_0 } else { self.chr_bank_1 };
+                (bank as usize % self.chr_banks.max(1)) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+            }
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        self.board == Board::Bnrom
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prg_bank);
+        writer.write_u8(self.chr_bank_0);
+        writer.write_u8(self.chr_bank_1);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.prg_bank = reader.read_u8()?;
+        self.chr_bank_0 = reader.read_u8()?;
+        self.chr_bank_1 = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn bnrom_selects_32kb_prg_bank() {
+        let mut mapper = Mapper034::new(&test_info(34, 131072, 0));
+        assert_eq!(mapper.board, Board::Bnrom);
+
+        mapper.cpu_write(0x8000, 0x02);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x10000)));
+        assert!(matches!(mapper.cpu_read(0xFFFF), MappedRead::PrgRom(0x17FFF)));
+
+        // BNROM has no PRG-RAM, so NINA-001's registers do nothing
+        assert!(matches!(mapper.cpu_write(0x7FFD, 0x01), MappedWrite::None));
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x10000)));
+        assert_eq!(mapper.ppu_write(0x1000, 0), Some(0x1000));
+    }
+
+    #[test]
+    fn nina001_selects_prg_and_chr_banks() {
+        let mut mapper = Mapper034::new(&test_info(34, 65536, 65536));
+        assert_eq!(mapper.board, Board::Nina001);
+        assert_eq!(mapper.ppu_read(0x1000), 0x1000);
+
+        assert!(matches!(mapper.cpu_write(0x7FFD, 0x01), MappedWrite::PrgRam(0x1FFD)));
+        mapper.cpu_write(0x7FFE, 0x03);
+        mapper.cpu_write(0x7FFF, 0x0A);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x8000)));
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::PrgRam(0x0000)));
+        assert_eq!(mapper.ppu_read(0x0010), 3 * 4096 + 0x0010);
+        assert_eq!(mapper.ppu_read(0x1010), 10 * 4096 + 0x0010);
+
+        // Writes to $8000-$FFFF are ignored on NINA-001
+        mapper.cpu_write(0x8000, 0x00);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x8000)));
+    }
+
+    #[test]
+    fn submapper_overrides_detection() {
+        let mut info = test_info(34, 65536, 8192);
+        info.submapper = 1;
+        assert_eq!(Mapper034::new(&info).board, Board::Nina001);
+
+        info.chr_rom_size = 65536;
+        info.submapper = 2;
+        assert_eq!(Mapper034::new(&info).board, Board::Bnrom);
+    }
+}
@@ -0,0 +1,105 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::cartridge::CHR_ROM_BANK_SIZE;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 32768; // 32KB
+
+/// GxROM: a switchable 32KB PRG bank and 8KB CHR bank, both selected by writes to $8000-$FFFF
+pub struct Mapper066 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bank_select: u8, // CHR bank (bits 0-1) and PRG bank (bits 4-5)
+}
+
+impl Mapper066 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_ROM_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            mirroring: info.mirroring,
+            bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper066 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = ((self.bank_select >> 4) & 0x03) as usize % self.prg_banks.max(1);
+                MappedRead::PrgRom(bank * PRG_BANK_SIZE + (address - 0x8000) as usize)
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x8000..=0xFFFF = address {
+            self.bank_select = value;
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = (self.bank_select & 0x03) as usize % self.chr_banks.max(1);
+        bank * CHR_ROM_BANK_SIZE + (address as usize & (CHR_ROM_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.bank_select = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    #[test]
+    fn selects_prg_and_chr_banks() {
+        let mut mapper = Mapper066::new(&test_info(66, 131072, 32768));
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0000)));
+        assert_eq!(mapper.ppu_read(0x0000), 0x0000);
+
+        mapper.cpu_write(0x8000, 0x21);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x10000)));
+        assert!(matches!(mapper.cpu_read(0xFFFF), MappedRead::PrgRom(0x17FFF)));
+        assert_eq!(mapper.ppu_read(0x0123), 8192 + 0x0123);
+
+        mapper.cpu_write(0xFFFF, 0x33);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x18000)));
+        assert_eq!(mapper.ppu_read(0x1FFF), 4 * 8192 - 1);
+    }
+
+    #[test]
+    fn ignores_writes_below_8000() {
+        let mut mapper = Mapper066::new(&test_info(66, 65536, 16384));
+        mapper.cpu_write(0x6000, 0x11);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x0000)));
+        assert_eq!(mapper.ppu_read(0x0000), 0x0000);
+    }
+}
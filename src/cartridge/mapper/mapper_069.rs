@@ -0,0 +1,264 @@
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, Sunsoft5b};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+
+/// Sunsoft FME-7 and the 5B, which is an FME-7 with built-in audio. Registers are written by selecting a command
+/// through $8000-$9FFF and then writing its parameter to $A000-$BFFF.
+pub struct Mapper069 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    prg_ram_banks: usize,
+
+    command: u8,
+    chr_banks_select: [u8; 8], // Commands $0-$7, 1KB each
+    prg_bank_6000: u8,         // Command $8: bank (bits 0-5), RAM instead of ROM (bit 6) and RAM enable (bit 7)
+    prg_banks_select: [u8; 3], // Commands $9-$B, $8000/$A000/$C000
+    mirroring: u8,             // Command $C
+
+    irq_enabled: bool,         // Command $D bit 0
+    irq_counter_enabled: bool, // Command $D bit 7
+    irq_counter: u16,          // Commands $E-$F
+    irq_pending: bool,
+
+    audio: Sunsoft5b,
+}
+
+impl Mapper069 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            prg_ram_banks: info.total_prg_ram_size() / PRG_BANK_SIZE,
+
+            command: 0,
+            chr_banks_select: [0; 8],
+            prg_bank_6000: 0,
+            prg_banks_select: [0; 3],
+            mirroring: 0,
+
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks_select[self.command as usize] = value,
+            0x8 => self.prg_bank_6000 = value,
+            0x9..=0xB => self.prg_banks_select[(self.command - 0x9) as usize] = value & 0x3F,
+            0xC => self.mirroring = value & 0x03,
+            0xD => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.irq_counter_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            0xF => self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16) << 8),
+            _ => unreachable!(),
+        }
+    }
+
+    fn prg_rom(&self, bank: usize, address: u16) -> MappedRead {
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+    }
+
+    /// Where $6000-$7FFF goes, `None` when RAM is selected but disabled (open bus)
+    fn prg_ram_offset(&self, address: u16) -> Option<u16> {
+        if self.prg_bank_6000 & 0xC0 != 0xC0 {
+            return None;
+        }
+
+        let bank = (self.prg_bank_6000 & 0x3F) as usize % self.prg_ram_banks.max(1);
+        Some((bank * PRG_BANK_SIZE) as u16 + (address - 0x6000))
+    }
+}
+
+impl Mapper for Mapper069 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        match address {
+            0x6000..=0x7FFF if self.prg_bank_6000 & 0x40 == 0 => self.prg_rom((self.prg_bank_6000 & 0x3F) as usize, address),
+            0x6000..=0x7FFF => self.prg_ram_offset(address).map_or(MappedRead::None, MappedRead::PrgRam),
+            0x8000..=0xDFFF => self.prg_rom(self.prg_banks_select[((address - 0x8000) / 0x2000) as usize] as usize, address),
+            0xE000..=0xFFFF => self.prg_rom(self.prg_banks.saturating_sub(1), address),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        match address {
+            0x6000..=0x7FFF => return self.prg_ram_offset(address).map_or(MappedWrite::None, MappedWrite::PrgRam),
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.write_address(value),
+            0xE000..=0xFFFF => self.audio.write_data(value),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = self.chr_banks_select[(address as usize >> 10) & 0x07] as usize;
+        (bank % self.chr_banks.max(1)) * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    /// The IRQ counter decrements every CPU cycle and fires when it wraps from $0000 to $FFFF
+    fn clock_cpu(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.command);
+        writer.write_bytes(&self.chr_banks_select);
+        writer.write_u8(self.prg_bank_6000);
+        writer.write_bytes(&self.prg_banks_select);
+        writer.write_u8(self.mirroring);
+
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_counter_enabled);
+        writer.write_u16(self.irq_counter);
+        writer.write_bool(self.irq_pending);
+
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.command = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.chr_banks_select)?;
+        self.prg_bank_6000 = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.prg_banks_select)?;
+        self.mirroring = reader.read_u8()?;
+
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_counter_enabled = reader.read_bool()?;
+        self.irq_counter = reader.read_u16()?;
+        self.irq_pending = reader.read_bool()?;
+
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    fn command(mapper: &mut Mapper069, command: u8, parameter: u8) {
+        mapper.cpu_write(0x8000, command);
+        mapper.cpu_write(0xA000, parameter);
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = Mapper069::new(&test_info(69, 262144, 262144));
+        command(&mut mapper, 0x9, 0x01);
+        command(&mut mapper, 0xA, 0x02);
+        command(&mut mapper, 0xB, 0x43); // Upper bits ignored
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x2000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x4000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x6000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+    }
+
+    #[test]
+    fn rom_or_ram_at_6000() {
+        let mut mapper = Mapper069::new(&test_info(69, 262144, 262144));
+
+        command(&mut mapper, 0x8, 0x05);
+        assert!(matches!(mapper.cpu_read(0x6010), MappedRead::PrgRom(0xA010)));
+        assert!(matches!(mapper.cpu_write(0x6010, 0), MappedWrite::None));
+
+        command(&mut mapper, 0x8, 0x40); // RAM selected but disabled
+        assert!(matches!(mapper.cpu_read(0x6010), MappedRead::None));
+
+        command(&mut mapper, 0x8, 0xC0);
+        assert!(matches!(mapper.cpu_read(0x6010), MappedRead::PrgRam(0x0010)));
+        assert!(matches!(mapper.cpu_write(0x6010, 0), MappedWrite::PrgRam(0x0010)));
+    }
+
+    #[test]
+    fn chr_banking_and_mirroring() {
+        let mut mapper = Mapper069::new(&test_info(69, 262144, 262144));
+        command(&mut mapper, 0x5, 0x21);
+        command(&mut mapper, 0xC, 0x03);
+
+        assert_eq!(mapper.ppu_read(0x1410), 0x21 * 1024 + 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn irq_fires_when_counter_wraps() {
+        let mut mapper = Mapper069::new(&test_info(69, 262144, 262144));
+        command(&mut mapper, 0xE, 0x02);
+        command(&mut mapper, 0xF, 0x00);
+        command(&mut mapper, 0xD, 0x81);
+
+        for _ in 0..2 {
+            mapper.clock_cpu();
+        }
+        assert!(!mapper.irq_pending());
+
+        mapper.clock_cpu();
+        assert!(mapper.irq_pending());
+
+        command(&mut mapper, 0xD, 0x81);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn audio_registers_are_mapped() {
+        let mut mapper = Mapper069::new(&test_info(69, 262144, 262144));
+        let volumes = ChannelVolumes::default();
+        assert_eq!(mapper.audio_output(&volumes), 0.0);
+
+        mapper.cpu_write(0xC000, 0x07);
+        mapper.cpu_write(0xE000, 0x3F);
+        mapper.cpu_write(0xC000, 0x08);
+        mapper.cpu_write(0xE000, 0x0F);
+        assert!(mapper.audio_output(&volumes) > 0.0);
+    }
+}
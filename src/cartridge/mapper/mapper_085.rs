@@ -0,0 +1,216 @@
+use super::mapper_021::VrcIrq;
+use super::{CartridgeInfo, MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, Vrc7Audio};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const PRG_BANK_SIZE: usize = 8192; // 8KB
+const CHR_BANK_SIZE: usize = 1024; // 1KB
+
+/// Konami VRC7. The second register of each pair is selected by A4 on VRC7a (Lagrange Point) and A3 on VRC7b (Tiny
+/// Toon Adventures 2), NES 2.0 submappers 2 and 1. Without a submapper either line selects it.
+pub struct Mapper085 {
+    prg_banks: usize,
+    chr_banks: usize,
+    chr_is_ram: bool,
+    register_lines: u16,
+
+    prg_banks_select: [u8; 3], // $8000, $8010 and $9000
+    chr_banks_select: [u8; 8], // $A000-$D010
+    control: u8,               // $E000: mirroring (bits 0-1), audio reset (bit 6), PRG-RAM enable (bit 7)
+    irq: VrcIrq,
+
+    audio: Vrc7Audio,
+}
+
+impl Mapper085 {
+    pub fn new(info: &CartridgeInfo) -> Self {
+        Self {
+            prg_banks: info.prg_rom_size / PRG_BANK_SIZE,
+            chr_banks: info.chr_size() / CHR_BANK_SIZE,
+            chr_is_ram: info.chr_rom_size == 0,
+            register_lines: match info.submapper {
+                1 => 0x08,
+                2 => 0x10,
+                _ => 0x18,
+            },
+
+            prg_banks_select: [0; 3],
+            chr_banks_select: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+
+            audio: Vrc7Audio::new(),
+        }
+    }
+
+    /// Reduces a CPU address to $x000 or $x010
+    fn register(&self, address: u16) -> u16 {
+        (address & 0xF000) | if address & self.register_lines != 0 { 0x10 } else { 0 }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+}
+
+impl Mapper for Mapper085 {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        let bank = match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => return MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xDFFF => self.prg_banks_select[((address - 0x8000) / 0x2000) as usize] as usize,
+            0xE000..=0xFFFF => self.prg_banks.saturating_sub(1),
+            _ => return MappedRead::None,
+        };
+
+        MappedRead::PrgRom((bank % self.prg_banks.max(1)) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1)))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        if let 0x6000..=0x7FFF = address {
+            return if self.prg_ram_enabled() {
+                MappedWrite::PrgRam(address - 0x6000)
+            } else {
+                MappedWrite::None
+            };
+        }
+
+        // The audio ports only exist on VRC7a and also decode A5
+        match (address & 0xF030, self.register(address)) {
+            (0x9010, _) => self.audio.select_register(value),
+            (0x9030, _) => self.audio.write_data(value),
+            (_, 0x8000) => self.prg_banks_select[0] = value & 0x3F,
+            (_, 0x8010) => self.prg_banks_select[1] = value & 0x3F,
+            (_, 0x9000) => self.prg_banks_select[2] = value & 0x3F,
+            (_, register @ 0xA000..=0xD010) => {
+                let index = (((register - 0xA000) >> 12) * 2 + ((register >> 4) & 0x01)) as usize;
+                self.chr_banks_select[index] = value;
+            }
+            (_, 0xE000) => {
+                self.control = value;
+                self.audio.set_reset(value & 0x40 != 0);
+            }
+            (_, 0xE010) => self.irq.write_latch(value),
+            (_, 0xF000) => self.irq.write_control(value),
+            (_, 0xF010) => self.irq.acknowledge(),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        let bank = self.chr_banks_select[(address as usize >> 10) & 0x07] as usize % self.chr_banks.max(1);
+        bank * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        if self.chr_is_ram { Some(self.ppu_read(address)) } else { None }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.audio.channels()
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_banks_select);
+        writer.write_bytes(&self.chr_banks_select);
+        writer.write_u8(self.control);
+        self.irq.save_state(writer);
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.prg_banks_select)?;
+        reader.read_bytes_into(&mut self.chr_banks_select)?;
+        self.control = reader.read_u8()?;
+        self.irq.load_state(reader)?;
+        self.audio.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_info;
+
+    fn with_submapper(submapper: u8) -> Mapper085 {
+        let mut info = test_info(85, 262144, 131072);
+        info.submapper = submapper;
+        Mapper085::new(&info)
+    }
+
+    #[test]
+    fn prg_and_chr_banking() {
+        let mut mapper = with_submapper(0);
+        mapper.cpu_write(0x8000, 0x01);
+        mapper.cpu_write(0x8010, 0x02);
+        mapper.cpu_write(0x9000, 0x03);
+        mapper.cpu_write(0xD008, 0x44);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::PrgRom(0x2000)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x4000)));
+        assert!(matches!(mapper.cpu_read(0xC000), MappedRead::PrgRom(0x6000)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::PrgRom(0x3E000)));
+        assert_eq!(mapper.ppu_read(0x1C00), 0x44 * 1024);
+    }
+
+    #[test]
+    fn submapper_selects_register_line() {
+        let mut vrc7a = with_submapper(2);
+        vrc7a.cpu_write(0x8008, 0x05);
+        assert_eq!(vrc7a.prg_banks_select, [0x05, 0x00, 0x00]);
+
+        let mut vrc7b = with_submapper(1);
+        vrc7b.cpu_write(0x8008, 0x05);
+        assert_eq!(vrc7b.prg_banks_select, [0x00, 0x05, 0x00]);
+    }
+
+    #[test]
+    fn control_register() {
+        let mut mapper = with_submapper(0);
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::None));
+
+        mapper.cpu_write(0xE000, 0x81);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        assert!(matches!(mapper.cpu_write(0x6000, 0), MappedWrite::PrgRam(0x0000)));
+    }
+
+    #[test]
+    fn cycle_mode_irq() {
+        let mut mapper = with_submapper(0);
+        mapper.cpu_write(0xE010, 0xFE);
+        mapper.cpu_write(0xF000, 0x06); // Enabled, cycle mode
+
+        mapper.clock_cpu();
+        assert!(!mapper.irq_pending());
+        mapper.clock_cpu();
+        assert!(mapper.irq_pending());
+
+        mapper.cpu_write(0xF010, 0x00);
+        assert!(!mapper.irq_pending());
+    }
+}
@@ -1,10 +1,46 @@
 mod mapper_000;
+mod mapper_001;
 mod mapper_002;
+mod mapper_003;
+mod mapper_004;
+mod mapper_005;
+mod mapper_007;
+mod mapper_009;
+mod mapper_010;
+mod mapper_019;
+mod mapper_020;
+mod mapper_021;
+mod mapper_024;
+mod mapper_034;
+mod mapper_066;
+mod mapper_069;
+mod mapper_085;
+mod nsf;
 
 pub use mapper_000::Mapper000;
+pub use mapper_001::Mapper001;
 pub use mapper_002::Mapper002;
+pub use mapper_003::Mapper003;
+pub use mapper_004::Mapper004;
+pub use mapper_005::Mapper005;
+pub use mapper_007::Mapper007;
+pub use mapper_009::Mapper009;
+pub use mapper_010::Mapper010;
+pub use mapper_019::Mapper019;
+pub use mapper_020::Mapper020;
+pub use mapper_021::Mapper021;
+pub use mapper_024::Mapper024;
+pub use mapper_034::Mapper034;
+pub use mapper_066::Mapper066;
+pub use mapper_069::Mapper069;
+pub use mapper_085::Mapper085;
+pub use nsf::NsfMapper;
 
-use super::Mirroring;
+use super::audio::ExpansionChannel;
+use super::{CartridgeInfo, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
 
 pub enum MappedRead {
     Data(u8),
@@ -18,6 +54,39 @@ pub enum MappedWrite {
     None,
 }
 
+/// Where a nametable access ($2000-$2FFF) ends up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MappedNametable {
+    Ciram(u16),           // Offset into the console's 2KB of nametable RAM
+    CartridgeVram(usize), // Offset into nametable RAM on the cartridge, e.g. the 4KB on four-screen boards
+    Chr(usize),           // Offset into CHR-ROM or CHR-RAM
+    Mapper(u16),          // Memory inside the mapper itself, accessed through `read_nametable`/`write_nametable`
+}
+
+/// Where the PPU is in the frame and how it's set up, for mappers that follow rendering (e.g. the MMC5, which counts
+/// scanlines and banks CHR separately for sprites and the background)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PpuRenderState {
+    pub rendering: bool, // Rendering is enabled and the PPU is on a visible or the pre-render scanline
+    pub scanline: u16,
+    pub dot: u16,
+    pub large_sprites: bool, // 8x16 sprites selected in $2000
+}
+
+/// Maps a nametable address to CIRAM using a fixed mirroring arrangement
+pub fn mirror_nametable(mirroring: Mirroring, address: u16) -> MappedNametable {
+    let offset = address & 0x03FF;
+    let table = (address >> 10) & 0x03;
+
+    match mirroring {
+        Mirroring::Horizontal => MappedNametable::Ciram(((table >> 1) << 10) | offset),
+        Mirroring::Vertical => MappedNametable::Ciram(((table & 0x01) << 10) | offset),
+        Mirroring::SingleScreenLower => MappedNametable::Ciram(offset),
+        Mirroring::SingleScreenUpper => MappedNametable::Ciram(0x0400 | offset),
+        Mirroring::FourScreen => MappedNametable::CartridgeVram((address & 0x0FFF) as usize),
+    }
+}
+
 pub trait Mapper {
     fn cpu_read(&mut self, address: u16) -> MappedRead;
 
@@ -28,4 +97,108 @@ pub trait Mapper {
     fn ppu_write(&mut self, address: u16, value: u8) -> Option<usize>;
 
     fn mirroring(&self) -> Mirroring;
+
+    /// Decides where a nametable address goes, by default following `mirroring()`. Boards that bank CIRAM or put CHR or
+    /// their own memory into nametable slots override this.
+    fn ppu_nametable(&mut self, address: u16) -> MappedNametable {
+        mirror_nametable(self.mirroring(), address)
+    }
+
+    /// Nametable reads that `ppu_nametable` mapped to `MappedNametable::Mapper`
+    fn read_nametable(&mut self, _offset: u16) -> u8 {
+        0
+    }
+
+    /// Nametable writes that `ppu_nametable` mapped to `MappedNametable::Mapper`
+    fn write_nametable(&mut self, _offset: u16, _value: u8) {}
+
+    /// Whether the PRG-ROM drives the data bus during writes to $8000-$FFFF. Boards without a chip to disable the ROM
+    /// on writes see the written value ANDed with the ROM byte at that address, which `Cartridge` applies before
+    /// calling `cpu_write`.
+    fn bus_conflicts(&self) -> bool {
+        false
+    }
+
+    /// Observe an address placed on the PPU bus, either by a rendering fetch or a $2007 access
+    fn notify_ppu_address(&mut self, _address: u16) {}
+
+    /// Observe a pattern table read after the data has been fetched, for boards that switch CHR banks based on what the
+    /// PPU reads (e.g. the MMC2/MMC4 latches)
+    fn notify_ppu_read(&mut self, _address: u16) {}
+
+    /// Called by the PPU on every dot before it makes that dot's fetches
+    fn notify_ppu_state(&mut self, _state: PpuRenderState) {}
+
+    /// Called once per CPU cycle, for mappers with cycle-counting IRQs or expansion audio
+    fn clock_cpu(&mut self) {}
+
+    /// Whether the mapper is currently asserting the CPU IRQ line
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Output of any expansion audio on the cartridge, mixed with the 2A03's channels. 1.0 is the full-scale output of
+    /// the 2A03's own mix, `volumes` holds the user's volumes for chips with individually adjustable channels.
+    fn audio_output(&self, _volumes: &ChannelVolumes) -> f32 {
+        0.0
+    }
+
+    /// The expansion audio channels, for display in the APU debug panel
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        Vec::new()
+    }
+
+    /// Mixes channels that the hardware outputs one at a time (the Namco 163's) evenly instead, which avoids aliasing
+    fn set_average_multiplexed_channels(&mut self, _enabled: bool) {}
+
+    /// Ejects the disk and, after a delay long enough for the system to notice, inserts the next side. Returns the side
+    /// that will be inserted, or `None` for cartridges.
+    fn switch_disk_side(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// Data the mapper persists itself rather than through battery-backed PRG-RAM (e.g. changes written to a disk),
+    /// which is marked as saved once taken. `None` for boards without any.
+    fn save_persistent_data(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Whether the data from `save_persistent_data` has changed since it was last saved or restored
+    fn persistent_data_dirty(&self) -> bool {
+        false
+    }
+
+    fn restore_persistent_data(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Serialise the mapper's internal registers for a save state
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
+}
+
+/// Board description for mapper unit tests, with the given ROM sizes and horizontal mirroring
+#[cfg(test)]
+fn test_info(mapper: u16, prg_rom_size: usize, chr_rom_size: usize) -> CartridgeInfo {
+    use super::header::{ConsoleType, HeaderFormat, TimingRegion};
+
+    CartridgeInfo {
+        format: HeaderFormat::INes,
+        mapper,
+        submapper: 0,
+        mirroring: Mirroring::Horizontal,
+        has_battery: false,
+        has_trainer: false,
+
+        prg_rom_size,
+        chr_rom_size,
+        prg_ram_size: 8192,
+        prg_nvram_size: 0,
+        chr_ram_size: if chr_rom_size == 0 { 8192 } else { 0 },
+        chr_nvram_size: 0,
+
+        timing: TimingRegion::Ntsc,
+        console_type: ConsoleType::Nes,
+    }
 }
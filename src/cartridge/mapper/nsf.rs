@@ -0,0 +1,346 @@
+use super::{MappedRead, MappedWrite, Mapper, Mirroring};
+use crate::apu::ChannelVolumes;
+use crate::cartridge::audio::{ExpansionChannel, FdsAudio, Mmc5Audio, Namco163Audio, Sunsoft5b, Vrc6Audio, Vrc7Audio};
+use crate::cartridge::nsf::{NSF_BANK_SIZE, NsfChips, NsfFile};
+use crate::state::{StateReader, StateWriter};
+use anyhow::Result;
+
+const FDS_RAM_SIZE: usize = 0xA000; // $6000-$FFFF
+const EXRAM_SIZE: usize = 1024;
+
+/// The board an NSF player puts a tune on: 4KB PRG banks at $8000-$FFFF switched through $5FF8-$5FFF, 8KB of PRG-RAM
+/// at $6000 and whichever expansion audio chips the tune uses. FDS tunes run from RAM at $6000-$FFFF instead, with
+/// bank writes (including $5FF6/$5FF7 for $6000-$7FFF) copying a bank into it.
+pub struct NsfMapper {
+    banks: [u8; 10], // 4KB slots of $6000-$FFFF
+    bank_count: usize,
+
+    fds_image: Vec<u8>, // The program data, only kept for FDS tunes which copy banks out of it
+    fds_ram: Vec<u8>,
+    exram: Vec<u8>, // MMC5 ExRAM at $5C00-$5FF5, usable as plain RAM
+    multiplicand: u8,
+    multiplier: u8,
+
+    vrc6: Option<Vrc6Audio>,
+    vrc7: Option<Vrc7Audio>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<Mmc5Audio>,
+    namco_163: Option<Namco163Audio>,
+    sunsoft_5b: Option<Sunsoft5b>,
+}
+
+impl NsfMapper {
+    pub fn new(file: &NsfFile) -> Self {
+        let image = file.prg_image();
+        let has = |chip: NsfChips| file.chips.contains(chip);
+
+        let mut mapper = Self {
+            banks: file.initial_banks(),
+            bank_count: image.len() / NSF_BANK_SIZE,
+
+            fds_image: if has(NsfChips::FDS) { image } else { vec![] },
+            fds_ram: if has(NsfChips::FDS) { vec![0; FDS_RAM_SIZE] } else { vec![] },
+            exram: if has(NsfChips::MMC5) { vec![0; EXRAM_SIZE] } else { vec![] },
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            vrc6: has(NsfChips::VRC6).then(Vrc6Audio::new),
+            vrc7: has(NsfChips::VRC7).then(Vrc7Audio::new),
+            fds: has(NsfChips::FDS).then(FdsAudio::new),
+            mmc5: has(NsfChips::MMC5).then(Mmc5Audio::new),
+            namco_163: has(NsfChips::NAMCO_163).then(Namco163Audio::new),
+            sunsoft_5b: has(NsfChips::SUNSOFT_5B).then(Sunsoft5b::new),
+        };
+
+        for slot in 0..mapper.banks.len() {
+            mapper.copy_fds_bank(slot);
+        }
+
+        mapper
+    }
+
+    fn is_fds(&self) -> bool {
+        !self.fds_ram.is_empty()
+    }
+
+    /// Copies the bank selected for a slot into FDS RAM, leaving the RAM as it is for banks past the end of the data
+    fn copy_fds_bank(&mut self, slot: usize) {
+        let start = self.banks[slot] as usize * NSF_BANK_SIZE;
+        if let (Some(bank), Some(target)) = (self.fds_image.get(start..start + NSF_BANK_SIZE), self.fds_ram.get_mut(slot * NSF_BANK_SIZE..)) {
+            target[..NSF_BANK_SIZE].copy_from_slice(bank);
+        }
+    }
+
+    /// Reads from the registers of whichever expansion chips the tune uses
+    fn read_audio(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x4092 => self.fds.as_ref()?.read(address),
+            0x4800 => Some(self.namco_163.as_mut()?.read_data()),
+            0x5010 | 0x5015 => Some(self.mmc5.as_ref()?.read(address)),
+            _ => None,
+        }
+    }
+
+    /// Writes to the registers of whichever expansion chips the tune uses. Their ports overlap the bank registers and
+    /// FDS RAM, so writes go to both.
+    fn write_audio(&mut self, address: u16, value: u8) {
+        if let Some(vrc6) = &mut self.vrc6
+            && let 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 = address
+        {
+            vrc6.write(address, value);
+        }
+        if let Some(vrc7) = &mut self.vrc7 {
+            match address {
+                0x9010 => vrc7.select_register(value),
+                0x9030 => vrc7.write_data(value),
+                _ => {}
+            }
+        }
+        if let Some(fds) = &mut self.fds
+            && let 0x4040..=0x408A = address
+        {
+            fds.write(address, value);
+        }
+        if let Some(mmc5) = &mut self.mmc5
+            && let 0x5000..=0x5015 = address
+        {
+            mmc5.write(address, value);
+        }
+        if let Some(namco_163) = &mut self.namco_163 {
+            match address {
+                0x4800 => namco_163.write_data(value),
+                0xF800 => namco_163.write_address(value),
+                _ => {}
+            }
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+            match address {
+                0xC000 => sunsoft_5b.write_address(value),
+                0xE000 => sunsoft_5b.write_data(value),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_read(&mut self, address: u16) -> MappedRead {
+        if let Some(value) = self.read_audio(address) {
+            return MappedRead::Data(value);
+        }
+
+        match address {
+            0x5205 if self.mmc5.is_some() => MappedRead::Data((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 if self.mmc5.is_some() => MappedRead::Data(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FF5 if self.mmc5.is_some() => MappedRead::Data(self.exram[(address - 0x5C00) as usize]),
+            0x6000..=0xFFFF if self.is_fds() => MappedRead::Data(self.fds_ram[(address - 0x6000) as usize]),
+            0x6000..=0x7FFF => MappedRead::PrgRam(address - 0x6000),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(address as usize >> 12) - 6] as usize;
+                if bank < self.bank_count {
+                    MappedRead::PrgRom(bank * NSF_BANK_SIZE + (address as usize & (NSF_BANK_SIZE - 1)))
+                } else {
+                    MappedRead::None
+                }
+            }
+            _ => MappedRead::None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> MappedWrite {
+        self.write_audio(address, value);
+
+        match address {
+            0x5205 if self.mmc5.is_some() => self.multiplicand = value,
+            0x5206 if self.mmc5.is_some() => self.multiplier = value,
+            0x5C00..=0x5FF5 if self.mmc5.is_some() => self.exram[(address - 0x5C00) as usize] = value,
+            0x5FF6..=0x5FFF if self.is_fds() => {
+                let slot = (address - 0x5FF6) as usize;
+                self.banks[slot] = value;
+                self.copy_fds_bank(slot);
+            }
+            0x5FF8..=0x5FFF => self.banks[(address - 0x5FF6) as usize] = value,
+            0x6000..=0xFFFF if self.is_fds() => self.fds_ram[(address - 0x6000) as usize] = value,
+            0x6000..=0x7FFF => return MappedWrite::PrgRam(address - 0x6000),
+            _ => {}
+        }
+
+        MappedWrite::None
+    }
+
+    fn ppu_read(&mut self, address: u16) -> usize {
+        address as usize
+    }
+
+    fn ppu_write(&mut self, address: u16, _value: u8) -> Option<usize> {
+        Some(address as usize)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn clock_cpu(&mut self) {
+        self.vrc6.iter_mut().for_each(Vrc6Audio::clock);
+        self.vrc7.iter_mut().for_each(Vrc7Audio::clock);
+        self.fds.iter_mut().for_each(FdsAudio::clock);
+        self.mmc5.iter_mut().for_each(Mmc5Audio::clock);
+        self.namco_163.iter_mut().for_each(Namco163Audio::clock);
+        self.sunsoft_5b.iter_mut().for_each(Sunsoft5b::clock);
+    }
+
+    fn audio_output(&self, volumes: &ChannelVolumes) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |vrc6| vrc6.output(volumes))
+            + self.vrc7.as_ref().map_or(0.0, Vrc7Audio::output)
+            + self.fds.as_ref().map_or(0.0, FdsAudio::output)
+            + self.mmc5.as_ref().map_or(0.0, Mmc5Audio::output)
+            + self.namco_163.as_ref().map_or(0.0, Namco163Audio::output)
+            + self.sunsoft_5b.as_ref().map_or(0.0, Sunsoft5b::output)
+    }
+
+    fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        let mut channels = Vec::new();
+        channels.extend(self.vrc6.iter().flat_map(Vrc6Audio::channels));
+        channels.extend(self.vrc7.iter().flat_map(Vrc7Audio::channels));
+        channels.extend(self.fds.iter().flat_map(FdsAudio::channels));
+        channels.extend(self.mmc5.iter().flat_map(Mmc5Audio::channels));
+        channels.extend(self.namco_163.iter().flat_map(Namco163Audio::channels));
+        channels.extend(self.sunsoft_5b.iter().flat_map(Sunsoft5b::channels));
+        channels
+    }
+
+    fn set_average_multiplexed_channels(&mut self, enabled: bool) {
+        if let Some(namco_163) = &mut self.namco_163 {
+            namco_163.average_channels = enabled;
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.banks);
+        writer.write_bytes(&self.fds_ram);
+        writer.write_bytes(&self.exram);
+        writer.write_u8(self.multiplicand);
+        writer.write_u8(self.multiplier);
+
+        self.vrc6.iter().for_each(|vrc6| vrc6.save_state(writer));
+        self.vrc7.iter().for_each(|vrc7| vrc7.save_state(writer));
+        self.fds.iter().for_each(|fds| fds.save_state(writer));
+        self.mmc5.iter().for_each(|mmc5| mmc5.save_state(writer));
+        self.namco_163.iter().for_each(|namco_163| namco_163.save_state(writer));
+        self.sunsoft_5b.iter().for_each(|sunsoft_5b| sunsoft_5b.save_state(writer));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.banks)?;
+        reader.read_bytes_into(&mut self.fds_ram)?;
+        reader.read_bytes_into(&mut self.exram)?;
+        self.multiplicand = reader.read_u8()?;
+        self.multiplier = reader.read_u8()?;
+
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.load_state(reader)?;
+        }
+        if let Some(vrc7) = &mut self.vrc7 {
+            vrc7.load_state(reader)?;
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.load_state(reader)?;
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.load_state(reader)?;
+        }
+        if let Some(namco_163) = &mut self.namco_163 {
+            namco_163.load_state(reader)?;
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+            sunsoft_5b.load_state(reader)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nsf_file(load_address: u16, banks: Option<[u8; 8]>, chips: NsfChips) -> NsfFile {
+        let mut data = vec![0; 3 * NSF_BANK_SIZE];
+        for (bank, chunk) in data.chunks_mut(NSF_BANK_SIZE).enumerate() {
+            chunk.fill(bank as u8 + 1);
+        }
+
+        let mut header = vec![0; 0x80];
+        header[..5].copy_from_slice(b"NESM\x1A");
+        header[0x06] = 1;
+        header[0x08..0x0A].copy_from_slice(&load_address.to_le_bytes());
+        header[0x70..0x78].copy_from_slice(&banks.unwrap_or_default());
+        header[0x7B] = chips.bits();
+        NsfFile::parse(&[header, data].concat()).unwrap()
+    }
+
+    #[test]
+    fn maps_non_bankswitched_data_at_the_load_address() {
+        let file = nsf_file(0xA000, None, NsfChips::empty());
+        let mut mapper = NsfMapper::new(&file);
+
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::None));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x0000)));
+        assert!(matches!(mapper.cpu_read(0xC123), MappedRead::PrgRom(0x2123)));
+        assert!(matches!(mapper.cpu_read(0xE000), MappedRead::None));
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::PrgRam(0x0000)));
+    }
+
+    #[test]
+    fn switches_4k_banks() {
+        let file = nsf_file(0x8100, Some([0, 1, 2, 0, 0, 0, 0, 0]), NsfChips::empty());
+        let mut mapper = NsfMapper::new(&file);
+
+        // The data is padded so that $8100 is $100 bytes into the first bank
+        assert!(matches!(mapper.cpu_read(0x8100), MappedRead::PrgRom(0x0100)));
+        assert!(matches!(mapper.cpu_read(0xA000), MappedRead::PrgRom(0x2000)));
+
+        mapper.cpu_write(0x5FFF, 0x01);
+        assert!(matches!(mapper.cpu_read(0xF000), MappedRead::PrgRom(0x1000)));
+    }
+
+    #[test]
+    fn fds_tunes_copy_banks_into_ram() {
+        let file = nsf_file(0x6000, Some([2, 2, 2, 2, 2, 2, 0, 1]), NsfChips::FDS);
+        let mut mapper = NsfMapper::new(&file);
+
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::Data(0x01)));
+        assert!(matches!(mapper.cpu_read(0x7000), MappedRead::Data(0x02)));
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::Data(0x03)));
+
+        mapper.cpu_write(0x8000, 0x42);
+        assert!(matches!(mapper.cpu_read(0x8000), MappedRead::Data(0x42)));
+
+        mapper.cpu_write(0x5FF6, 0x01);
+        assert!(matches!(mapper.cpu_read(0x6000), MappedRead::Data(0x02)));
+
+        // Sound registers are always enabled
+        mapper.cpu_write(0x4080, 0x80 | 0x20);
+        assert!(matches!(mapper.cpu_read(0x4090), MappedRead::Data(0x60)));
+    }
+
+    #[test]
+    fn mixes_expansion_chips() {
+        let file = nsf_file(0x8000, None, NsfChips::VRC6 | NsfChips::MMC5);
+        let mut mapper = NsfMapper::new(&file);
+        assert_eq!(mapper.audio_channels().len(), 3 + 3);
+
+        mapper.cpu_write(0x5205, 7);
+        mapper.cpu_write(0x5206, 9);
+        assert!(matches!(mapper.cpu_read(0x5205), MappedRead::Data(63)));
+
+        mapper.cpu_write(0x5C10, 0x42);
+        assert!(matches!(mapper.cpu_read(0x5C10), MappedRead::Data(0x42)));
+
+        // A constant VRC6 pulse output
+        mapper.cpu_write(0x9000, 0x8F);
+        mapper.cpu_write(0x9002, 0x80);
+        mapper.clock_cpu();
+        assert!(mapper.audio_output(&ChannelVolumes::default()) > 0.0);
+    }
+}
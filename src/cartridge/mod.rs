@@ -1,15 +1,30 @@
+mod audio;
+mod disk;
+mod error;
+mod header;
 mod mapper;
+mod nsf;
 
-pub use mapper::Mapper;
+pub use audio::ExpansionChannel;
+pub use error::CartridgeError;
+pub use header::{CartridgeInfo, ConsoleType, HeaderFormat, TimingRegion};
+pub use mapper::{MappedNametable, Mapper, PpuRenderState};
+pub use nsf::{NsfChips, NsfFile, NsfTrack};
 
-use anyhow::{Context, Result, bail, ensure};
-use mapper::{MappedRead, MappedWrite, Mapper000, Mapper002};
+use crate::apu::ChannelVolumes;
+use crate::state::{SaveState, StateReader, StateWriter, crc32};
+use anyhow::{Context, Result, bail};
+use header::{FDS_BIOS_SIZE, HEADER_SIZE, INesHeader};
+use mapper::{
+    MappedRead, MappedWrite, Mapper000, Mapper001, Mapper002, Mapper003, Mapper004, Mapper005, Mapper007, Mapper009, Mapper010, Mapper019, Mapper020,
+    Mapper021, Mapper024, Mapper034, Mapper066, Mapper069, Mapper085, NsfMapper,
+};
+use std::path::{Path, PathBuf};
 
-const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // NES^Z
+const TRAINER_SIZE: usize = 512;
+const FOUR_SCREEN_VRAM_SIZE: usize = 4096;
 const PRG_ROM_BANK_SIZE: usize = 16384; // 16KB
 const CHR_ROM_BANK_SIZE: usize = 8192; // 8KB
-const PRG_RAM_SIZE: usize = 8192;
-const CHR_RAM_SIZE: usize = 8192;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
@@ -20,95 +35,245 @@ pub enum Mirroring {
     FourScreen,
 }
 
-#[repr(C, packed)]
-pub struct INesHeader {
-    tag: [u8; 4],     // "NES" followed by MS-DOS EOF
-    prg_rom_size: u8, // Size of PRG-ROM in 16KB units
-    chr_rom_size: u8, // Size of CHR-ROM in 8KB units
-    flags_6: u8,      // Lower nybble of mapper, mirroring, battery and trainer
-    flags_7: u8,      // Upper nybble of mapper, version
-    flags_8: u8,      // PRG-RAM size
-    _unused: [u8; 7],
-}
-
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
     pub prg_ram: Vec<u8>,
     pub chr_ram: Vec<u8>,
+    pub nametable_ram: Vec<u8>, // Extra VRAM on four-screen boards
     pub mapper: Box<dyn Mapper>,
-    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub info: CartridgeInfo,
+
+    rom_checksum: u32,
+    sram_dirty: bool,
 }
 
 impl Cartridge {
-    pub fn load(path: &str) -> Result<Cartridge> {
-        let data = std::fs::read(path).with_context(|| format!("Failed to read ROM file: {}", path))?;
-        ensure!(data.len() >= 16, "ROM data is too small for expected iNES header");
-
-        let header = unsafe { &*(data.as_ptr() as *const INesHeader) };
-        ensure!(header.tag == NES_TAG, "Invalid iNES header");
+    pub fn load(path: impl AsRef<Path>) -> Result<Cartridge, CartridgeError> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data)
+    }
 
-        let mapper_number = (header.flags_7 & 0xF0) | (header.flags_6 >> 4);
+    /// Loads a cartridge from an iNES or NES 2.0 image held in memory
+    pub fn from_bytes(data: &[u8]) -> Result<Cartridge, CartridgeError> {
+        let header = INesHeader::parse(data)?;
+        let info = CartridgeInfo::from_header(&header);
 
-        let mirroring = if header.flags_6 & 0x08 != 0 {
-            Mirroring::FourScreen
-        } else if header.flags_6 & 0x01 != 0 {
-            Mirroring::Vertical
-        } else {
-            Mirroring::Horizontal
-        };
+        let mut remaining = &data[HEADER_SIZE..];
+        if info.has_trainer {
+            remaining = remaining.get(TRAINER_SIZE..).ok_or(CartridgeError::TruncatedTrainer)?;
+        }
 
-        let prg_rom_size = (header.prg_rom_size as usize) * PRG_ROM_BANK_SIZE;
-        let chr_rom_size = (header.chr_rom_size as usize) * CHR_ROM_BANK_SIZE;
-        let has_trainer = header.flags_6 & 0x4 != 0;
+        let prg_rom = remaining.get(..info.prg_rom_size).ok_or(CartridgeError::TruncatedPrgRom {
+            expected: info.prg_rom_size,
+            available: remaining.len(),
+        })?;
+        remaining = &remaining[info.prg_rom_size..];
 
-        let mut offset = 16;
-        if has_trainer {
-            offset += 512;
-        }
+        let chr_rom = remaining.get(..info.chr_rom_size).ok_or(CartridgeError::TruncatedChrRom {
+            expected: info.chr_rom_size,
+            available: remaining.len(),
+        })?;
 
-        ensure!(data.len() >= offset + prg_rom_size, "ROM data is too small for expected PRG-ROM");
-        let prg_rom = data[offset..offset + prg_rom_size].to_vec();
-        offset += prg_rom_size;
+        let prg_rom = prg_rom.to_vec();
+        let chr_rom = chr_rom.to_vec();
 
-        let (chr_rom, chr_ram) = if chr_rom_size > 0 {
-            ensure!(data.len() >= offset + chr_rom_size, "ROM data is too small for expected CHR-ROM");
-            (data[offset..offset + chr_rom_size].to_vec(), vec![])
-        } else {
-            (vec![], vec![0; CHR_RAM_SIZE]) // CHR-RAM (8KB)
-        };
+        let rom_checksum = crc32(&[prg_rom.as_slice(), chr_rom.as_slice()].concat());
 
         Ok(Self {
             prg_rom,
             chr_rom,
-            prg_ram: vec![0; PRG_RAM_SIZE], // PRG-RAM (8KB)
-            chr_ram,
-            mapper: Self::create_mapper(mapper_number, prg_rom_size, chr_rom_size, mirroring)?,
-            mirroring,
+            prg_ram: vec![0; info.total_prg_ram_size()],
+            chr_ram: vec![0; info.total_chr_ram_size()],
+            nametable_ram: if info.mirroring == Mirroring::FourScreen {
+                vec![0; FOUR_SCREEN_VRAM_SIZE]
+            } else {
+                vec![]
+            },
+            mapper: Self::create_mapper(&info)?,
+            has_battery: info.has_battery,
+            info,
+
+            rom_checksum,
+            sram_dirty: false,
+        })
+    }
+
+    /// Loads a Famicom Disk System image along with the BIOS ROM the user supplies
+    pub fn load_fds(path: impl AsRef<Path>, bios_path: impl AsRef<Path>) -> Result<Cartridge, CartridgeError> {
+        let disk = std::fs::read(path)?;
+        let bios = std::fs::read(bios_path)?;
+        Self::from_fds(&disk, &bios)
+    }
+
+    /// Loads a .fds disk image held in memory, with or without its fwNES header. The 8KB BIOS sits where PRG-ROM would
+    /// on a cartridge.
+    pub fn from_fds(disk: &[u8], bios: &[u8]) -> Result<Cartridge, CartridgeError> {
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(CartridgeError::InvalidFdsBios { length: bios.len() });
+        }
+
+        let sides = disk::parse_disk_image(disk)?;
+        let raw_sides = sides.iter().map(|side| disk::build_raw_side(side)).collect();
+        let info = CartridgeInfo::fds();
+
+        Ok(Self {
+            prg_rom: bios.to_vec(),
+            chr_rom: vec![],
+            prg_ram: vec![0; info.total_prg_ram_size()],
+            chr_ram: vec![0; info.total_chr_ram_size()],
+            nametable_ram: vec![],
+            mapper: Box::new(Mapper020::new(raw_sides)),
+            has_battery: false,
+            info,
+
+            rom_checksum: crc32(&sides.concat()),
+            sram_dirty: false,
         })
     }
 
+    /// Builds the board an NSF player runs a tune on, with the tune's program data as PRG-ROM
+    pub fn from_nsf(file: &NsfFile) -> Cartridge {
+        let prg_rom = file.prg_image();
+        let info = CartridgeInfo::nsf(prg_rom.len());
+
+        Self {
+            prg_rom,
+            chr_rom: vec![],
+            prg_ram: vec![0; info.total_prg_ram_size()],
+            chr_ram: vec![0; info.total_chr_ram_size()],
+            nametable_ram: vec![],
+            mapper: Box::new(NsfMapper::new(file)),
+            has_battery: false,
+            info,
+
+            rom_checksum: crc32(&file.data),
+            sram_dirty: false,
+        }
+    }
+
+    /// Whether the `path` looks like a disk image rather than a cartridge, going by its extension
+    pub fn is_disk_image(path: impl AsRef<Path>) -> bool {
+        path.as_ref().extension().is_some_and(|extension| extension.eq_ignore_ascii_case("fds"))
+    }
+
+    /// CRC-32 of the PRG-ROM and CHR-ROM contents (or the disk sides of a disk image), used to identify the game a save state belongs to
+    pub fn rom_checksum(&self) -> u32 {
+        self.rom_checksum
+    }
+
+    /// Path of the save file that sits alongside a ROM, e.g. `zelda.nes` -> `zelda.sav`
+    pub fn sram_path(rom_path: impl AsRef<Path>) -> PathBuf {
+        rom_path.as_ref().with_extension("sav")
+    }
+
+    /// Path of the file that changes written to a disk image are kept in, e.g. `zelda.fds` -> `zelda.fdsdiff`. The image
+    /// itself is left untouched.
+    pub fn disk_diff_path(disk_path: impl AsRef<Path>) -> PathBuf {
+        disk_path.as_ref().with_extension("fdsdiff")
+    }
+
+    /// Battery-backed PRG-RAM contents, empty if the cartridge has no battery
+    pub fn sram(&self) -> &[u8] {
+        if self.has_battery { &self.prg_ram } else { &[] }
+    }
+
+    /// Restores battery-backed PRG-RAM, e.g. from a previously persisted save
+    pub fn restore_sram(&mut self, data: &[u8]) {
+        if !self.has_battery {
+            return;
+        }
+
+        let length = data.len().min(self.prg_ram.len());
+        self.prg_ram[..length].copy_from_slice(&data[..length]);
+        self.sram_dirty = false;
+    }
+
+    /// Whether battery-backed PRG-RAM, or the data a mapper persists itself, has been written since it was last loaded or
+    /// saved
+    pub fn sram_dirty(&self) -> bool {
+        self.sram_dirty || self.mapper.persistent_data_dirty()
+    }
+
+    /// Whether there's anything to keep in a save file, battery-backed PRG-RAM or changes to a disk
+    fn has_save_data(&self) -> bool {
+        self.has_battery || self.info.format == HeaderFormat::Fds
+    }
+
+    /// Loads battery-backed PRG-RAM (or a disk's changes) from a save file, returning false if no save file exists yet
+    pub fn load_sram_file(&mut self, path: impl AsRef<Path>) -> Result<bool> {
+        let path = path.as_ref();
+        if !self.has_save_data() || !path.exists() {
+            return Ok(false);
+        }
+
+        let data = std::fs::read(path).with_context(|| format!("Failed to read save file: {}", path.display()))?;
+        if self.has_battery {
+            self.restore_sram(&data);
+        } else {
+            self.mapper
+                .restore_persistent_data(&data)
+                .with_context(|| format!("Failed to apply save file: {}", path.display()))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Writes battery-backed PRG-RAM (or a disk's changes) to a save file
+    pub fn save_sram_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let data = if self.has_battery {
+            Some(self.prg_ram.clone())
+        } else {
+            self.mapper.save_persistent_data()
+        };
+        let Some(data) = data else {
+            return Ok(());
+        };
+
+        std::fs::write(path, data).with_context(|| format!("Failed to write save file: {}", path.display()))?;
+        self.sram_dirty = false;
+
+        Ok(())
+    }
+
     /// CPU reads from $4020-$FFFF
     pub fn cpu_read(&mut self, address: u16) -> u8 {
         match self.mapper.cpu_read(address) {
             MappedRead::Data(value) => value,
             MappedRead::PrgRom(address) if address < self.prg_rom.len() => self.prg_rom[address],
-            MappedRead::PrgRam(address) if address < self.prg_ram.len() as u16 => self.prg_ram[address as usize],
+            MappedRead::PrgRam(address) if (address as usize) < self.prg_ram.len() => self.prg_ram[address as usize],
             _ => 0, // Open bus
         }
     }
 
     /// CPU writes to $4020-$FFFF
     pub fn cpu_write(&mut self, address: u16, value: u8) {
+        let value = if address >= 0x8000 && self.mapper.bus_conflicts() {
+            value & self.cpu_read(address)
+        } else {
+            value
+        };
+
         if let MappedWrite::PrgRam(address) = self.mapper.cpu_write(address, value)
-            && address < self.prg_ram.len() as u16
+            && (address as usize) < self.prg_ram.len()
         {
+            self.sram_dirty |= self.has_battery && self.prg_ram[address as usize] != value;
             self.prg_ram[address as usize] = value;
         }
     }
 
-    /// PPU reads from $0000-$1FFF
+    /// PPU reads from $0000-$1FFF, the mapper is told about the read once it has happened
     pub fn ppu_read(&mut self, address: u16) -> u8 {
+        let value = self.ppu_peek(address);
+        self.mapper.notify_ppu_read(address);
+
+        value
+    }
+
+    /// Reads CHR as the PPU would see it without the mapper reacting to the read, e.g. for debug views
+    pub fn ppu_peek(&mut self, address: u16) -> u8 {
         let mapped_address = self.mapper.ppu_read(address);
 
         if !self.chr_rom.is_empty() && mapped_address < self.chr_rom.len() {
@@ -134,11 +299,145 @@ impl Cartridge {
         self.mapper.mirroring()
     }
 
-    fn create_mapper(mapper_number: u8, prg_rom_size: usize, chr_rom_size: usize, mirroring: Mirroring) -> Result<Box<dyn Mapper>> {
-        match mapper_number {
-            0 => Ok(Box::new(Mapper000::new(prg_rom_size, chr_rom_size, mirroring))),
-            2 => Ok(Box::new(Mapper002::new(prg_rom_size, chr_rom_size, mirroring))),
-            _ => bail!("Unsupported mapper: {}", mapper_number),
+    /// Asks the mapper where a PPU access to $2000-$3EFF goes. Anything other than CIRAM, which lives in the PPU, is
+    /// then read and written through `read_nametable`/`write_nametable`.
+    pub fn map_nametable(&mut self, address: u16) -> MappedNametable {
+        self.mapper.ppu_nametable(0x2000 | (address & 0x0FFF))
+    }
+
+    pub fn read_nametable(&mut self, mapped: MappedNametable) -> u8 {
+        match mapped {
+            MappedNametable::CartridgeVram(offset) => self.nametable_ram.get(offset).copied().unwrap_or(0),
+            MappedNametable::Chr(offset) if !self.chr_rom.is_empty() => self.chr_rom.get(offset).copied().unwrap_or(0),
+            MappedNametable::Chr(offset) => self.chr_ram.get(offset).copied().unwrap_or(0),
+            MappedNametable::Mapper(offset) => self.mapper.read_nametable(offset),
+            MappedNametable::Ciram(_) => 0, // Handled by the PPU
+        }
+    }
+
+    pub fn write_nametable(&mut self, mapped: MappedNametable, value: u8) {
+        match mapped {
+            MappedNametable::CartridgeVram(offset) => {
+                if let Some(byte) = self.nametable_ram.get_mut(offset) {
+                    *byte = value;
+                }
+            }
+            MappedNametable::Chr(offset) if self.chr_rom.is_empty() => {
+                if let Some(byte) = self.chr_ram.get_mut(offset) {
+                    *byte = value;
+                }
+            }
+            MappedNametable::Mapper(offset) => self.mapper.write_nametable(offset, value),
+            _ => {} // CHR-ROM is read-only, CIRAM is handled by the PPU
+        }
+    }
+
+    /// Lets the mapper observe PPU bus activity (e.g. MMC3 counts rising edges of A12)
+    pub fn notify_ppu_address(&mut self, address: u16) {
+        self.mapper.notify_ppu_address(address);
+    }
+
+    /// Keeps the mapper up to date with where the PPU is rendering
+    pub fn notify_ppu_state(&mut self, state: PpuRenderState) {
+        self.mapper.notify_ppu_state(state);
+    }
+
+    /// Advances the mapper by one CPU cycle
+    pub fn clock_cpu(&mut self) {
+        self.mapper.clock_cpu();
+    }
+
+    /// Level of the cartridge's expansion audio for the current cycle
+    pub fn audio_output(&self, volumes: &ChannelVolumes) -> f32 {
+        self.mapper.audio_output(volumes)
+    }
+
+    pub fn audio_channels(&self) -> Vec<ExpansionChannel> {
+        self.mapper.audio_channels()
+    }
+
+    /// Mixes the channels of expansion audio that outputs one channel at a time evenly, see `Namco163Audio`
+    pub fn set_average_multiplexed_channels(&mut self, enabled: bool) {
+        self.mapper.set_average_multiplexed_channels(enabled);
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
+    /// Ejects the disk and inserts its next side, returning the side inserted or `None` if this isn't a disk system
+    pub fn switch_disk_side(&mut self) -> Option<usize> {
+        self.mapper.switch_disk_side()
+    }
+
+    fn create_mapper(info: &CartridgeInfo) -> Result<Box<dyn Mapper>, CartridgeError> {
+        match info.mapper {
+            0 => Ok(Box::new(Mapper000::new(info))),
+            1 => Ok(Box::new(Mapper001::new(info))),
+            2 => Ok(Box::new(Mapper002::new(info))),
+            3 => Ok(Box::new(Mapper003::new(info))),
+            4 => Ok(Box::new(Mapper004::new(info))),
+            5 => Ok(Box::new(Mapper005::new(info))),
+            7 => Ok(Box::new(Mapper007::new(info))),
+            9 => Ok(Box::new(Mapper009::new(info))),
+            10 => Ok(Box::new(Mapper010::new(info))),
+            19 => Ok(Box::new(Mapper019::new(info))),
+            21 | 22 | 23 | 25 => Ok(Box::new(Mapper021::new(info))),
+            24 | 26 => Ok(Box::new(Mapper024::new(info))),
+            34 => Ok(Box::new(Mapper034::new(info))),
+            66 => Ok(Box::new(Mapper066::new(info))),
+            69 => Ok(Box::new(Mapper069::new(info))),
+            85 => Ok(Box::new(Mapper085::new(info))),
+            _ => Err(CartridgeError::UnsupportedMapper {
+                mapper: info.mapper,
+                submapper: info.submapper,
+            }),
         }
     }
 }
+
+impl SaveState for Cartridge {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        writer.write_bytes(&self.chr_ram);
+        writer.write_bytes(&self.nametable_ram);
+        self.mapper.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.prg_ram)?;
+        reader.read_bytes_into(&mut self.chr_ram)?;
+        reader.read_bytes_into(&mut self.nametable_ram)?;
+        self.mapper.load_state(reader)?;
+
+        // Restored PRG-RAM differs from what was last flushed to the save file
+        self.sram_dirty = self.has_battery;
+
+        Ok(())
+    }
+}
+
+impl SaveState for Mirroring {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self {
+            Mirroring::Horizontal => 0,
+            Mirroring::Vertical => 1,
+            Mirroring::SingleScreenLower => 2,
+            Mirroring::SingleScreenUpper => 3,
+            Mirroring::FourScreen => 4,
+        });
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = match reader.read_u8()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::SingleScreenLower,
+            3 => Mirroring::SingleScreenUpper,
+            4 => Mirroring::FourScreen,
+            value => bail!("Invalid mirroring in save state: {}", value),
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use super::CartridgeError;
+
+const NSF_TAG: &[u8] = b"NESM\x1A";
+const NSFE_TAG: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+const DEFAULT_PLAY_PERIOD_US: u16 = 16639; // 60.1Hz, the NTSC frame rate
+pub const NSF_BANK_SIZE: usize = 4096;
+
+bitflags::bitflags! {
+    /// Expansion audio the tune was written for, from the header's chip byte
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct NsfChips: u8 {
+        const VRC6 = 0b0000_0001;
+        const VRC7 = 0b0000_0010;
+        const FDS = 0b0000_0100;
+        const MMC5 = 0b0000_1000;
+        const NAMCO_163 = 0b0001_0000;
+        const SUNSOFT_5B = 0b0010_0000;
+    }
+}
+
+/// Per-track metadata, only NSFe files (and NSF2 files with metadata) carry any of it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NsfTrack {
+    pub title: Option<String>,
+    pub length_ms: Option<u32>,
+    pub fade_ms: Option<u32>,
+}
+
+/// A parsed NSF or NSFe file: the program data, where it loads and the routines the player calls
+#[derive(Debug, Clone)]
+pub struct NsfFile {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub play_period_us: u16,    // Microseconds between PLAY calls on NTSC
+    pub banks: Option<[u8; 8]>, // Initial $5FF8-$5FFF values, `None` if the tune isn't bankswitched
+    pub chips: NsfChips,
+
+    pub starting_track: u8, // 0-based
+    pub tracks: Vec<NsfTrack>,
+
+    pub data: Vec<u8>,
+}
+
+impl NsfFile {
+    /// Parses an NSF (including NSF2) or NSFe file
+    pub fn parse(data: &[u8]) -> Result<NsfFile, CartridgeError> {
+        if data.starts_with(NSF_TAG) {
+            Self::parse_nsf(data)
+        } else if data.starts_with(NSFE_TAG) {
+            let mut file = Self::empty();
+            file.apply_chunks(&data[NSFE_TAG.len()..], true)?;
+            file.validate()
+        } else {
+            Err(CartridgeError::InvalidNsf("missing NESM or NSFE tag".into()))
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+
+            load_address: 0,
+            init_address: 0,
+            play_address: 0,
+            play_period_us: DEFAULT_PLAY_PERIOD_US,
+            banks: None,
+            chips: NsfChips::empty(),
+
+            starting_track: 0,
+            tracks: Vec::new(),
+
+            data: Vec::new(),
+        }
+    }
+
+    fn parse_nsf(data: &[u8]) -> Result<NsfFile, CartridgeError> {
+        let Some(header) = data.first_chunk::<NSF_HEADER_SIZE>() else {
+            return Err(CartridgeError::InvalidNsf(format!("file is too small for a header ({} bytes)", data.len())));
+        };
+
+        let word = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let banks: [u8; 8] = header[0x70..0x78].try_into().unwrap();
+        let track_count = header[0x06].max(1) as usize;
+
+        // NSF2 files can give the program's length, with NSFe metadata chunks following it
+        let program_length = u32::from_le_bytes([header[0x7D], header[0x7E], header[0x7F], 0]) as usize;
+        let body = &data[NSF_HEADER_SIZE..];
+        let (program, metadata) = if header[0x05] >= 2 && program_length > 0 && program_length <= body.len() {
+            body.split_at(program_length)
+        } else {
+            (body, &[][..])
+        };
+
+        let mut file = Self {
+            title: header_string(&header[0x0E..0x2E]),
+            artist: header_string(&header[0x2E..0x4E]),
+            copyright: header_string(&header[0x4E..0x6E]),
+
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            play_period_us: play_period(word(0x6E)),
+            banks: banks.iter().any(|bank| *bank != 0).then_some(banks),
+            chips: NsfChips::from_bits_truncate(header[0x7B]),
+
+            starting_track: header[0x07].saturating_sub(1).min(track_count as u8 - 1),
+            tracks: vec![NsfTrack::default(); track_count],
+
+            data: program.to_vec(),
+        };
+
+        if !metadata.is_empty() {
+            file.apply_chunks(metadata, false)?;
+        }
+
+        file.validate()
+    }
+
+    /// Reads NSFe chunks, each a 32-bit length, a four character ID and the data. Chunks with an uppercase first letter
+    /// are required to play the file, so unknown ones of those are rejected.
+    fn apply_chunks(&mut self, mut chunks: &[u8], full_file: bool) -> Result<(), CartridgeError> {
+        let mut have_info = !full_file;
+
+        while let Some((header, rest)) = chunks.split_first_chunk::<8>() {
+            let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let id = &header[4..8];
+            let Some(chunk) = rest.get(..length) else {
+                return Err(CartridgeError::InvalidNsf(format!("truncated {} chunk", String::from_utf8_lossy(id))));
+            };
+            chunks = &rest[length..];
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 9 {
+                        return Err(CartridgeError::InvalidNsf("INFO chunk is too small".into()));
+                    }
+
+                    let word = |offset: usize| u16::from_le_bytes([chunk[offset], chunk[offset + 1]]);
+                    self.load_address = word(0);
+                    self.init_address = word(2);
+                    self.play_address = word(4);
+                    self.chips = NsfChips::from_bits_truncate(chunk[7]);
+
+                    let track_count = chunk.get(8).copied().unwrap_or(1).max(1);
+                    self.tracks.resize(track_count as usize, NsfTrack::default());
+                    self.starting_track = chunk.get(9).copied().unwrap_or(0).min(track_count - 1);
+                    have_info = true;
+                }
+                b"DATA" => self.data = chunk.to_vec(),
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    let length = chunk.len().min(8);
+                    banks[..length].copy_from_slice(&chunk[..length]);
+                    self.banks = Some(banks);
+                }
+                b"RATE" if chunk.len() >= 2 => {
+                    self.play_period_us = play_period(u16::from_le_bytes([chunk[0], chunk[1]]));
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|byte| *byte == 0).map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+                    self.title = strings.next().unwrap_or_default();
+                    self.artist = strings.next().unwrap_or_default();
+                    self.copyright = strings.next().unwrap_or_default();
+                }
+                b"time" | b"fade" => {
+                    let times = chunk.chunks_exact(4).map(|bytes| i32::from_le_bytes(bytes.try_into().unwrap()));
+                    for (track, time) in self.tracks.iter_mut().zip(times) {
+                        // Negative times mean the player's default
+                        let time = u32::try_from(time).ok();
+                        if id == b"time" {
+                            track.length_ms = time;
+                        } else {
+                            track.fade_ms = time;
+                        }
+                    }
+                }
+                b"tlbl" => {
+                    let labels = chunk.split(|byte| *byte == 0).map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+                    for (track, label) in self.tracks.iter_mut().zip(labels) {
+                        track.title = Some(label).filter(|label| !label.is_empty());
+                    }
+                }
+                b"NEND" => break,
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(CartridgeError::InvalidNsf(format!(
+                        "unsupported required chunk {}",
+                        String::from_utf8_lossy(id)
+                    )));
+                }
+                _ => {} // Optional chunks the player doesn't use, e.g. playlists
+            }
+        }
+
+        if !have_info {
+            return Err(CartridgeError::InvalidNsf("missing INFO chunk".into()));
+        }
+
+        Ok(())
+    }
+
+    fn validate(self) -> Result<NsfFile, CartridgeError> {
+        if self.data.is_empty() {
+            return Err(CartridgeError::InvalidNsf("no program data".into()));
+        }
+        if self.load_address < 0x6000 || (self.load_address < 0x8000 && !self.chips.contains(NsfChips::FDS)) {
+            return Err(CartridgeError::InvalidNsf(format!("load address ${:04X} is outside of PRG", self.load_address)));
+        }
+
+        Ok(self)
+    }
+}
+
+impl NsfFile {
+    /// Whether the tune switches 4KB banks through $5FF8-$5FFF
+    pub fn is_bankswitched(&self) -> bool {
+        self.banks.is_some()
+    }
+
+    /// The program data split into 4KB banks. Bankswitched tunes are padded at the front so that the load address falls at
+    /// the same offset within its bank, others are laid out as they'd sit in memory from the start of the load address's bank.
+    pub fn prg_image(&self) -> Vec<u8> {
+        let padding = (self.load_address & 0x0FFF) as usize;
+        let mut image = vec![0; padding];
+        image.extend_from_slice(&self.data);
+        image.resize(image.len().next_multiple_of(NSF_BANK_SIZE), 0);
+        image
+    }
+
+    /// Banks initially mapped into the 4KB slots of $6000-$FFFF. Only FDS tunes use the first two, from $5FF6/$5FF7.
+    pub fn initial_banks(&self) -> [u8; 10] {
+        match self.banks {
+            Some(banks) => [
+                banks[6], banks[7], banks[0], banks[1], banks[2], banks[3], banks[4], banks[5], banks[6], banks[7],
+            ],
+            None => {
+                let first_slot = (self.load_address >> 12) as i32 - 6;
+                // Slots before the load address get a bank past the end of the image, which reads as open bus
+                std::array::from_fn(|slot| u8::try_from(slot as i32 - first_slot).unwrap_or(0xFF))
+            }
+        }
+    }
+}
+
+/// A play period of 0 isn't usable, players fall back to the NTSC frame rate
+fn play_period(period_us: u16) -> u16 {
+    if period_us == 0 { DEFAULT_PLAY_PERIOD_US } else { period_us }
+}
+
+/// Header strings are null-terminated within their 32-byte field
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nsf_header() -> Vec<u8> {
+        let mut header = vec![0; NSF_HEADER_SIZE];
+        header[..5].copy_from_slice(NSF_TAG);
+        header[0x05] = 1;
+        header[0x06] = 3; // 3 tracks, starting with the second
+        header[0x07] = 2;
+        header[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x03, 0x80, 0x06, 0x80]);
+        header[0x0E..0x13].copy_from_slice(b"Title");
+        header[0x6E..0x70].copy_from_slice(&16666u16.to_le_bytes());
+        header[0x7B] = 0x21;
+        header
+    }
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u32).to_le_bytes(), id, data].concat()
+    }
+
+    #[test]
+    fn parses_nsf_header() {
+        let data = [nsf_header(), vec![0x60; 16]].concat();
+        let file = NsfFile::parse(&data).unwrap();
+
+        assert_eq!(file.title, "Title");
+        assert_eq!((file.load_address, file.init_address, file.play_address), (0x8000, 0x8003, 0x8006));
+        assert_eq!(file.play_period_us, 16666);
+        assert_eq!(file.banks, None);
+        assert_eq!(file.chips, NsfChips::VRC6 | NsfChips::SUNSOFT_5B);
+        assert_eq!(file.starting_track, 1);
+        assert_eq!(file.tracks.len(), 3);
+        assert_eq!(file.data.len(), 16);
+    }
+
+    #[test]
+    fn parses_nsf2_metadata() {
+        let mut header = nsf_header();
+        header[0x05] = 2;
+        header[0x7D] = 16;
+
+        let times = [60_000i32, -1, 1000].iter().flat_map(|time| time.to_le_bytes()).collect::<Vec<_>>();
+        let data = [header, vec![0x60; 16], chunk(b"time", &times), chunk(b"tlbl", b"Intro\0\0Boss\0")].concat();
+        let file = NsfFile::parse(&data).unwrap();
+
+        assert_eq!(file.data.len(), 16);
+        assert_eq!(
+            file.tracks[0],
+            NsfTrack {
+                title: Some("Intro".into()),
+                length_ms: Some(60_000),
+                fade_ms: None
+            }
+        );
+        assert_eq!(file.tracks[1], NsfTrack::default());
+        assert_eq!(file.tracks[2].title.as_deref(), Some("Boss"));
+    }
+
+    #[test]
+    fn parses_nsfe() {
+        let info = [0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x00, 0x04, 0x02, 0x01];
+        let data = [
+            NSFE_TAG.to_vec(),
+            chunk(b"INFO", &info),
+            chunk(b"DATA", &[0x60; 8]),
+            chunk(b"BANK", &[0, 1]),
+            chunk(b"auth", b"Song\0Composer\0(c)\0Ripper\0"),
+            chunk(b"fade", &2000i32.to_le_bytes()),
+            chunk(b"plst", &[1, 0]),
+            chunk(b"NEND", &[]),
+        ]
+        .concat();
+        let file = NsfFile::parse(&data).unwrap();
+
+        assert_eq!(file.artist, "Composer");
+        assert_eq!(file.chips, NsfChips::FDS);
+        assert_eq!(file.banks, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(file.starting_track, 1);
+        assert_eq!(file.tracks[0].fade_ms, Some(2000));
+        assert_eq!(file.data, [0x60; 8]);
+    }
+
+    #[test]
+    fn defaults_zero_play_rate() {
+        let mut header = nsf_header();
+        header[0x6E..0x70].fill(0);
+        let file = NsfFile::parse(&[header, vec![0x60; 16]].concat()).unwrap();
+        assert_eq!(file.play_period_us, DEFAULT_PLAY_PERIOD_US);
+
+        let info = [0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x00, 0x00, 0x01];
+        let nsfe = |rate: u16| {
+            [
+                NSFE_TAG.to_vec(),
+                chunk(b"INFO", &info),
+                chunk(b"DATA", &[0x60]),
+                chunk(b"RATE", &rate.to_le_bytes()),
+            ]
+            .concat()
+        };
+        assert_eq!(NsfFile::parse(&nsfe(0)).unwrap().play_period_us, DEFAULT_PLAY_PERIOD_US);
+        assert_eq!(NsfFile::parse(&nsfe(20000)).unwrap().play_period_us, 20000);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(NsfFile::parse(b"NESM\x1A").is_err());
+        assert!(NsfFile::parse(&nsf_header()).is_err()); // No program data
+
+        let missing_info = [NSFE_TAG.to_vec(), chunk(b"DATA", &[0x60])].concat();
+        assert!(NsfFile::parse(&missing_info).is_err());
+
+        let unknown_required = [NSFE_TAG.to_vec(), chunk(b"INFO", &[0x00, 0x80, 0, 0x80, 0, 0x80, 0, 0, 1]), chunk(b"XTRA", &[])].concat();
+        assert!(NsfFile::parse(&unknown_required).is_err());
+    }
+}
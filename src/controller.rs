@@ -1,3 +1,6 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
 bitflags::bitflags! {
     #[derive(Copy, Clone)]
     pub struct ControllerButton: u8 {
@@ -57,3 +60,19 @@ impl Default for Controller {
         Self::new()
     }
 }
+
+impl SaveState for Controller {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.button_index);
+        writer.write_u8(self.button_states.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.strobe = reader.read_bool()?;
+        self.button_index = reader.read_u8()?;
+        self.button_states = ControllerButton::from_bits_truncate(reader.read_u8()?);
+
+        Ok(())
+    }
+}
@@ -4,7 +4,9 @@ pub mod opcode;
 pub mod trace;
 
 use super::bus::Bus;
+use crate::state::{SaveState, StateReader, StateWriter};
 use addressing::AddressingMode;
+use anyhow::{Result, anyhow};
 use opcode::{OPCODES_MAP, Opcode};
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -70,6 +72,15 @@ impl Cpu {
         self.cycles += 7;
     }
 
+    /// Jumps to a subroutine as if it had been called with JSR from just before `return_address`, abandoning any
+    /// instruction in flight. Used by the NSF player to call a tune's INIT and PLAY routines.
+    pub fn call_subroutine(&mut self, address: u16, return_address: u16) {
+        self.stack_push_u16(return_address.wrapping_sub(1));
+        self.pc = address;
+        self.current_instruction = None;
+        self.cycles_remaining = 0;
+    }
+
     pub fn step(&mut self) -> u64 {
         let start_cycles = self.cycles;
 
@@ -235,3 +246,48 @@ impl Cpu {
         self.cycles_remaining = 7; // NMI/IRQ takes 7 cycles
     }
 }
+
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.pc);
+        writer.write_u8(self.sp);
+        writer.write_u8(self.a);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u8(self.status.bits());
+        writer.write_bool(self.halted);
+        writer.write_u64(self.cycles);
+
+        // The in-flight instruction is stored by opcode byte and restored from the opcode table
+        writer.write_bool(self.current_instruction.is_some());
+        if let Some((opcode, operand_pc)) = self.current_instruction {
+            writer.write_u8(opcode.opcode);
+            writer.write_u16(operand_pc);
+        }
+        writer.write_u8(self.cycles_remaining);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u8()?;
+        self.a = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.status = StatusFlags::from_bits_truncate(reader.read_u8()?);
+        self.halted = reader.read_bool()?;
+        self.cycles = reader.read_u64()?;
+
+        self.current_instruction = if reader.read_bool()? {
+            let opcode = reader.read_u8()?;
+            let opcode = **OPCODES_MAP
+                .get(&opcode)
+                .ok_or_else(|| anyhow!("Unknown opcode in save state: {:#2X}", opcode))?;
+            Some((opcode, reader.read_u16()?))
+        } else {
+            None
+        };
+        self.cycles_remaining = reader.read_u8()?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::apu::Apu;
+use crate::cartridge::ExpansionChannel;
 use crate::emulator::NTSC_CPU_FREQUENCY;
 use imgui::{TreeNodeFlags, Ui};
 
@@ -75,14 +76,14 @@ impl ApuDebugPanel {
         }
     }
 
-    pub fn render(&mut self, ui: &Ui, apu: &mut Apu) {
+    pub fn render(&mut self, ui: &Ui, apu: &mut Apu, expansion_channels: &[ExpansionChannel]) {
         if !ui.collapsing_header("APU Debug", TreeNodeFlags::DEFAULT_OPEN) {
             return;
         }
 
         if let Some(_tab_bar) = ui.tab_bar("apu_tabs") {
             if let Some(_tab) = ui.tab_item("Channels") {
-                self.render_channel_status(ui, apu);
+                self.render_channel_status(ui, apu, expansion_channels);
             }
 
             if let Some(_tab) = ui.tab_item("Visualisation") {
@@ -95,7 +96,7 @@ impl ApuDebugPanel {
         }
     }
 
-    fn render_channel_status(&self, ui: &Ui, apu: &Apu) {
+    fn render_channel_status(&self, ui: &Ui, apu: &Apu, expansion_channels: &[ExpansionChannel]) {
         ui.text("Channel Status");
         ui.separator();
 
@@ -118,6 +119,12 @@ impl ApuDebugPanel {
         ui.tree_node_config("DMC").default_open(true).build(|| {
             self.render_dmc_channel_status(ui, apu);
         });
+
+        for channel in expansion_channels {
+            ui.tree_node_config(channel.name).default_open(true).build(|| {
+                self.render_expansion_channel_status(ui, channel);
+            });
+        }
     }
 
     fn render_pulse_channel_status(&self, ui: &Ui, apu: &Apu, channel: usize) {
@@ -290,6 +297,27 @@ impl ApuDebugPanel {
         ui.columns(1, "", false);
     }
 
+    fn render_expansion_channel_status(&self, ui: &Ui, channel: &ExpansionChannel) {
+        ui.columns(2, format!("{}_cols", channel.name), true);
+
+        ui.text("Enabled:");
+        ui.next_column();
+        text_boolean!(ui, channel.enabled, "Yes", "No");
+        ui.next_column();
+
+        ui.text("Timer Period:");
+        ui.next_column();
+        ui.text(format!("{} ({:.1} Hz)", channel.period, channel.frequency));
+        ui.next_column();
+
+        ui.text("Output:");
+        ui.next_column();
+        self.render_value_bar(ui, channel.output);
+        ui.next_column();
+
+        ui.columns(1, "", false);
+    }
+
     fn render_value_bar(&self, ui: &Ui, value: f32) {
         let width = ui.content_region_avail()[0];
         let height = 20.0;
@@ -420,6 +448,10 @@ impl ApuDebugPanel {
             ("Triangle", "triangle", &mut audio_processor.channel_volumes.triangle),
             ("Noise", "noise", &mut audio_processor.channel_volumes.noise),
             ("DMC", "dmc", &mut audio_processor.channel_volumes.dmc),
+            ("Expansion", "expansion", &mut audio_processor.channel_volumes.expansion),
+            ("VRC6 Pulse 1", "vrc6_pulse1", &mut audio_processor.channel_volumes.vrc6_pulse_1),
+            ("VRC6 Pulse 2", "vrc6_pulse2", &mut audio_processor.channel_volumes.vrc6_pulse_2),
+            ("VRC6 Sawtooth", "vrc6_sawtooth", &mut audio_processor.channel_volumes.vrc6_sawtooth),
         ];
 
         for (label, prefix, volume) in channels {
@@ -2,9 +2,11 @@ use super::bus::Bus;
 use super::cartridge::Cartridge;
 use super::cpu::Cpu;
 use crate::apu::Apu;
-use crate::audio::AudioOutput;
+use crate::audio::AudioSink;
 use crate::debug::ApuDebugPanel;
 use crate::ppu::Ppu;
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::{Result, ensure};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
@@ -60,7 +62,8 @@ pub struct Emulator {
     pub cartridge: Rc<RefCell<Cartridge>>,
     pub bus: Rc<RefCell<Bus>>,
 
-    pub audio: Option<AudioOutput>,
+    pub audio: Option<Box<dyn AudioSink>>,
+    pub volume: f32, // Applied to samples on their way to the audio output, e.g. for the NSF player's fade-out
 
     pub apu_debug_panel: ApuDebugPanel,
 }
@@ -81,13 +84,42 @@ impl Emulator {
             bus,
 
             audio: None,
+            volume: 1.0,
 
             apu_debug_panel: ApuDebugPanel::default(),
         }
     }
 
-    pub fn connect_audio(&mut self, audio: AudioOutput) {
-        self.audio = Some(audio);
+    pub fn connect_audio(&mut self, audio: impl AudioSink + 'static) {
+        self.audio = Some(Box::new(audio));
+    }
+
+    /// Captures the complete machine state as a versioned binary blob tied to the loaded ROM
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_header(self.cartridge.borrow().rom_checksum());
+
+        self.cpu.save_state(&mut writer);
+        self.bus.borrow().save_state(&mut writer);
+        self.ppu.borrow().save_state(&mut writer);
+        self.apu.borrow().save_state(&mut writer);
+        self.cartridge.borrow().save_state(&mut writer);
+
+        writer.into_bytes()
+    }
+
+    /// Restores a state produced by `save_state`. The header is validated before anything is touched, but a state that
+    /// is corrupt beyond the header can leave the machine partially restored.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = StateReader::with_header(data, self.cartridge.borrow().rom_checksum())?;
+
+        self.cpu.load_state(&mut reader)?;
+        self.bus.borrow_mut().load_state(&mut reader)?;
+        self.ppu.borrow_mut().load_state(&mut reader)?;
+        self.apu.borrow_mut().load_state(&mut reader)?;
+        self.cartridge.borrow_mut().load_state(&mut reader)?;
+
+        ensure!(reader.is_empty(), "Save state contains unexpected trailing data");
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -123,29 +155,44 @@ impl Emulator {
                 }
             }
 
-            for _ in 0..cpu_cycles {
-                let mut apu = self.apu.borrow_mut();
-                apu.clock(|address| self.bus.borrow_mut().read(address));
+            self.clock_cpu_devices(cpu_cycles);
 
-                let sample = apu.output();
+            if frame_complete {
+                break;
+            }
+        }
 
-                self.apu_debug_panel.update(&apu, sample);
+        accumulated_cycles
+    }
 
-                if let Some(audio) = &mut self.audio {
-                    audio.push_source_sample(sample);
-                }
+    /// Clocks the APU and cartridge for cycles the CPU has run, pushing the audio they produce and raising their IRQs
+    pub fn clock_cpu_devices(&mut self, cpu_cycles: u64) {
+        for _ in 0..cpu_cycles {
+            let mut apu = self.apu.borrow_mut();
+            apu.clock(|address| self.bus.borrow_mut().read(address));
 
-                if apu.irq_pending() {
-                    self.bus.borrow_mut().trigger_irq();
-                }
+            let expansion = {
+                let mut cartridge = self.cartridge.borrow_mut();
+                cartridge.clock_cpu();
+                cartridge.audio_output(&apu.audio_processor.channel_volumes)
+            };
+
+            let sample = apu.output(expansion);
+
+            self.apu_debug_panel.update(&apu, sample);
+
+            if let Some(audio) = &mut self.audio {
+                audio.push_source_sample(sample * self.volume);
             }
 
-            if frame_complete {
-                break;
+            if apu.irq_pending() {
+                self.bus.borrow_mut().trigger_irq();
             }
         }
 
-        accumulated_cycles
+        if self.cartridge.borrow().irq_pending() {
+            self.bus.borrow_mut().trigger_irq();
+        }
     }
 
     pub fn run<F>(&mut self, mut frame_callback: F)
@@ -6,4 +6,6 @@ pub mod controller;
 pub mod cpu;
 mod debug;
 pub mod emulator;
+pub mod player;
 pub mod ppu;
+pub mod state;
@@ -8,14 +8,16 @@ pub mod controller;
 pub mod cpu;
 pub mod debug;
 pub mod emulator;
+pub mod player;
 pub mod ppu;
+pub mod state;
 
-use anyhow::{Context as _, Result};
-use audio::AudioOutput;
-use cartridge::Cartridge;
+use anyhow::{Context as _, Result, bail};
+use audio::{AudioOutput, WavFormat, WavWriter};
+use cartridge::{Cartridge, NsfFile};
 use clap::Parser;
 use controller::ControllerButton;
-use emulator::Emulator;
+use emulator::{Emulator, TimingController};
 use glow::HasContext;
 use glutin::config::ConfigTemplateBuilder;
 use glutin::context::{ContextAttributesBuilder, NotCurrentGlContext, PossiblyCurrentContext};
@@ -26,10 +28,11 @@ use glutin_winit::DisplayBuilder;
 use imgui::{Condition, Context, FontSource};
 use imgui_glow_renderer::AutoRenderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use ppu::render::frame::Frame;
+use player::NsfPlayer;
 use raw_window_handle::HasWindowHandle;
 use std::collections::HashMap;
 use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use winit::dpi::PhysicalSize;
 use winit::event::{ElementState, Event, WindowEvent};
@@ -46,6 +49,8 @@ const NES_ASPECT_RATIO: f32 = NES_WIDTH as f32 / NES_HEIGHT as f32;
 const DEFAULT_WINDOW_WIDTH: u32 = NES_WIDTH * 4;
 const DEFAULT_WINDOW_HEIGHT: u32 = NES_HEIGHT * 4;
 
+const SRAM_FLUSH_INTERVAL_FRAMES: u32 = 300; // Roughly every 5 seconds
+
 #[derive(Parser, Debug)]
 struct Args {
     #[arg()]
@@ -53,6 +58,43 @@ struct Args {
 
     #[arg(short, long)]
     debug: bool,
+
+    /// Draw every sprite on a scanline instead of the hardware limit of 8, reducing flicker
+    #[arg(long)]
+    no_sprite_limit: bool,
+
+    /// Mix the Namco 163's channels evenly instead of switching between them every 15 CPU cycles like the hardware,
+    /// which avoids aliasing when many channels are enabled
+    #[arg(long)]
+    average_n163: bool,
+
+    /// Famicom Disk System BIOS ROM (8KB), needed to run .fds disk images
+    #[arg(long)]
+    fds_bios: Option<String>,
+
+    /// Track to start an NSF or NSFe file on, counting from 1
+    #[arg(long)]
+    track: Option<usize>,
+
+    /// Run without a window and write the audio to this WAV file, for the length given by --frames or --seconds
+    #[arg(long)]
+    wav: Option<String>,
+
+    /// Frames to run when writing a WAV file
+    #[arg(long, conflicts_with = "seconds")]
+    frames: Option<u64>,
+
+    /// Seconds of audio to write to a WAV file
+    #[arg(long)]
+    seconds: Option<f64>,
+
+    /// Sample rate of the WAV file
+    #[arg(long, default_value_t = 44100)]
+    sample_rate: u32,
+
+    /// Write 32-bit float samples to the WAV file instead of 16-bit
+    #[arg(long)]
+    float: bool,
 }
 
 fn create_window(debug: bool) -> Result<(EventLoop<()>, Window, Surface<WindowSurface>, PossiblyCurrentContext)> {
@@ -121,6 +163,12 @@ fn imgui_init(window: &Window) -> (WinitPlatform, Context) {
 
 fn main() -> Result<()> {
     let args = Args::try_parse().context("Failed to parse command line arguments")?;
+    if let Some(wav_path) = &args.wav {
+        return render_wav(&args, wav_path);
+    }
+    if NsfPlayer::is_nsf_file(&args.rom) {
+        return run_player(&args);
+    }
 
     let (mut event_loop, window, surface, gl_context) = create_window(args.debug).context("Failed to create window")?;
     let (mut imgui_platform, mut imgui_context) = imgui_init(&window);
@@ -136,10 +184,16 @@ fn main() -> Result<()> {
     let mut debug_visible = args.debug;
     let mut active_palette: u8 = 0;
 
-    let mut frame = Frame::new();
+    let (mut cartridge, sram_path) = load_cartridge(&args)?;
+    if cartridge.load_sram_file(&sram_path).context("Failed to load save file")? {
+        println!("Loaded save file: {}", sram_path.display());
+    }
 
-    let cartridge = Cartridge::load(args.rom.as_str()).context("Failed to load ROM file into Cartridge")?;
     let mut emulator = Emulator::new(cartridge);
+    emulator.ppu.borrow_mut().set_sprite_limit(!args.no_sprite_limit);
+    let mut frames_since_sram_flush = 0;
+
+    let state_path = Path::new(&args.rom).with_extension("state");
 
     let audio = AudioOutput::new(emulator::NTSC_CPU_FREQUENCY, true).context("Failed to create audio output")?;
     emulator.connect_audio(audio);
@@ -163,6 +217,9 @@ fn main() -> Result<()> {
                                     KeyCode::Escape => should_exit = true,
                                     KeyCode::F12 => debug_visible = !debug_visible,
                                     KeyCode::KeyP => active_palette = (active_palette + 1) & 0x07,
+                                    KeyCode::F5 => save_state_file(emulator, &state_path),
+                                    KeyCode::F9 => load_state_file(emulator, &state_path),
+                                    KeyCode::F7 => switch_disk_side(emulator),
                                     _ => {}
                                 }
                             }
@@ -185,6 +242,12 @@ fn main() -> Result<()> {
                 }
             });
 
+        frames_since_sram_flush += 1;
+        if should_exit || frames_since_sram_flush >= SRAM_FLUSH_INTERVAL_FRAMES {
+            flush_sram(emulator, &sram_path);
+            frames_since_sram_flush = 0;
+        }
+
         if should_exit {
             std::process::exit(0);
         }
@@ -293,8 +356,7 @@ fn main() -> Result<()> {
                         text_bitflags(ui, "CTRL", "VPHBSINN", ppu.ctrl.bits());
                         text_bitflags(ui, "MASK", "BGRsbMmG", ppu.mask.bits());
                         text_bitflags(ui, "STATUS", "VSO-----", ppu.status.bits());
-                        ui.text(format!("SCROLL: {:02X} {:02X}", ppu.render_scroll_x, ppu.render_scroll_y));
-                        ui.text(format!("ADDR: {:04X}", ppu.addr.get()));
+                        ui.text(format!("V: {:04X} T: {:04X} X: {}", ppu.loopy.v, ppu.loopy.t, ppu.loopy.fine_x));
 
                         ui.separator();
 
@@ -306,8 +368,9 @@ fn main() -> Result<()> {
                         draw_pattern_table(ui, pattern_table_textures[1], 128.0 * 2.5);
                     }
 
+                    let expansion_channels = emulator.cartridge.borrow().audio_channels();
                     let mut apu = emulator.apu.borrow_mut();
-                    emulator.apu_debug_panel.render(ui, &mut apu);
+                    emulator.apu_debug_panel.render(ui, &mut apu, &expansion_channels);
                 });
         }
 
@@ -319,15 +382,14 @@ fn main() -> Result<()> {
 
         let bus = emulator.cpu.bus.borrow_mut();
         let ppu = bus.ppu.borrow_mut();
-        ppu::render::render(&ppu, &mut frame);
-        update_rgb_texture(gl, nes_texture, NES_WIDTH as i32, NES_HEIGHT as i32, &frame.data);
+        update_rgb_texture(gl, nes_texture, NES_WIDTH as i32, NES_HEIGHT as i32, &ppu.frame_buffer().data);
 
         if debug_visible {
             let palette_data = populate_palette_texture(&ppu.palette_table);
             update_rgb_texture(gl, palette_texture, 8, 4, &palette_data);
 
             for (i, pattern_table_texture) in pattern_table_textures.iter().enumerate() {
-                let pattern_table_data = populate_pattern_table_texture(i, &mut *ppu.cartridge.borrow_mut(), &ppu.palette_table, active_palette);
+                let pattern_table_data = populate_pattern_table_texture(i, &mut ppu.cartridge.borrow_mut(), &ppu.palette_table, active_palette);
                 update_rgb_texture(gl, *pattern_table_texture, 128, 128, &pattern_table_data);
             }
         }
@@ -341,6 +403,214 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Plays an NSF or NSFe file, showing the tune's details in place of the game screen. Left/Right change track.
+fn run_player(args: &Args) -> Result<()> {
+    let mut player = load_player(args)?;
+
+    let (mut event_loop, window, surface, gl_context) = create_window(false).context("Failed to create window")?;
+    let (mut imgui_platform, mut imgui_context) = imgui_init(&window);
+
+    let gl = unsafe { glow::Context::from_loader_function_cstr(|s| gl_context.display().get_proc_address(s).cast()) };
+    let mut imgui_renderer = AutoRenderer::new(gl, &mut imgui_context).context("Failed to create ImGui renderer")?;
+
+    let audio = AudioOutput::new(emulator::NTSC_CPU_FREQUENCY, true).context("Failed to create audio output")?;
+    player.emulator.connect_audio(audio);
+
+    let mut timing_controller = TimingController::default();
+
+    loop {
+        let cycles = player.run_frame();
+        let mut should_exit = false;
+
+        #[allow(deprecated)]
+        event_loop.pump_events(Some(Duration::ZERO), |event, _window_target| {
+            imgui_platform.handle_event(imgui_context.io_mut(), &window, &event);
+
+            if let Event::WindowEvent { event: window_event, .. } = event {
+                match window_event {
+                    WindowEvent::CloseRequested => should_exit = true,
+                    WindowEvent::KeyboardInput { event: key_event, .. } if key_event.state == ElementState::Pressed => match key_event.physical_key {
+                        PhysicalKey::Code(KeyCode::Escape) => should_exit = true,
+                        PhysicalKey::Code(KeyCode::ArrowLeft) => player.previous_track(),
+                        PhysicalKey::Code(KeyCode::ArrowRight) => player.next_track(),
+                        _ => {}
+                    },
+                    WindowEvent::Resized(physical_size) => surface.resize(
+                        &gl_context,
+                        NonZeroU32::new(physical_size.width).unwrap(),
+                        NonZeroU32::new(physical_size.height).unwrap(),
+                    ),
+                    WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        imgui_context.io_mut().font_global_scale = scale_factor as f32;
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        if should_exit {
+            return Ok(());
+        }
+
+        imgui_platform.prepare_frame(imgui_context.io_mut(), &window).unwrap();
+        let ui = imgui_context.frame();
+
+        ui.window("##player")
+            .position([0.0, 0.0], Condition::Always)
+            .size(ui.io().display_size, Condition::Always)
+            .movable(false)
+            .resizable(false)
+            .collapsible(false)
+            .title_bar(false)
+            .build(|| {
+                let file = &player.file;
+                ui.text(&file.title);
+                ui.text(&file.artist);
+                ui.text(&file.copyright);
+
+                ui.separator();
+
+                let track_title = player.track_info().title.as_deref().unwrap_or_default();
+                ui.text(format!("Track {}/{} {}", player.track() + 1, player.track_count(), track_title));
+                ui.text(format!("{} / {}", format_duration(player.elapsed()), format_duration(player.track_length())));
+                ui.text("Left/Right: change track");
+
+                if args.debug {
+                    ui.separator();
+
+                    let expansion_channels = player.emulator.cartridge.borrow().audio_channels();
+                    let mut apu = player.emulator.apu.borrow_mut();
+                    player.emulator.apu_debug_panel.render(ui, &mut apu, &expansion_channels);
+                }
+            });
+
+        let gl = imgui_renderer.gl_context();
+        unsafe {
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+
+        let draw_data = imgui_context.render();
+        imgui_renderer.render(draw_data).unwrap();
+
+        surface.swap_buffers(&gl_context).unwrap();
+
+        timing_controller.synchronize(cycles);
+    }
+}
+
+/// Loads the cartridge or disk image given on the command line, along with the path its save file goes in
+fn load_cartridge(args: &Args) -> Result<(Cartridge, PathBuf)> {
+    let (mut cartridge, sram_path) = if Cartridge::is_disk_image(&args.rom) {
+        let bios = args.fds_bios.as_deref().context("Disk images need the FDS BIOS, pass it with --fds-bios")?;
+        let cartridge = Cartridge::load_fds(&args.rom, bios).context("Failed to load disk image")?;
+        (cartridge, Cartridge::disk_diff_path(&args.rom))
+    } else {
+        let cartridge = Cartridge::load(args.rom.as_str()).context("Failed to load ROM file into Cartridge")?;
+        (cartridge, Cartridge::sram_path(&args.rom))
+    };
+
+    cartridge.set_average_multiplexed_channels(args.average_n163);
+    Ok((cartridge, sram_path))
+}
+
+fn load_player(args: &Args) -> Result<NsfPlayer> {
+    let data = std::fs::read(&args.rom).with_context(|| format!("Failed to read NSF file: {}", args.rom))?;
+    let file = NsfFile::parse(&data).context("Failed to load NSF file")?;
+
+    let mut player = NsfPlayer::new(file);
+    player.set_average_multiplexed_channels(args.average_n163);
+    if let Some(track) = args.track {
+        player.start_track(track.saturating_sub(1));
+    }
+
+    Ok(player)
+}
+
+/// Runs the ROM or NSF file without a window or sound card, writing its audio to a WAV file
+fn render_wav(args: &Args, wav_path: &str) -> Result<()> {
+    let (frame_limit, cycle_limit) = match (args.frames, args.seconds) {
+        (Some(frames), _) => (frames, u64::MAX),
+        (None, Some(seconds)) => (u64::MAX, (seconds * emulator::NTSC_CPU_FREQUENCY) as u64),
+        (None, None) => bail!("Writing a WAV file needs a length, pass --frames or --seconds"),
+    };
+
+    let format = if args.float { WavFormat::Float32 } else { WavFormat::Pcm16 };
+    let create_wav = || WavWriter::create(wav_path, emulator::NTSC_CPU_FREQUENCY, args.sample_rate, format);
+
+    let audio = if NsfPlayer::is_nsf_file(&args.rom) {
+        let mut player = load_player(args)?;
+        player.emulator.connect_audio(create_wav()?);
+        run_headless(frame_limit, cycle_limit, || player.run_frame());
+        player.emulator.audio.take()
+    } else {
+        let mut emulator = Emulator::new(load_cartridge(args)?.0);
+        emulator.connect_audio(create_wav()?);
+        emulator.reset();
+        run_headless(frame_limit, cycle_limit, || emulator.run_frame());
+        emulator.audio.take()
+    };
+
+    if let Some(mut audio) = audio {
+        audio.finish()?;
+    }
+
+    println!("Wrote audio: {}", wav_path);
+    Ok(())
+}
+
+/// Runs frames as fast as possible until either limit is reached
+fn run_headless(frame_limit: u64, cycle_limit: u64, mut run_frame: impl FnMut() -> u64) {
+    let mut frames = 0;
+    let mut cycles = 0;
+
+    while frames < frame_limit && cycles < cycle_limit {
+        cycles += run_frame();
+        frames += 1;
+    }
+}
+
+/// Formats a track position as m:ss
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn flush_sram(emulator: &Emulator, sram_path: &Path) {
+    let mut cartridge = emulator.cartridge.borrow_mut();
+    if !cartridge.sram_dirty() {
+        return;
+    }
+
+    if let Err(err) = cartridge.save_sram_file(sram_path) {
+        eprintln!("{:#}", err);
+    }
+}
+
+fn switch_disk_side(emulator: &Emulator) {
+    if let Some(side) = emulator.cartridge.borrow_mut().switch_disk_side() {
+        println!("Inserting disk {} side {}", side / 2 + 1, if side % 2 == 0 { 'A' } else { 'B' });
+    }
+}
+
+fn save_state_file(emulator: &Emulator, state_path: &Path) {
+    match std::fs::write(state_path, emulator.save_state()) {
+        Ok(()) => println!("Saved state: {}", state_path.display()),
+        Err(err) => eprintln!("Failed to write save state {}: {}", state_path.display(), err),
+    }
+}
+
+fn load_state_file(emulator: &mut Emulator, state_path: &Path) {
+    let result = std::fs::read(state_path)
+        .with_context(|| format!("Failed to read save state {}", state_path.display()))
+        .and_then(|data| emulator.load_state(&data));
+
+    match result {
+        Ok(()) => println!("Loaded state: {}", state_path.display()),
+        Err(err) => eprintln!("{:#}", err),
+    }
+}
+
 fn create_rgb_texture(gl: &glow::Context, width: i32, height: i32) -> glow::Texture {
     unsafe {
         let texture = gl.create_texture().unwrap();
@@ -415,8 +685,8 @@ fn populate_pattern_table_texture(pattern_table_idx: usize, cartridge: &mut Cart
 
         let tile_address = (bank + tile_n * 16) as u16;
         let mut tile = [0u8; 16];
-        for j in 0..16 {
-            tile[j] = cartridge.ppu_read(tile_address + j as u16);
+        for (j, byte) in tile.iter_mut().enumerate() {
+            *byte = cartridge.ppu_peek(tile_address + j as u16);
         }
 
         for y in 0..=7 {
@@ -7,6 +7,7 @@ pub mod cpu;
 pub mod debug;
 pub mod emulator;
 pub mod ppu;
+pub mod state;
 
 use crate::apu::Apu;
 use crate::ppu::Ppu;
@@ -0,0 +1,261 @@
+use crate::cartridge::{Cartridge, NsfChips, NsfFile, NsfTrack};
+use crate::emulator::{Emulator, NTSC_CPU_FREQUENCY};
+use std::path::Path;
+use std::time::Duration;
+
+const DEFAULT_TRACK_LENGTH_MS: u32 = 150_000;
+const DEFAULT_FADE_MS: u32 = 8_000;
+const CYCLES_PER_FRAME: u64 = 29781; // An NTSC frame, for pacing the player alongside the UI
+
+// Where INIT and PLAY return to. It's never executed, the player stops stepping the CPU once it gets here.
+const IDLE_ADDRESS: u16 = 0x5FF5;
+
+/// Plays an NSF tune: the program's INIT routine is called for the selected track, then PLAY at the file's rate, with
+/// only the CPU, APU and cartridge running. Tracks play for the length from the file's metadata (or a default), fade
+/// out and move on to the next one.
+pub struct NsfPlayer {
+    pub file: NsfFile,
+    pub emulator: Emulator,
+
+    track: usize,
+    average_multiplexed_channels: bool,
+    play_period_cycles: f64,
+    cycles_until_play: f64,
+    elapsed_cycles: u64, // Since the track started
+}
+
+impl NsfPlayer {
+    pub fn new(file: NsfFile) -> Self {
+        let track = file.starting_track as usize;
+        let play_period_cycles = file.play_period_us as f64 * NTSC_CPU_FREQUENCY / 1_000_000.0;
+        let emulator = Emulator::new(Cartridge::from_nsf(&file));
+
+        let mut player = Self {
+            file,
+            emulator,
+
+            track,
+            average_multiplexed_channels: false,
+            play_period_cycles,
+            cycles_until_play: 0.0,
+            elapsed_cycles: 0,
+        };
+
+        player.start_track(track);
+        player
+    }
+
+    /// Whether the `path` looks like an NSF or NSFe file, going by its extension
+    pub fn is_nsf_file(path: impl AsRef<Path>) -> bool {
+        path.as_ref()
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("nsf") || extension.eq_ignore_ascii_case("nsfe"))
+    }
+
+    /// Restarts playback from the beginning of a track (0-based). The machine is rebuilt so the tune starts from the
+    /// power-on state, keeping the audio output along with the user's mixer and debug panel settings.
+    pub fn start_track(&mut self, track: usize) {
+        self.track = track.min(self.track_count() - 1);
+
+        let mut emulator = Emulator::new(Cartridge::from_nsf(&self.file));
+        emulator.audio = self.emulator.audio.take();
+        emulator.apu_debug_panel = std::mem::take(&mut self.emulator.apu_debug_panel);
+        emulator
+            .cartridge
+            .borrow_mut()
+            .set_average_multiplexed_channels(self.average_multiplexed_channels);
+        {
+            let mut previous = self.emulator.apu.borrow_mut();
+            let mut apu = emulator.apu.borrow_mut();
+            apu.audio_processor.channel_volumes = std::mem::take(&mut previous.audio_processor.channel_volumes);
+            apu.audio_processor.master_volume = previous.audio_processor.master_volume;
+        }
+        self.emulator = emulator;
+
+        // The sound registers start silent with the frame counter IRQ disabled
+        let cpu = &mut self.emulator.cpu;
+        for address in 0x4000..=0x4013 {
+            cpu.write(address, 0x00);
+        }
+        cpu.write(0x4015, 0x00);
+        cpu.write(0x4015, 0x0F);
+        cpu.write(0x4017, 0x40);
+        if self.file.chips.contains(NsfChips::FDS) {
+            cpu.write(0x4089, 0x80);
+            cpu.write(0x408A, 0xE8);
+        }
+
+        cpu.a = self.track as u8;
+        cpu.x = 0; // NTSC
+        cpu.call_subroutine(self.file.init_address, IDLE_ADDRESS);
+
+        self.cycles_until_play = 0.0;
+        self.elapsed_cycles = 0;
+    }
+
+    /// Mixes the Namco 163's channels evenly instead of outputting them in turn, see `Namco163Audio`
+    pub fn set_average_multiplexed_channels(&mut self, enabled: bool) {
+        self.average_multiplexed_channels = enabled;
+        self.emulator.cartridge.borrow_mut().set_average_multiplexed_channels(enabled);
+    }
+
+    pub fn next_track(&mut self) {
+        self.start_track((self.track + 1) % self.track_count());
+    }
+
+    pub fn previous_track(&mut self) {
+        self.start_track((self.track + self.track_count() - 1) % self.track_count());
+    }
+
+    /// The playing track, 0-based
+    pub fn track(&self) -> usize {
+        self.track
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.file.tracks.len()
+    }
+
+    pub fn track_info(&self) -> &NsfTrack {
+        &self.file.tracks[self.track]
+    }
+
+    /// How long the track plays before fading out
+    pub fn track_length(&self) -> Duration {
+        Duration::from_millis(self.track_info().length_ms.unwrap_or(DEFAULT_TRACK_LENGTH_MS) as u64)
+    }
+
+    pub fn fade_length(&self) -> Duration {
+        Duration::from_millis(self.track_info().fade_ms.unwrap_or(DEFAULT_FADE_MS) as u64)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.elapsed_cycles as f64 / NTSC_CPU_FREQUENCY)
+    }
+
+    /// Whether the track has played out its length and fade
+    pub fn finished(&self) -> bool {
+        self.elapsed() >= self.track_length() + self.fade_length()
+    }
+
+    /// Runs a frame's worth of CPU cycles, moving on to the next track when the current one finishes. Returns the
+    /// cycles run.
+    pub fn run_frame(&mut self) -> u64 {
+        self.run_cycles(CYCLES_PER_FRAME);
+
+        if self.finished() && self.track + 1 < self.track_count() {
+            self.next_track();
+        }
+
+        CYCLES_PER_FRAME
+    }
+
+    /// Runs the tune for at least `cycles` CPU cycles, calling PLAY whenever its period has passed and the previous
+    /// call has returned
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let target = self.elapsed_cycles + cycles;
+
+        while self.elapsed_cycles < target {
+            let idle = self.emulator.cpu.pc == IDLE_ADDRESS;
+            if idle && self.cycles_until_play <= 0.0 {
+                self.cycles_until_play += self.play_period_cycles;
+                self.emulator.cpu.call_subroutine(self.file.play_address, IDLE_ADDRESS);
+                continue;
+            }
+
+            let cycles = if idle { 1 } else { self.emulator.cpu.step() };
+            self.emulator.clock_cpu_devices(cycles);
+
+            self.cycles_until_play -= cycles as f64;
+            self.elapsed_cycles += cycles;
+        }
+
+        self.emulator.volume = self.fade_volume();
+    }
+
+    /// Volume through the fade-out, falling linearly from 1.0 at the end of the track to 0.0 at the end of the fade
+    fn fade_volume(&self) -> f32 {
+        let Some(fading) = self.elapsed().checked_sub(self.track_length()) else {
+            return 1.0;
+        };
+
+        let fade = self.fade_length();
+        if fade.is_zero() {
+            0.0
+        } else {
+            (1.0 - fading.as_secs_f32() / fade.as_secs_f32()).max(0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tune whose INIT stores the track number at $0200 and whose PLAY counts its calls at $0201
+    fn test_file(tracks: u8) -> NsfFile {
+        let mut header = vec![0; 0x80];
+        header[..5].copy_from_slice(b"NESM\x1A");
+        header[0x06] = tracks;
+        header[0x07] = 1;
+        header[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x04, 0x80]);
+        header[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+
+        let program = [0x8D, 0x00, 0x02, 0x60, 0xEE, 0x01, 0x02, 0x60];
+        NsfFile::parse(&[header, program.to_vec()].concat()).unwrap()
+    }
+
+    #[test]
+    fn calls_init_and_play() {
+        let mut player = NsfPlayer::new(test_file(3));
+        player.run_cycles(NTSC_CPU_FREQUENCY as u64 / 2);
+
+        // PLAY is called as soon as INIT returns, then 60.1 times a second
+        let cpu = &mut player.emulator.cpu;
+        assert_eq!(cpu.read(0x0200), 0);
+        assert_eq!(cpu.read(0x0201), 31);
+
+        player.next_track();
+        player.run_cycles(1000);
+        assert_eq!(player.track(), 1);
+        assert_eq!(player.emulator.cpu.read(0x0200), 1);
+        assert_eq!(player.emulator.cpu.read(0x0201), 1);
+    }
+
+    #[test]
+    fn fades_out_and_advances() {
+        let mut file = test_file(2);
+        file.tracks[0] = NsfTrack {
+            title: None,
+            length_ms: Some(100),
+            fade_ms: Some(100),
+        };
+        let mut player = NsfPlayer::new(file);
+
+        player.run_cycles(NTSC_CPU_FREQUENCY as u64 / 20);
+        assert_eq!(player.emulator.volume, 1.0);
+
+        player.run_cycles(NTSC_CPU_FREQUENCY as u64 / 10);
+        assert!((player.emulator.volume - 0.5).abs() < 0.01);
+
+        while player.track() == 0 {
+            player.run_frame();
+        }
+        assert_eq!(player.track_length(), Duration::from_millis(DEFAULT_TRACK_LENGTH_MS as u64));
+    }
+
+    #[test]
+    fn keeps_mixer_settings_between_tracks() {
+        let mut player = NsfPlayer::new(test_file(2));
+        {
+            let mut apu = player.emulator.apu.borrow_mut();
+            apu.audio_processor.channel_volumes.triangle = 0.0;
+            apu.audio_processor.master_volume = 0.5;
+        }
+
+        player.next_track();
+        let apu = player.emulator.apu.borrow();
+        assert_eq!(apu.audio_processor.channel_volumes.triangle, 0.0);
+        assert_eq!(apu.audio_processor.master_volume, 0.5);
+    }
+}
@@ -1,12 +1,17 @@
 pub mod registers;
 pub mod render;
 
-use crate::cartridge::{Cartridge, Mirroring};
-use crate::ppu::registers::addr::PpuAddrRegister;
+use crate::cartridge::{Cartridge, MappedNametable, PpuRenderState};
 use crate::ppu::registers::ctrl::PpuCtrlRegister;
+use crate::ppu::registers::loopy::PpuLoopyRegister;
 use crate::ppu::registers::mask::PpuMaskRegister;
-use crate::ppu::registers::scroll::PpuScrollRegister;
 use crate::ppu::registers::status::PpuStatusRegister;
+use crate::ppu::render::background::BackgroundPipeline;
+use crate::ppu::render::frame::Frame;
+use crate::ppu::render::palette::SYSTEM_PALETTE_COLOURS;
+use crate::ppu::render::sprites::{MAX_SPRITES_PER_SCANLINE, SpriteUnit};
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -16,8 +21,7 @@ pub struct Ppu {
     pub ctrl: PpuCtrlRegister,
     pub mask: PpuMaskRegister,
     pub status: PpuStatusRegister,
-    pub scroll: PpuScrollRegister,
-    pub addr: PpuAddrRegister,
+    pub loopy: PpuLoopyRegister,
 
     oam_addr: u8,
     oam_data: [u8; 256],
@@ -33,10 +37,10 @@ pub struct Ppu {
     nmi_output: bool,
     nmi_previous: bool,
 
-    // Latched scroll values for rendering (captured at end of vblank)
-    pub render_scroll_x: u8,
-    pub render_scroll_y: u8,
-    pub render_nametable_addr: u16,
+    background: BackgroundPipeline,
+    sprites: SpriteUnit,
+    back_buffer: Frame,  // Frame currently being drawn
+    front_buffer: Frame, // Last completed frame
 }
 
 impl Ppu {
@@ -47,8 +51,7 @@ impl Ppu {
             ctrl: PpuCtrlRegister::new(),
             mask: PpuMaskRegister::new(),
             status: PpuStatusRegister::new(),
-            scroll: PpuScrollRegister::new(),
-            addr: PpuAddrRegister::new(),
+            loopy: PpuLoopyRegister::new(),
 
             oam_addr: 0,
             oam_data: [0; 64 * 4],
@@ -64,9 +67,10 @@ impl Ppu {
             nmi_output: false,
             nmi_previous: false,
 
-            render_scroll_x: 0,
-            render_scroll_y: 0,
-            render_nametable_addr: 0x2000,
+            background: BackgroundPipeline::new(),
+            sprites: SpriteUnit::new(),
+            back_buffer: Frame::new(),
+            front_buffer: Frame::new(),
         }
     }
 
@@ -76,19 +80,12 @@ impl Ppu {
         let mut new_frame = false;
 
         if self.cycle > 340 {
-            if self.is_sprite_0_hit(self.cycle as usize) {
-                self.status.insert(PpuStatusRegister::SPRITE_0_HIT);
-            }
-
             self.cycle = 0;
             self.scanline += 1;
 
-            if self.scanline == 241 {
-                self.render_scroll_x = self.scroll.scroll_x;
-                self.render_scroll_y = self.scroll.scroll_y;
-                self.render_nametable_addr = self.ctrl.nametable_addr();
-
-                self.status.remove(PpuStatusRegister::SPRITE_0_HIT);
+            if self.scanline == 240 {
+                std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+            } else if self.scanline == 241 {
                 self.set_vblank();
             } else if self.scanline > 261 {
                 self.scanline = 0;
@@ -104,11 +101,27 @@ impl Ppu {
             self.clear_vblank();
         }
 
+        self.notify_render_state();
+        self.clock_background();
+        self.render_pixel();
+        self.clock_sprites();
+        self.clock_scroll();
         self.update_nmi_output();
 
         new_frame
     }
 
+    /// Removing the limit draws every sprite on a scanline instead of the first 8, which reduces flicker in games that
+    /// multiplex sprites but isn't how the hardware behaves
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprites.sprite_limit = enabled;
+    }
+
+    /// The most recently completed frame, swapped in at the start of the post-render scanline
+    pub fn frame_buffer(&self) -> &Frame {
+        &self.front_buffer
+    }
+
     pub fn cpu_read(&mut self, address: u16) -> u8 {
         match address {
             0x2002 => self.read_status(),
@@ -142,8 +155,7 @@ impl Ppu {
         let result = self.status.bits();
 
         self.clear_vblank();
-        self.addr.reset_latch();
-        self.scroll.reset_latch();
+        self.loopy.reset_latch();
 
         result
     }
@@ -153,8 +165,9 @@ impl Ppu {
     }
 
     fn read_data(&mut self) -> u8 {
-        let address = self.addr.get();
+        let address = self.loopy.vram_addr();
         self.increment_vram_addr();
+        self.cartridge.borrow_mut().notify_ppu_address(address);
 
         match address {
             0..=0x1FFF => {
@@ -163,21 +176,24 @@ impl Ppu {
 
                 result
             }
-            0x2000..=0x2FFF => {
+            0x2000..=0x3EFF => {
                 let result = self.internal_data_buffer;
-                self.internal_data_buffer = self.vram[self.mirror_vram_address(address) as usize];
+                self.internal_data_buffer = self.read_nametable(address);
 
                 result
             }
-            0x3000..=0x3EFF => unimplemented!("Address space 0x3000..0x3EFF is not expected to be used, attempted to read {:#x}", address),
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => self.palette_table[((address - 0x10) - 0x3F00) as usize],
-            0x3F00..=0x3FFF => self.palette_table[(address - 0x3F00) as usize],
+            0x3F00..=0x3FFF => {
+                // Palette reads are immediate, but the buffer is still filled from the nametable underneath
+                self.internal_data_buffer = self.read_nametable(address & 0x2FFF);
+                self.palette_table[Self::mirror_palette_address(address)]
+            }
             _ => panic!("Unexpected access to mirrored address space, attempted to read {:#x}", address),
         }
     }
 
     fn write_to_ppu_ctrl(&mut self, value: u8) {
         self.ctrl.update(value);
+        self.loopy.write_ctrl(value);
     }
 
     fn write_to_ppu_mask(&mut self, value: u8) {
@@ -194,24 +210,21 @@ impl Ppu {
     }
 
     fn write_to_ppu_scroll(&mut self, value: u8) {
-        self.scroll.update(value);
+        self.loopy.write_scroll(value);
     }
 
     fn write_to_ppu_addr(&mut self, value: u8) {
-        self.addr.update(value);
+        self.loopy.write_addr(value);
     }
 
     fn write_data(&mut self, value: u8) {
-        let address = self.addr.get();
+        let address = self.loopy.vram_addr();
+        self.cartridge.borrow_mut().notify_ppu_address(address);
 
         match address {
             0..=0x1FFF => self.cartridge.borrow_mut().ppu_write(address, value),
-            0x2000..=0x2FFF => self.vram[self.mirror_vram_address(address) as usize] = value,
-            0x3000..=0x3EFF => unimplemented!("Address space 0x3000..0x3EFF is not expected to be used, attempted to write {:#x}", address),
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => self.palette_table[((address - 0x10) - 0x3F00) as usize] = value,
-            0x3F00..=0x3FFF => {
-                self.palette_table[(address - 0x3F00) as usize] = value;
-            }
+            0x2000..=0x3EFF => self.write_nametable(address, value),
+            0x3F00..=0x3FFF => self.palette_table[Self::mirror_palette_address(address)] = value & 0x3F,
             _ => panic!("Unexpected access to mirrored address space, attempted to write {:#x}", address),
         }
 
@@ -224,20 +237,39 @@ impl Ppu {
         }
     }
 
-    fn mirror_vram_address(&self, address: u16) -> u16 {
-        let mirrored_vram = address & 0x2FFF;
-        let vram_index = mirrored_vram - 0x2000;
-        let name_table = vram_index / 0x400;
+    /// Nametable accesses are decoded by the mapper, $3000-$3EFF mirrors $2000-$2EFF
+    fn read_nametable(&mut self, address: u16) -> u8 {
+        let mut cartridge = self.cartridge.borrow_mut();
+
+        match cartridge.map_nametable(address) {
+            MappedNametable::Ciram(offset) => self.vram[offset as usize & 0x07FF],
+            mapped => cartridge.read_nametable(mapped),
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8) {
+        let mut cartridge = self.cartridge.borrow_mut();
 
-        match (self.cartridge.borrow_mut().mirroring, name_table) {
-            (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) | (Mirroring::Horizontal, 3) => vram_index - 0x800,
-            (Mirroring::Horizontal, 1) | (Mirroring::Horizontal, 2) => vram_index - 0x400,
-            _ => vram_index,
+        match cartridge.map_nametable(address) {
+            MappedNametable::Ciram(offset) => self.vram[offset as usize & 0x07FF] = value,
+            mapped => cartridge.write_nametable(mapped, value),
         }
     }
 
+    /// $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries, and the 32 entries repeat up to $3FFF
+    fn mirror_palette_address(address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+        if index & 0x13 == 0x10 { index & 0x0F } else { index }
+    }
+
     fn increment_vram_addr(&mut self) {
-        self.addr.increment(self.ctrl.vram_add_increment());
+        if self.is_rendering_enabled() && self.is_render_scanline() {
+            // Accessing $2007 while rendering triggers both a coarse X and a Y increment
+            self.loopy.increment_coarse_x();
+            self.loopy.increment_y();
+        } else {
+            self.loopy.increment(self.ctrl.vram_add_increment());
+        }
     }
 
     fn set_vblank(&mut self) {
@@ -255,9 +287,355 @@ impl Ppu {
         self.nmi_output = self.nmi_occurred && nmi_enabled;
     }
 
-    fn is_sprite_0_hit(&self, cycle: usize) -> bool {
-        let y = self.oam_data[0] as usize;
-        let x = self.oam_data[3] as usize;
-        (y == self.scanline as usize) && x <= cycle && self.mask.contains(PpuMaskRegister::SHOW_SPRITES)
+    fn is_rendering_enabled(&self) -> bool {
+        self.mask.intersects(PpuMaskRegister::SHOW_BACKGROUND | PpuMaskRegister::SHOW_SPRITES)
+    }
+
+    /// Visible scanlines and the pre-render scanline, where the PPU fetches tiles and updates v
+    fn is_render_scanline(&self) -> bool {
+        self.scanline < 240 || self.scanline == 261
+    }
+
+    /// Advances v the way the background fetch pipeline does: coarse X after every tile, Y at dot 256, and the
+    /// horizontal and vertical reloads from t at dot 257 and dots 280-304 of the pre-render scanline
+    fn clock_scroll(&mut self) {
+        if !self.is_rendering_enabled() || !self.is_render_scanline() {
+            return;
+        }
+
+        match self.cycle {
+            256 => {
+                self.loopy.increment_coarse_x();
+                self.loopy.increment_y();
+            }
+            257 => self.loopy.copy_horizontal(),
+            280..=304 if self.scanline == 261 => self.loopy.copy_vertical(),
+            1..=255 | 328 | 336 if self.cycle.is_multiple_of(8) => self.loopy.increment_coarse_x(),
+            _ => {}
+        }
+    }
+
+    /// Lets the mapper follow rendering, which boards such as the MMC5 need to tell sprite fetches from background
+    /// fetches and to count scanlines
+    fn notify_render_state(&mut self) {
+        let state = PpuRenderState {
+            rendering: self.is_rendering_enabled() && self.is_render_scanline(),
+            scanline: self.scanline,
+            dot: self.cycle,
+            large_sprites: self.ctrl.sprite_size() == 16,
+        };
+
+        self.cartridge.borrow_mut().notify_ppu_state(state);
+    }
+
+    /// Runs the background fetch pipeline. Each 8-dot group fetches the nametable byte, attribute byte and both pattern
+    /// bytes of the next tile, which are loaded into the shift registers at the start of the following group. Dots 321-336
+    /// prefetch the first two tiles of the next scanline.
+    fn clock_background(&mut self) {
+        if !self.is_rendering_enabled() || !self.is_render_scanline() {
+            return;
+        }
+
+        if matches!(self.cycle, 2..=257 | 322..=337) {
+            self.background.shift();
+        }
+
+        match self.cycle {
+            1..=256 | 321..=336 => match (self.cycle - 1) % 8 {
+                0 => {
+                    self.background.load_shifters();
+                    self.background.next_tile_id = self.read_nametable(self.loopy.tile_addr());
+                }
+                2 => {
+                    let attribute = self.read_nametable(self.loopy.attribute_addr());
+                    let shift = ((self.loopy.coarse_y() & 0x02) << 1) | (self.loopy.coarse_x() & 0x02);
+                    self.background.next_attribute = (attribute >> shift) & 0x03;
+                }
+                4 => self.background.next_pattern_low = self.read_pattern(self.background_tile_row_addr()),
+                6 => self.background.next_pattern_high = self.read_pattern(self.background_tile_row_addr() + 8),
+                _ => {}
+            },
+            257 => self.background.load_shifters(),
+            337 => {
+                self.background.load_shifters();
+                self.background.next_tile_id = self.read_nametable(self.loopy.tile_addr()); // Unused nametable fetch
+            }
+            339 => self.background.next_tile_id = self.read_nametable(self.loopy.tile_addr()),
+            _ => {}
+        }
+    }
+
+    fn background_tile_row_addr(&self) -> u16 {
+        self.ctrl.background_pattern_addr() + self.background.next_tile_id as u16 * 16 + self.loopy.fine_y()
+    }
+
+    /// Evaluates the sprites for the next scanline from dot 65 and fetches their patterns during dots 257-320, one slot
+    /// every 8 dots with the pattern fetch falling on the same dots as the background's
+    fn clock_sprites(&mut self) {
+        if !self.is_rendering_enabled() || !self.is_render_scanline() {
+            return;
+        }
+
+        if self.cycle == 65 {
+            if self.scanline == 261 {
+                self.sprites.clear();
+            } else {
+                self.sprites.evaluate(&self.oam_data, self.scanline, self.ctrl.sprite_size() as u16);
+            }
+        }
+
+        if self.sprites.overflow_at(self.cycle) {
+            self.status.insert(PpuStatusRegister::SPRITE_OVERFLOW);
+        }
+
+        if (257..=320).contains(&self.cycle) && (self.cycle - 257) % 8 == 4 {
+            self.fetch_sprite_patterns(((self.cycle - 257) / 8) as usize);
+        }
+
+        // Sprites past the hardware limit only exist with the limit removed, they're fetched off the bus so mappers don't see them
+        if self.cycle == 320 {
+            for slot in MAX_SPRITES_PER_SCANLINE..self.sprites.sprite_count() {
+                self.fetch_sprite_patterns(slot);
+            }
+        }
+    }
+
+    fn fetch_sprite_patterns(&mut self, slot: usize) {
+        let sprite = self.sprites.sprite(slot);
+
+        // Unused slots still fetch tile $FF, which mappers watching the PPU address bus can see
+        let address = match sprite {
+            Some(sprite) => {
+                let height = self.ctrl.sprite_size() as u16;
+                let row = self.scanline.wrapping_sub(sprite.y as u16) & (height - 1);
+                let row = if sprite.flip_vertical() { height - 1 - row } else { row };
+                self.sprite_row_addr(sprite.tile, row)
+            }
+            None => self.sprite_row_addr(0xFF, 0),
+        };
+
+        let (mut pattern_low, mut pattern_high) = if slot < MAX_SPRITES_PER_SCANLINE {
+            (self.read_pattern(address), self.read_pattern(address + 8))
+        } else {
+            let mut cartridge = self.cartridge.borrow_mut();
+            (cartridge.ppu_peek(address), cartridge.ppu_peek(address + 8))
+        };
+
+        if let Some(sprite) = sprite
+            && sprite.flip_horizontal()
+        {
+            pattern_low = pattern_low.reverse_bits();
+            pattern_high = pattern_high.reverse_bits();
+        }
+
+        self.sprites.load_slot(slot, pattern_low, pattern_high);
+    }
+
+    /// Pattern address of one row of a sprite. 8x16 sprites take their pattern table from bit 0 of the tile index and are
+    /// drawn from the even/odd tile pair that follows, so a vertically flipped sprite also swaps its two halves.
+    fn sprite_row_addr(&self, tile: u8, row: u16) -> u16 {
+        if self.ctrl.sprite_size() == 16 {
+            let table = (tile as u16 & 0x01) * 0x1000;
+            let tile = (tile & 0xFE) as u16 + (row >> 3);
+            table + tile * 16 + (row & 0x07)
+        } else {
+            self.ctrl.sprite_pattern_addr() + tile as u16 * 16 + row
+        }
+    }
+
+    /// Outputs one pixel of the visible area into the back buffer
+    fn render_pixel(&mut self) {
+        if self.scanline >= 240 || !(1..=256).contains(&self.cycle) {
+            return;
+        }
+
+        let x = self.cycle - 1;
+
+        let palette_addr = if self.is_rendering_enabled() {
+            let (background_pixel, background_palette) = if self.mask.show_background_at(x) {
+                self.background.pixel(self.loopy.fine_x)
+            } else {
+                (0, 0)
+            };
+
+            let sprite = self.sprites.next_pixel().filter(|_| self.mask.show_sprites_at(x));
+
+            // Both pixels are already transparent where their layer is disabled or clipped, which covers every case where
+            // sprite 0 hit can't happen other than x=255
+            if let Some(sprite) = &sprite
+                && sprite.is_sprite_0
+                && background_pixel != 0
+                && x != 255
+            {
+                self.status.insert(PpuStatusRegister::SPRITE_0_HIT);
+            }
+
+            // A sprite with its priority bit set only shows through transparent background pixels, but it still hides any
+            // lower priority sprites behind it
+            match sprite {
+                Some(sprite) if background_pixel == 0 || !sprite.behind_background => 0x10 | (sprite.palette << 2) | sprite.pixel,
+                _ if background_pixel != 0 => (background_palette << 2) | background_pixel,
+                _ => 0, // Backdrop colour
+            }
+        } else if self.loopy.vram_addr() >= 0x3F00 {
+            // With rendering disabled the backdrop is replaced by whichever palette entry v points at
+            Self::mirror_palette_address(self.loopy.vram_addr()) as u8
+        } else {
+            0
+        };
+
+        let colour = self.palette_table[Self::mirror_palette_address(palette_addr as u16)];
+        let colour = SYSTEM_PALETTE_COLOURS[self.mask.palette_index(colour)];
+        self.back_buffer.set_pixel(x as usize, self.scanline as usize, colour);
+    }
+
+    /// Pattern table fetches made while rendering, these are visible to mappers watching the PPU address bus
+    fn read_pattern(&mut self, address: u16) -> u8 {
+        let mut cartridge = self.cartridge.borrow_mut();
+        cartridge.notify_ppu_address(address);
+        cartridge.ppu_read(address)
+    }
+}
+
+impl SaveState for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.ctrl.bits());
+        writer.write_u8(self.mask.bits());
+        writer.write_u8(self.status.bits());
+        self.loopy.save_state(writer);
+
+        writer.write_u8(self.oam_addr);
+        writer.write_bytes(&self.oam_data);
+        writer.write_bytes(&self.palette_table);
+        writer.write_bytes(&self.vram);
+        writer.write_u8(self.internal_data_buffer);
+
+        writer.write_u16(self.cycle);
+        writer.write_u16(self.scanline);
+        writer.write_u64(self.frame);
+
+        writer.write_bool(self.nmi_occurred);
+        writer.write_bool(self.nmi_output);
+        writer.write_bool(self.nmi_previous);
+
+        self.background.save_state(writer);
+        self.sprites.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.ctrl.update(reader.read_u8()?);
+        self.mask.update(reader.read_u8()?);
+        self.status = PpuStatusRegister::from_bits_truncate(reader.read_u8()?);
+        self.loopy.load_state(reader)?;
+
+        self.oam_addr = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.oam_data)?;
+        reader.read_bytes_into(&mut self.palette_table)?;
+        reader.read_bytes_into(&mut self.vram)?;
+        self.internal_data_buffer = reader.read_u8()?;
+
+        self.cycle = reader.read_u16()?;
+        self.scanline = reader.read_u16()?;
+        self.frame = reader.read_u64()?;
+
+        self.nmi_occurred = reader.read_bool()?;
+        self.nmi_output = reader.read_bool()?;
+        self.nmi_previous = reader.read_bool()?;
+
+        self.background.load_state(reader)?;
+        self.sprites.load_state(reader)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOW_BOTH: u8 = 0x18;
+    const SHOW_BOTH_LEFT_COLUMN: u8 = 0x1E;
+
+    /// A PPU on an NROM board with CHR-RAM and horizontal mirroring
+    fn nrom_ppu() -> Ppu {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.resize(16 + 16384, 0);
+        Ppu::new(Rc::new(RefCell::new(Cartridge::from_bytes(&rom).unwrap())))
+    }
+
+    /// Tile 1 is solid colour 1 and tile 0 is transparent. The nametable is filled with `background_tile` and sprite 0 is
+    /// placed at (`x`, 31) using `sprite_tile`.
+    fn sprite_0_ppu(background_tile: u8, sprite_tile: u8, x: u8) -> Ppu {
+        let mut ppu = nrom_ppu();
+        write_vram(&mut ppu, 0x0010, &[0xFF; 8]);
+        write_vram(&mut ppu, 0x2000, &[background_tile; 960]);
+        write_vram(&mut ppu, 0x0000, &[]);
+
+        let mut oam = [0xFF; 256];
+        oam[..4].copy_from_slice(&[30, sprite_tile, 0x00, x]);
+        ppu.write_to_oam_dma(&oam);
+
+        ppu
+    }
+
+    fn write_vram(ppu: &mut Ppu, address: u16, data: &[u8]) {
+        ppu.cpu_write(0x2006, (address >> 8) as u8);
+        ppu.cpu_write(0x2006, address as u8);
+        for &value in data {
+            ppu.cpu_write(0x2007, value);
+        }
+    }
+
+    /// Draws the visible scanlines with the given PPUMASK and returns whether sprite 0 hit was raised
+    fn sprite_0_hit(ppu: &mut Ppu, mask: u8) -> bool {
+        ppu.cpu_write(0x2001, mask);
+        while ppu.scanline < 240 {
+            ppu.tick();
+        }
+
+        ppu.status.contains(PpuStatusRegister::SPRITE_0_HIT)
+    }
+
+    #[test]
+    fn palette_reads_fill_buffer_from_nametable() {
+        let mut ppu = nrom_ppu();
+        write_vram(&mut ppu, 0x2F10, &[0x55]);
+        write_vram(&mut ppu, 0x3F10, &[0x21]);
+
+        write_vram(&mut ppu, 0x3F10, &[]);
+        assert_eq!(ppu.cpu_read(0x2007), 0x21);
+
+        // The buffer now holds $2F10, which the next non-palette read returns
+        write_vram(&mut ppu, 0x2000, &[]);
+        assert_eq!(ppu.cpu_read(0x2007), 0x55);
+    }
+
+    #[test]
+    fn sprite_0_hit_needs_both_pixels_opaque() {
+        assert!(sprite_0_hit(&mut sprite_0_ppu(1, 1, 100), SHOW_BOTH));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(0, 1, 100), SHOW_BOTH));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 0, 100), SHOW_BOTH));
+
+        // Either layer being disabled hides its pixels
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 100), 0x08));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 100), 0x10));
+    }
+
+    #[test]
+    fn sprite_0_hit_misses_last_pixel() {
+        // Only the sprite's leftmost pixel is onscreen at x=255
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 255), SHOW_BOTH));
+        assert!(sprite_0_hit(&mut sprite_0_ppu(1, 1, 254), SHOW_BOTH));
+    }
+
+    #[test]
+    fn sprite_0_hit_respects_left_column_clipping() {
+        assert!(sprite_0_hit(&mut sprite_0_ppu(1, 1, 0), SHOW_BOTH_LEFT_COLUMN));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 0), SHOW_BOTH));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 0), SHOW_BOTH | 0x02));
+        assert!(!sprite_0_hit(&mut sprite_0_ppu(1, 1, 0), SHOW_BOTH | 0x04));
+
+        // A sprite that reaches past the clipped column still hits there
+        assert!(sprite_0_hit(&mut sprite_0_ppu(1, 1, 1), SHOW_BOTH));
     }
 }
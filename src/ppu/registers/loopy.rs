@@ -0,0 +1,264 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+const HORIZONTAL_BITS: u16 = NAMETABLE_X | COARSE_X;
+const VERTICAL_BITS: u16 = FINE_Y | NAMETABLE_Y | COARSE_Y;
+
+/// The PPU's internal scroll/address registers, shared between $2000, $2005, $2006 and rendering:
+///
+/// ```text
+/// v/t: yyy NN YYYYY XXXXX
+///      ||| || ||||| +++++-- coarse X scroll
+///      ||| || +++++-------- coarse Y scroll
+///      ||| ++-------------- nametable select
+///      +++----------------- fine Y scroll
+/// ```
+pub struct PpuLoopyRegister {
+    pub v: u16,     // Current VRAM address (15 bits)
+    pub t: u16,     // Temporary VRAM address, the top-left onscreen tile (15 bits)
+    pub fine_x: u8, // Fine X scroll (3 bits)
+    pub w: bool,    // First or second write toggle shared by $2005 and $2006
+}
+
+impl PpuLoopyRegister {
+    pub fn new() -> Self {
+        Self {
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+        }
+    }
+
+    /// $2000 write, nametable select bits go to t
+    pub fn write_ctrl(&mut self, value: u8) {
+        self.t = (self.t & !(NAMETABLE_X | NAMETABLE_Y)) | (((value & 0x03) as u16) << 10);
+    }
+
+    /// $2005 write, X scroll on the first write and Y scroll on the second
+    pub fn write_scroll(&mut self, value: u8) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (value >> 3) as u16;
+            self.fine_x = value & 0x07;
+        } else {
+            self.t = (self.t & !(FINE_Y | COARSE_Y)) | (((value & 0x07) as u16) << 12) | (((value >> 3) as u16) << 5);
+        }
+
+        self.w = !self.w;
+    }
+
+    /// $2006 write, high byte on the first write and low byte on the second (which also copies t to v)
+    pub fn write_addr(&mut self, value: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | (((value & 0x3F) as u16) << 8);
+        } else {
+            self.t = (self.t & 0x7F00) | value as u16;
+            self.v = self.t;
+        }
+
+        self.w = !self.w;
+    }
+
+    /// $2002 read clears the write toggle
+    pub fn reset_latch(&mut self) {
+        self.w = false;
+    }
+
+    /// The 14-bit address on the PPU bus for $2007 accesses
+    pub fn vram_addr(&self) -> u16 {
+        self.v & 0x3FFF
+    }
+
+    /// Increment after a $2007 access outside of rendering
+    pub fn increment(&mut self, increment: u8) {
+        self.v = self.v.wrapping_add(increment as u16) & 0x7FFF;
+    }
+
+    pub fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    pub fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !FINE_Y;
+
+        let mut coarse_y = (self.v & COARSE_Y) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= NAMETABLE_Y;
+        } else if coarse_y == 31 {
+            coarse_y = 0; // Row 31 wraps without switching nametable, rows 30-31 are the attribute table
+        } else {
+            coarse_y += 1;
+        }
+
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    /// Dot 257, copy the horizontal position from t to v
+    pub fn copy_horizontal(&mut self) {
+        self.v = (self.v & !HORIZONTAL_BITS) | (self.t & HORIZONTAL_BITS);
+    }
+
+    /// Dots 280-304 of the pre-render scanline, copy the vertical position from t to v
+    pub fn copy_vertical(&mut self) {
+        self.v = (self.v & !VERTICAL_BITS) | (self.t & VERTICAL_BITS);
+    }
+
+    pub fn coarse_x(&self) -> u16 {
+        self.v & COARSE_X
+    }
+
+    pub fn coarse_y(&self) -> u16 {
+        (self.v & COARSE_Y) >> 5
+    }
+
+    pub fn fine_y(&self) -> u16 {
+        (self.v & FINE_Y) >> 12
+    }
+
+    /// Nametable address of the tile v currently points at
+    pub fn tile_addr(&self) -> u16 {
+        0x2000 | (self.v & 0x0FFF)
+    }
+
+    /// Attribute table address covering the tile v currently points at
+    pub fn attribute_addr(&self) -> u16 {
+        0x23C0 | (self.v & (NAMETABLE_X | NAMETABLE_Y)) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07)
+    }
+}
+
+impl Default for PpuLoopyRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for PpuLoopyRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.v);
+        writer.write_u16(self.t);
+        writer.write_u8(self.fine_x);
+        writer.write_bool(self.w);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.v = reader.read_u16()?;
+        self.t = reader.read_u16()?;
+        self.fine_x = reader.read_u8()?;
+        self.w = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_and_address_writes() {
+        let mut loopy = PpuLoopyRegister::new();
+        loopy.write_ctrl(0x00);
+
+        loopy.write_scroll(0x7D);
+        assert_eq!(loopy.t, 0x000F);
+        assert_eq!(loopy.fine_x, 0x05);
+        assert!(loopy.w);
+
+        loopy.write_scroll(0x5E);
+        assert_eq!(loopy.t, 0x616F);
+        assert!(!loopy.w);
+
+        // The first $2006 write clears bit 14 and leaves v alone
+        loopy.write_addr(0x7D);
+        assert_eq!(loopy.t, 0x3D6F);
+        assert_eq!(loopy.v, 0x0000);
+
+        loopy.write_addr(0xF0);
+        assert_eq!(loopy.t, 0x3DF0);
+        assert_eq!(loopy.v, 0x3DF0);
+        assert_eq!(loopy.fine_x, 0x05);
+        assert!(!loopy.w);
+
+        loopy.write_ctrl(0x03);
+        assert_eq!(loopy.t, 0x3DF0 | NAMETABLE_X | NAMETABLE_Y);
+    }
+
+    #[test]
+    fn status_read_resets_write_toggle() {
+        let mut loopy = PpuLoopyRegister::new();
+        loopy.write_addr(0x21);
+        loopy.reset_latch();
+
+        // Both writes are treated as the first one
+        loopy.write_addr(0x23);
+        assert!(loopy.w);
+        loopy.write_addr(0x45);
+        assert_eq!(loopy.v, 0x2345);
+
+        // The toggle is shared between $2005 and $2006
+        loopy.write_scroll(0xFF);
+        loopy.reset_latch();
+        loopy.write_scroll(0x08);
+        assert_eq!(loopy.t & COARSE_X, 0x01);
+        assert_eq!(loopy.fine_x, 0x00);
+    }
+
+    #[test]
+    fn coarse_x_wraps_into_next_nametable() {
+        let mut loopy = PpuLoopyRegister::new();
+        loopy.v = 0x001E;
+
+        loopy.increment_coarse_x();
+        assert_eq!(loopy.v, 0x001F);
+
+        loopy.increment_coarse_x();
+        assert_eq!(loopy.v, NAMETABLE_X);
+
+        loopy.v = NAMETABLE_X | NAMETABLE_Y | 0x001F;
+        loopy.increment_coarse_x();
+        assert_eq!(loopy.v, NAMETABLE_Y);
+    }
+
+    #[test]
+    fn fine_y_wraps_at_row_29_and_31() {
+        let mut loopy = PpuLoopyRegister::new();
+        loopy.v = 0x6000 | (28 << 5);
+        loopy.increment_y();
+        assert_eq!(loopy.v, FINE_Y | (28 << 5));
+
+        loopy.increment_y();
+        assert_eq!(loopy.coarse_y(), 29);
+        assert_eq!(loopy.fine_y(), 0);
+
+        // The last row of tiles moves on to the top of the nametable below
+        loopy.v |= FINE_Y;
+        loopy.increment_y();
+        assert_eq!(loopy.v, NAMETABLE_Y);
+
+        // Rows 30 and 31 are only reached by writing the scroll, 31 wraps to 0 in the same nametable
+        loopy.v = FINE_Y | NAMETABLE_Y | (30 << 5);
+        loopy.increment_y();
+        assert_eq!(loopy.v, NAMETABLE_Y | (31 << 5));
+
+        loopy.v |= FINE_Y;
+        loopy.increment_y();
+        assert_eq!(loopy.v, NAMETABLE_Y);
+    }
+}
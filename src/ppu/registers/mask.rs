@@ -11,31 +11,31 @@
     }
 }
 
-pub enum Colour {
-    Red,
-    Green,
-    Blue,
-}
-
 impl PpuMaskRegister {
     pub fn new() -> Self {
         Self::from_bits_truncate(0)
     }
 
-    pub fn emphasize(&self) -> Vec<Colour> {
-        let mut result = Vec::<Colour>::new();
+    /// The red, green and blue emphasis bits as a 3-bit value
+    pub fn emphasis(&self) -> u8 {
+        self.bits() >> 5
+    }
+
+    /// Whether the background is drawn at the given x position, taking the left column clipping into account
+    pub fn show_background_at(&self, x: u16) -> bool {
+        self.contains(Self::SHOW_BACKGROUND) && (x >= 8 || self.contains(Self::LEFTMOST_8PXL_BACKGROUND))
+    }
 
-        if self.contains(Self::EMPHASIZE_RED) {
-            result.push(Colour::Red);
-        }
-        if self.contains(Self::EMPHASIZE_BLUE) {
-            result.push(Colour::Blue);
-        }
-        if self.contains(Self::EMPHASIZE_GREEN) {
-            result.push(Colour::Green);
-        }
+    /// Whether sprites are drawn at the given x position, taking the left column clipping into account
+    pub fn show_sprites_at(&self, x: u16) -> bool {
+        self.contains(Self::SHOW_SPRITES) && (x >= 8 || self.contains(Self::LEFTMOST_8PXL_SPRITE))
+    }
 
-        result
+    /// Index into the system palette for a colour read from palette RAM. Greyscale keeps only the brightness column and
+    /// the emphasis bits select one of the 8 tinted copies of the palette.
+    pub fn palette_index(&self, colour: u8) -> usize {
+        let colour = if self.contains(Self::GREYSCALE) { colour & 0x30 } else { colour & 0x3F };
+        ((self.emphasis() as usize) << 6) | colour as usize
     }
 
     pub fn update(&mut self, value: u8) {
@@ -48,3 +48,51 @@ impl Default for PpuMaskRegister {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greyscale_and_emphasis() {
+        let mut mask = PpuMaskRegister::new();
+        assert_eq!(mask.palette_index(0x27), 0x27);
+
+        mask.update(0x01);
+        assert_eq!(mask.palette_index(0x27), 0x20);
+        assert_eq!(mask.palette_index(0x0F), 0x00);
+
+        mask.update(0xA0);
+        assert_eq!(mask.emphasis(), 0x05);
+        assert_eq!(mask.palette_index(0x27), 0x167);
+
+        mask.update(0xE1);
+        assert_eq!(mask.palette_index(0x3D), 0x1F0);
+    }
+
+    #[test]
+    fn left_column_clipping() {
+        let mut mask = PpuMaskRegister::new();
+        assert!(!mask.show_background_at(100));
+        assert!(!mask.show_sprites_at(100));
+
+        mask.update(0x18);
+        assert!(!mask.show_background_at(0));
+        assert!(!mask.show_sprites_at(7));
+        assert!(mask.show_background_at(8));
+        assert!(mask.show_sprites_at(255));
+
+        mask.update(0x1A);
+        assert!(mask.show_background_at(0));
+        assert!(!mask.show_sprites_at(0));
+
+        mask.update(0x1C);
+        assert!(!mask.show_background_at(0));
+        assert!(mask.show_sprites_at(0));
+
+        // The column flags don't draw a hidden layer
+        mask.update(0x06);
+        assert!(!mask.show_background_at(0));
+        assert!(!mask.show_sprites_at(0));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
+/// Background tile pipeline. Every 8 dots the PPU fetches a nametable byte, an attribute byte and two pattern bytes into
+/// the latches, which are then loaded into the low byte of the 16-bit shift registers so that the next tile is always
+/// queued up behind the one being drawn.
+#[derive(Default)]
+pub struct BackgroundPipeline {
+    pub next_tile_id: u8,
+    pub next_attribute: u8, // 2-bit palette for the next tile, already selected from the attribute byte
+    pub next_pattern_low: u8,
+    pub next_pattern_high: u8,
+
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+}
+
+impl BackgroundPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the latched tile into the low byte of the shift registers, the attribute bits are expanded to cover all 8 pixels
+    pub fn load_shifters(&mut self) {
+        self.pattern_low = (self.pattern_low & 0xFF00) | self.next_pattern_low as u16;
+        self.pattern_high = (self.pattern_high & 0xFF00) | self.next_pattern_high as u16;
+        self.attribute_low = (self.attribute_low & 0xFF00) | if self.next_attribute & 0x01 != 0 { 0xFF } else { 0x00 };
+        self.attribute_high = (self.attribute_high & 0xFF00) | if self.next_attribute & 0x02 != 0 { 0xFF } else { 0x00 };
+    }
+
+    pub fn shift(&mut self) {
+        self.pattern_low <<= 1;
+        self.pattern_high <<= 1;
+        self.attribute_low <<= 1;
+        self.attribute_high <<= 1;
+    }
+
+    /// Returns the 2-bit pixel value and 2-bit palette at the current position, offset by the fine X scroll
+    pub fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let mask = 0x8000 >> fine_x;
+        let bit = |register: u16| (register & mask != 0) as u8;
+
+        let pixel = (bit(self.pattern_high) << 1) | bit(self.pattern_low);
+        let palette = (bit(self.attribute_high) << 1) | bit(self.attribute_low);
+
+        (pixel, palette)
+    }
+}
+
+impl SaveState for BackgroundPipeline {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.next_tile_id);
+        writer.write_u8(self.next_attribute);
+        writer.write_u8(self.next_pattern_low);
+        writer.write_u8(self.next_pattern_high);
+
+        writer.write_u16(self.pattern_low);
+        writer.write_u16(self.pattern_high);
+        writer.write_u16(self.attribute_low);
+        writer.write_u16(self.attribute_high);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.next_tile_id = reader.read_u8()?;
+        self.next_attribute = reader.read_u8()?;
+        self.next_pattern_low = reader.read_u8()?;
+        self.next_pattern_high = reader.read_u8()?;
+
+        self.pattern_low = reader.read_u16()?;
+        self.pattern_high = reader.read_u16()?;
+        self.attribute_low = reader.read_u16()?;
+        self.attribute_high = reader.read_u16()?;
+
+        Ok(())
+    }
+}
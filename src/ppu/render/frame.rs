@@ -1,4 +1,4 @@
-﻿#[derive(Copy, Clone, Debug)]
+﻿#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Colour(pub u8, pub u8, pub u8);
 
 pub struct Frame {
@@ -1,6 +1,12 @@
 ﻿use crate::ppu::render::frame::Colour;
 
-pub static SYSTEM_PALETTE_COLOURS: [Colour; 64] = [
+/// The 64 base colours with every combination of the PPUMASK emphasis bits, indexed by `emphasis << 6 | colour`
+pub static SYSTEM_PALETTE_COLOURS: [Colour; 512] = build_emphasis_palette();
+
+// Emphasising a colour darkens the other two channels to roughly 74.6% of their level
+const EMPHASIS_ATTENUATION: u32 = 746;
+
+const BASE_COLOURS: [Colour; 64] = [
     Colour(0x66, 0x66, 0x66),
     Colour(0x00, 0x2A, 0x88),
     Colour(0x14, 0x12, 0xA7),
@@ -66,3 +72,67 @@ pub static SYSTEM_PALETTE_COLOURS: [Colour; 64] = [
     Colour(0x00, 0x00, 0x00),
     Colour(0x00, 0x00, 0x00),
 ];
+
+const fn attenuate(value: u8, emphasised: bool) -> u8 {
+    if emphasised {
+        value
+    } else {
+        (value as u32 * EMPHASIS_ATTENUATION / 1000) as u8
+    }
+}
+
+const fn build_emphasis_palette() -> [Colour; 512] {
+    let mut palette = [Colour(0, 0, 0); 512];
+
+    let mut i = 0;
+    while i < palette.len() {
+        let emphasis = i >> 6;
+        let colour = BASE_COLOURS[i & 0x3F];
+
+        // The blacks in columns $E and $F are unaffected by emphasis
+        palette[i] = if emphasis == 0 || i & 0x0E == 0x0E {
+            colour
+        } else {
+            Colour(
+                attenuate(colour.0, emphasis & 0x01 != 0),
+                attenuate(colour.1, emphasis & 0x02 != 0),
+                attenuate(colour.2, emphasis & 0x04 != 0),
+            )
+        };
+
+        i += 1;
+    }
+
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_emphasis_is_the_base_palette() {
+        assert_eq!(SYSTEM_PALETTE_COLOURS[..64], BASE_COLOURS);
+    }
+
+    #[test]
+    fn emphasis_darkens_other_channels() {
+        // $30 is white, emphasising red leaves red alone and dims green and blue
+        assert_eq!(SYSTEM_PALETTE_COLOURS[(0x01 << 6) | 0x30], Colour(0xFF, 0xBD, 0xBE));
+        assert_eq!(SYSTEM_PALETTE_COLOURS[(0x02 << 6) | 0x30], Colour(0xBE, 0xFE, 0xBE));
+        assert_eq!(SYSTEM_PALETTE_COLOURS[(0x04 << 6) | 0x30], Colour(0xBE, 0xBD, 0xFF));
+        assert_eq!(SYSTEM_PALETTE_COLOURS[(0x03 << 6) | 0x30], Colour(0xFF, 0xFE, 0xBE));
+    }
+
+    #[test]
+    fn emphasis_leaves_blacks_alone() {
+        for emphasis in 0..8 {
+            for colour in [0x0E, 0x0F, 0x1E, 0x1F, 0x2E, 0x2F, 0x3E, 0x3F] {
+                assert_eq!(SYSTEM_PALETTE_COLOURS[(emphasis << 6) | colour], Colour(0x00, 0x00, 0x00));
+            }
+        }
+
+        // Column $D is the greys, which are dimmed like any other colour
+        assert_eq!(SYSTEM_PALETTE_COLOURS[(0x01 << 6) | 0x2D], Colour(0x4F, 0x3A, 0x3A));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+use anyhow::Result;
+
+pub const MAX_SPRITES_PER_SCANLINE: usize = 8;
+const OAM_SPRITES: usize = 64;
+
+/// A 4-byte OAM entry as laid out in primary and secondary OAM
+#[derive(Clone, Copy)]
+pub struct OamSprite {
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8, // Palette (bits 0-1), behind background (bit 5), flip horizontal (bit 6), flip vertical (bit 7)
+    pub x: u8,
+}
+
+impl OamSprite {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            y: bytes[0],
+            tile: bytes[1],
+            attributes: bytes[2],
+            x: bytes[3],
+        }
+    }
+
+    pub fn flip_horizontal(&self) -> bool {
+        self.attributes & 0x40 != 0
+    }
+
+    pub fn flip_vertical(&self) -> bool {
+        self.attributes & 0x80 != 0
+    }
+}
+
+/// The pattern shifters, attribute latch and X counter for one of the 8 sprites drawn on a scanline
+#[derive(Clone, Copy, Default)]
+struct SpriteSlot {
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x_counter: u8,
+}
+
+pub struct SpritePixel {
+    pub pixel: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub is_sprite_0: bool,
+}
+
+/// Sprite evaluation and output. During each scanline the sprites in range of the next scanline are copied into
+/// secondary OAM, their patterns are then fetched into the slots during dots 257-320 and drawn on the following line.
+///
+/// With the sprite limit removed every sprite in range is kept, not just the first 8. The overflow flag still behaves as
+/// it does on hardware so that games timing off it are unaffected.
+pub struct SpriteUnit {
+    pub sprite_limit: bool,
+
+    secondary_oam: [u8; OAM_SPRITES * 4],
+    sprite_count: usize,
+    sprite_0_selected: bool,
+    overflow_dot: Option<u16>,
+
+    // Evaluation for the next scanline overlaps drawing the current one, so the slots keep their own count
+    slots: [SpriteSlot; OAM_SPRITES],
+    slot_count: usize,
+    sprite_0_in_slots: bool,
+}
+
+impl SpriteUnit {
+    pub fn new() -> Self {
+        Self {
+            sprite_limit: true,
+
+            secondary_oam: [0xFF; OAM_SPRITES * 4],
+            sprite_count: 0,
+            sprite_0_selected: false,
+            overflow_dot: None,
+
+            slots: [SpriteSlot::default(); OAM_SPRITES],
+            slot_count: 0,
+            sprite_0_in_slots: false,
+        }
+    }
+
+    /// Selects the sprites in OAM whose Y range (8 or 16 lines tall) covers the scanline after `scanline`. Evaluation
+    /// starts at dot 65 and takes 2 dots per sprite checked plus another 6 for each sprite copied, which decides the dot
+    /// the overflow flag is raised on.
+    pub fn evaluate(&mut self, oam: &[u8; 256], scanline: u16, sprite_height: u16) {
+        self.clear();
+
+        let in_range = |y: u8| scanline.wrapping_sub(y as u16) < sprite_height;
+        let mut dot = 65;
+        let mut n = 0;
+
+        while n < OAM_SPRITES && self.sprite_count < MAX_SPRITES_PER_SCANLINE {
+            if in_range(oam[n * 4]) {
+                self.copy_to_secondary(&oam[n * 4..n * 4 + 4]);
+                self.sprite_0_selected |= n == 0;
+                dot += 8;
+            } else {
+                dot += 2;
+            }
+
+            n += 1;
+        }
+
+        let scanned = n;
+
+        // Once secondary OAM is full the hardware keeps looking for a 9th sprite, but it wrongly increments the byte
+        // offset m along with the sprite index n, so it compares tile numbers, attributes and X positions as if they were Y
+        let mut m = 0;
+        while n < OAM_SPRITES {
+            if in_range(oam[n * 4 + m]) {
+                self.overflow_dot = Some(dot);
+                break;
+            }
+
+            n += 1;
+            m = (m + 1) & 0x03;
+            dot += 2;
+        }
+
+        if !self.sprite_limit {
+            for sprite in oam.chunks_exact(4).skip(scanned) {
+                if in_range(sprite[0]) {
+                    self.copy_to_secondary(sprite);
+                }
+            }
+        }
+    }
+
+    fn copy_to_secondary(&mut self, sprite: &[u8]) {
+        let base = self.sprite_count * 4;
+        self.secondary_oam[base..base + 4].copy_from_slice(sprite);
+        self.sprite_count += 1;
+    }
+
+    /// The pre-render scanline doesn't evaluate sprites, so nothing is drawn on the first visible scanline
+    pub fn clear(&mut self) {
+        self.secondary_oam = [0xFF; OAM_SPRITES * 4];
+        self.sprite_count = 0;
+        self.sprite_0_selected = false;
+        self.overflow_dot = None;
+    }
+
+    pub fn sprite_count(&self) -> usize {
+        self.sprite_count
+    }
+
+    /// Whether evaluation found a 9th sprite on the given dot
+    pub fn overflow_at(&self, dot: u16) -> bool {
+        self.overflow_dot == Some(dot)
+    }
+
+    /// The sprite in the given secondary OAM slot, or `None` for an unused slot
+    pub fn sprite(&self, slot: usize) -> Option<OamSprite> {
+        if slot < self.sprite_count {
+            Some(OamSprite::from_bytes(&self.secondary_oam[slot * 4..slot * 4 + 4]))
+        } else {
+            None
+        }
+    }
+
+    /// Loads the pattern bytes fetched for a slot, already flipped horizontally if required
+    pub fn load_slot(&mut self, slot: usize, pattern_low: u8, pattern_high: u8) {
+        if slot == 0 {
+            self.slot_count = self.sprite_count;
+            self.sprite_0_in_slots = self.sprite_0_selected;
+        }
+
+        self.slots[slot] = match self.sprite(slot) {
+            Some(sprite) => SpriteSlot {
+                pattern_low,
+                pattern_high,
+                attributes: sprite.attributes,
+                x_counter: sprite.x,
+            },
+            None => SpriteSlot::default(), // Unused slots are transparent
+        };
+    }
+
+    /// Produces the sprite pixel for the current dot and advances every slot by one pixel. Sprites whose X counter hasn't
+    /// run out yet count down instead, lower slots take priority over higher ones.
+    pub fn next_pixel(&mut self) -> Option<SpritePixel> {
+        let mut result = None;
+
+        for (i, slot) in self.slots.iter_mut().enumerate().take(self.slot_count) {
+            if slot.x_counter > 0 {
+                slot.x_counter -= 1;
+                continue;
+            }
+
+            let pixel = ((slot.pattern_high >> 7) << 1) | (slot.pattern_low >> 7);
+            slot.pattern_low <<= 1;
+            slot.pattern_high <<= 1;
+
+            if pixel != 0 && result.is_none() {
+                result = Some(SpritePixel {
+                    pixel,
+                    palette: slot.attributes & 0x03,
+                    behind_background: slot.attributes & 0x20 != 0,
+                    is_sprite_0: i == 0 && self.sprite_0_in_slots,
+                });
+            }
+        }
+
+        result
+    }
+}
+
+impl Default for SpriteUnit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for SpriteUnit {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.secondary_oam);
+        writer.write_u8(self.sprite_count as u8);
+        writer.write_bool(self.sprite_0_selected);
+        writer.write_u16(self.overflow_dot.unwrap_or(0));
+
+        for slot in &self.slots {
+            writer.write_u8(slot.pattern_low);
+            writer.write_u8(slot.pattern_high);
+            writer.write_u8(slot.attributes);
+            writer.write_u8(slot.x_counter);
+        }
+        writer.write_u8(self.slot_count as u8);
+        writer.write_bool(self.sprite_0_in_slots);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.secondary_oam)?;
+        self.sprite_count = (reader.read_u8()? as usize).min(OAM_SPRITES);
+        self.sprite_0_selected = reader.read_bool()?;
+        self.overflow_dot = Some(reader.read_u16()?).filter(|dot| *dot != 0);
+
+        for slot in &mut self.slots {
+            slot.pattern_low = reader.read_u8()?;
+            slot.pattern_high = reader.read_u8()?;
+            slot.attributes = reader.read_u8()?;
+            slot.x_counter = reader.read_u8()?;
+        }
+        self.slot_count = (reader.read_u8()? as usize).min(OAM_SPRITES);
+        self.sprite_0_in_slots = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// OAM with every byte $FF, which puts all 64 sprites below the screen, and the given sprites placed on line 100
+    fn oam_with_sprites_on_line(sprites: &[usize]) -> [u8; 256] {
+        let mut oam = [0xFF; 256];
+        for &n in sprites {
+            oam[n * 4] = 100;
+        }
+        oam
+    }
+
+    fn overflowed(unit: &SpriteUnit) -> bool {
+        unit.overflow_dot.is_some()
+    }
+
+    #[test]
+    fn eight_sprites_on_a_line() {
+        let mut unit = SpriteUnit::new();
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7]), 100, 8);
+
+        assert_eq!(unit.sprite_count(), 8);
+        assert!(unit.sprite(7).is_some());
+        assert!(unit.sprite(8).is_none());
+        assert!(!overflowed(&unit));
+
+        // A line outside every sprite's range
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7]), 108, 8);
+        assert_eq!(unit.sprite_count(), 0);
+    }
+
+    #[test]
+    fn ninth_sprite_sets_overflow() {
+        let mut unit = SpriteUnit::new();
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7, 8]), 100, 8);
+
+        // 8 copied sprites take 8 dots each from dot 65, the 9th is found on its first check
+        assert_eq!(unit.sprite_count(), 8);
+        assert!(unit.overflow_at(129));
+    }
+
+    #[test]
+    fn overflow_false_positive() {
+        // Sprite 9 is off the line, but its tile number is compared as a Y coordinate
+        let mut oam = oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        oam[9 * 4 + 1] = 100;
+
+        let mut unit = SpriteUnit::new();
+        unit.evaluate(&oam, 100, 8);
+
+        assert_eq!(unit.sprite_count(), 8);
+        assert!(unit.overflow_at(131));
+    }
+
+    #[test]
+    fn overflow_false_negative() {
+        // Sprite 9 is on the line, but the scan has moved on to its tile number by then
+        let mut unit = SpriteUnit::new();
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7, 9]), 100, 8);
+
+        assert_eq!(unit.sprite_count(), 8);
+        assert!(!overflowed(&unit));
+    }
+
+    #[test]
+    fn no_sprite_limit_keeps_extra_sprites() {
+        let mut unit = SpriteUnit::new();
+        unit.sprite_limit = false;
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7, 9, 22, 63]), 100, 8);
+
+        // Every sprite on the line is kept in order, while the flag follows the hardware's scan, which misses them
+        assert_eq!(unit.sprite_count(), 11);
+        assert!(unit.sprite(10).is_some_and(|sprite| sprite.y == 100 && sprite.tile == 0xFF));
+        assert!(!overflowed(&unit));
+
+        unit.evaluate(&oam_with_sprites_on_line(&[0, 1, 2, 3, 4, 5, 6, 7, 8]), 100, 8);
+        assert_eq!(unit.sprite_count(), 9);
+        assert!(unit.overflow_at(129));
+    }
+
+    #[test]
+    fn tall_sprites() {
+        let mut unit = SpriteUnit::new();
+        unit.evaluate(&oam_with_sprites_on_line(&[5]), 115, 16);
+
+        assert_eq!(unit.sprite_count(), 1);
+        assert!(!unit.sprite_0_selected);
+    }
+}
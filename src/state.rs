@@ -0,0 +1,175 @@
+use anyhow::{Result, bail, ensure};
+
+const STATE_MAGIC: [u8; 4] = *b"NESS";
+pub const STATE_VERSION: u32 = 8;
+
+/// Implemented by every component that is captured in a save state. Fields are written and read back in the same
+/// fixed order, so any change to what a component writes must be accompanied by a bump of `STATE_VERSION`.
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new save state with a header identifying the format version and the ROM it was taken from
+    pub fn with_header(rom_checksum: u32) -> Self {
+        let mut writer = Self::new();
+        writer.data.extend_from_slice(&STATE_MAGIC);
+        writer.write_u32(STATE_VERSION);
+        writer.write_u32(rom_checksum);
+        writer
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length-prefixed block of bytes
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    /// Validates the header written by `StateWriter::with_header` against the currently loaded ROM
+    pub fn with_header(data: &'a [u8], rom_checksum: u32) -> Result<Self> {
+        let mut reader = Self::new(data);
+
+        ensure!(reader.take(4)? == STATE_MAGIC, "Not a save state file");
+
+        let version = reader.read_u32()?;
+        ensure!(
+            version == STATE_VERSION,
+            "Unsupported save state version {} (expected {})",
+            version,
+            STATE_VERSION
+        );
+
+        let checksum = reader.read_u32()?;
+        ensure!(
+            checksum == rom_checksum,
+            "Save state was created for a different ROM (checksum {:08X}, expected {:08X})",
+            checksum,
+            rom_checksum
+        );
+
+        Ok(reader)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            bail!("Save state is truncated");
+        }
+
+        let slice = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// Reads a length-prefixed block of bytes
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    /// Reads a length-prefixed block of bytes into a fixed size buffer, the lengths must match
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let bytes = self.read_bytes()?;
+        ensure!(
+            bytes.len() == buffer.len(),
+            "Save state block has length {}, expected {}",
+            bytes.len(),
+            buffer.len()
+        );
+
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// CRC-32 (IEEE) used to tie a save state to the ROM it was created from
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
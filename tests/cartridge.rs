@@ -0,0 +1,376 @@
+use nes_emulator::cartridge::{Cartridge, CartridgeError, ConsoleType, HeaderFormat, MappedNametable, NsfFile, TimingRegion};
+
+fn build_rom(header: [u8; 16], prg_rom_size: usize, chr_rom_size: usize) -> Vec<u8> {
+    let mut data = header.to_vec();
+    data.resize(16 + prg_rom_size + chr_rom_size, 0);
+    data
+}
+
+fn ines_header(prg_banks: u8, chr_banks: u8, flags_6: u8, flags_7: u8) -> [u8; 16] {
+    [0x4E, 0x45, 0x53, 0x1A, prg_banks, chr_banks, flags_6, flags_7, 0, 0, 0, 0, 0, 0, 0, 0]
+}
+
+#[test]
+fn from_bytes_matches_load() {
+    let data = std::fs::read("test_roms/nestest.nes").unwrap();
+
+    let from_bytes = Cartridge::from_bytes(&data).unwrap();
+    let loaded = Cartridge::load("test_roms/nestest.nes").unwrap();
+
+    assert_eq!(from_bytes.rom_checksum(), loaded.rom_checksum());
+    assert_eq!(from_bytes.info.format, HeaderFormat::INes);
+    assert_eq!(from_bytes.info.mapper, 0);
+}
+
+#[test]
+fn rejects_truncated_header() {
+    let result = Cartridge::from_bytes(&[0x4E, 0x45, 0x53]);
+    assert!(matches!(result, Err(CartridgeError::TruncatedHeader { length: 3 })));
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let mut data = build_rom(ines_header(1, 1, 0, 0), 16384, 8192);
+    data[3] = 0x00;
+
+    assert!(matches!(Cartridge::from_bytes(&data), Err(CartridgeError::BadMagic(_))));
+}
+
+#[test]
+fn rejects_truncated_prg_rom() {
+    let data = build_rom(ines_header(2, 1, 0, 0), 16384, 0);
+    assert!(matches!(
+        Cartridge::from_bytes(&data),
+        Err(CartridgeError::TruncatedPrgRom {
+            expected: 32768,
+            available: 16384
+        })
+    ));
+}
+
+#[test]
+fn rejects_truncated_chr_rom() {
+    let data = build_rom(ines_header(1, 1, 0, 0), 16384, 4096);
+    assert!(matches!(
+        Cartridge::from_bytes(&data),
+        Err(CartridgeError::TruncatedChrRom {
+            expected: 8192,
+            available: 4096
+        })
+    ));
+}
+
+#[test]
+fn rejects_truncated_trainer() {
+    let data = ines_header(1, 1, 0x04, 0).to_vec();
+    assert!(matches!(Cartridge::from_bytes(&data), Err(CartridgeError::TruncatedTrainer)));
+}
+
+#[test]
+fn rejects_unsupported_mapper() {
+    let data = build_rom(ines_header(1, 1, 0xF0, 0xF0), 16384, 8192);
+    assert!(matches!(
+        Cartridge::from_bytes(&data),
+        Err(CartridgeError::UnsupportedMapper { mapper: 255, submapper: 0 })
+    ));
+}
+
+#[test]
+fn ines_defaults_ram_sizes() {
+    let data = build_rom(ines_header(1, 0, 0x02, 0), 16384, 0);
+    let cartridge = Cartridge::from_bytes(&data).unwrap();
+
+    assert_eq!(cartridge.info.prg_ram_size, 0);
+    assert_eq!(cartridge.info.prg_nvram_size, 8192);
+    assert_eq!(cartridge.info.chr_ram_size, 8192);
+    assert_eq!(cartridge.prg_ram.len(), 8192);
+    assert_eq!(cartridge.chr_ram.len(), 8192);
+}
+
+#[test]
+fn parses_nes2_header() {
+    let mut header = ines_header(2, 0, 0x12, 0x08);
+    header[8] = 0x20; // Submapper 2, mapper MSB 0
+    header[10] = 0x70; // 8KB PRG-NVRAM
+    header[11] = 0x09; // 32KB CHR-RAM
+    header[12] = 0x01; // PAL
+
+    let data = build_rom(header, 32768, 0);
+    let cartridge = Cartridge::from_bytes(&data).unwrap();
+    let info = &cartridge.info;
+
+    assert_eq!(info.format, HeaderFormat::Nes2);
+    assert_eq!(info.mapper, 1);
+    assert_eq!(info.submapper, 2);
+    assert_eq!(info.prg_rom_size, 32768);
+    assert_eq!(info.prg_ram_size, 0);
+    assert_eq!(info.prg_nvram_size, 8192);
+    assert_eq!(info.chr_ram_size, 32768);
+    assert_eq!(info.timing, TimingRegion::Pal);
+    assert_eq!(info.console_type, ConsoleType::Nes);
+    assert_eq!(cartridge.chr_ram.len(), 32768);
+}
+
+#[test]
+fn addresses_64k_of_prg_ram() {
+    let mut header = ines_header(1, 1, 0, 0x08);
+    header[10] = 0x0A; // 64KB PRG-RAM
+    let mut cartridge = Cartridge::from_bytes(&build_rom(header, 16384, 8192)).unwrap();
+    assert_eq!(cartridge.prg_ram.len(), 65536);
+
+    cartridge.cpu_write(0x6000, 0x42);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+
+    // iNES gives the size in 8KB units
+    let mut header = ines_header(1, 1, 0, 0);
+    header[8] = 8;
+    let mut cartridge = Cartridge::from_bytes(&build_rom(header, 16384, 8192)).unwrap();
+    assert_eq!(cartridge.prg_ram.len(), 65536);
+
+    cartridge.cpu_write(0x7FFF, 0x24);
+    assert_eq!(cartridge.cpu_read(0x7FFF), 0x24);
+}
+
+#[test]
+fn mmc5_banks_all_64k_of_prg_ram() {
+    let mut header = ines_header(8, 0, 0x50, 0x08); // Mapper 5, NES 2.0
+    header[10] = 0x0A; // 64KB PRG-RAM
+    let mut cartridge = Cartridge::from_bytes(&build_rom(header, 8 * 16384, 0)).unwrap();
+
+    cartridge.cpu_write(0x5100, 0x03); // 8KB PRG banks
+    cartridge.cpu_write(0x5102, 0x02);
+    cartridge.cpu_write(0x5103, 0x01);
+
+    // RAM bank 7 at $6000
+    cartridge.cpu_write(0x5113, 0x07);
+    cartridge.cpu_write(0x6000, 0x42);
+
+    // And the same bank at $8000, backed by the last 8KB of `prg_ram`
+    cartridge.cpu_write(0x5114, 0x07);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x42);
+    cartridge.cpu_write(0x9FFF, 0x24);
+    assert_eq!(cartridge.cpu_read(0x7FFF), 0x24);
+    assert_eq!(cartridge.prg_ram[0xFFFF], 0x24);
+
+    cartridge.cpu_write(0x5113, 0x00);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x00);
+}
+
+#[test]
+fn parses_nes2_exponent_multiplier_rom_size() {
+    let mut header = ines_header(0, 0, 0, 0x08);
+    header[4] = (14 << 2) | 0x01; // 2^14 * 3 = 48KB
+    header[9] = 0x0F;
+
+    let data = build_rom(header, 49152, 0);
+    let cartridge = Cartridge::from_bytes(&data).unwrap();
+
+    assert_eq!(cartridge.info.prg_rom_size, 49152);
+    assert_eq!(cartridge.prg_rom.len(), 49152);
+}
+
+#[test]
+fn mirrors_nametables_through_the_mapper() {
+    let horizontal = build_rom(ines_header(1, 1, 0x00, 0), 16384, 8192);
+    let mut cartridge = Cartridge::from_bytes(&horizontal).unwrap();
+    assert_eq!(cartridge.map_nametable(0x2400), MappedNametable::Ciram(0x0000));
+    assert_eq!(cartridge.map_nametable(0x2C05), MappedNametable::Ciram(0x0405));
+    assert_eq!(cartridge.map_nametable(0x3805), MappedNametable::Ciram(0x0405));
+
+    let vertical = build_rom(ines_header(1, 1, 0x01, 0), 16384, 8192);
+    let mut cartridge = Cartridge::from_bytes(&vertical).unwrap();
+    assert_eq!(cartridge.map_nametable(0x2400), MappedNametable::Ciram(0x0400));
+    assert_eq!(cartridge.map_nametable(0x2805), MappedNametable::Ciram(0x0005));
+}
+
+#[test]
+fn four_screen_uses_cartridge_vram() {
+    let data = build_rom(ines_header(1, 1, 0x08, 0), 16384, 8192);
+    let mut cartridge = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!(cartridge.nametable_ram.len(), 4096);
+
+    let mapped = cartridge.map_nametable(0x2C10);
+    assert_eq!(mapped, MappedNametable::CartridgeVram(0x0C10));
+
+    cartridge.write_nametable(mapped, 0x42);
+    assert_eq!(cartridge.read_nametable(mapped), 0x42);
+}
+
+/// A UxROM image whose 16KB banks are filled with their own bank number
+fn uxrom(flags_8: u8, flags_7: u8) -> Cartridge {
+    let mut header = ines_header(4, 0, 0x20, flags_7);
+    header[8] = flags_8;
+
+    let mut data = build_rom(header, 4 * 16384, 0);
+    for (bank, chunk) in data[16..].chunks_mut(16384).enumerate() {
+        chunk.fill(bank as u8);
+    }
+
+    Cartridge::from_bytes(&data).unwrap()
+}
+
+#[test]
+fn bus_conflicts_and_written_value_with_rom() {
+    let mut cartridge = uxrom(0, 0);
+
+    // The fixed bank at $C000 is bank 3, so writing 2 there selects 3 & 2
+    cartridge.cpu_write(0xC000, 0x02);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x02);
+
+    // Bank 2 is now at $8000, writing 1 over it selects 2 & 1 = bank 0
+    cartridge.cpu_write(0x8000, 0x01);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x00);
+}
+
+#[test]
+fn nes2_submapper_disables_bus_conflicts() {
+    let mut cartridge = uxrom(0x10, 0x08); // NES 2.0, submapper 1
+
+    cartridge.cpu_write(0xC000, 0x02);
+    cartridge.cpu_write(0x8000, 0x01);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x01);
+}
+
+#[test]
+fn uxrom_with_4mb_of_prg_rom() {
+    let mut header = ines_header(0, 0, 0x20, 0x08);
+    header[9] = 0x01; // 256 16KB banks
+    let mut data = build_rom(header, 256 * 16384, 0);
+    for (bank, chunk) in data[16..].chunks_mut(16384).enumerate() {
+        chunk.fill(bank as u8);
+    }
+
+    let mut cartridge = Cartridge::from_bytes(&data).unwrap();
+    assert_eq!(cartridge.cpu_read(0xC000), 0xFF);
+
+    // Written over the last bank, which is all $FF, so bus conflicts leave the value alone
+    cartridge.cpu_write(0xC000, 0x81);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x81);
+}
+
+/// A single-sided disk image holding just the disk info and file amount blocks
+fn fds_image(fwnes_header: bool) -> Vec<u8> {
+    let mut data = if fwnes_header {
+        vec![0x46, 0x44, 0x53, 0x1A, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    } else {
+        vec![]
+    };
+    let mut side = vec![0; 65500];
+    side[..15].copy_from_slice(b"\x01*NINTENDO-HVC*");
+    side[56] = 0x02;
+    data.extend_from_slice(&side);
+    data
+}
+
+#[test]
+fn loads_fds_images() {
+    let bios = vec![0xEA; 8192];
+
+    let headered = Cartridge::from_fds(&fds_image(true), &bios).unwrap();
+    let headerless = Cartridge::from_fds(&fds_image(false), &bios).unwrap();
+    assert_eq!(headered.rom_checksum(), headerless.rom_checksum());
+
+    let mut cartridge = headerless;
+    assert_eq!(cartridge.info.format, HeaderFormat::Fds);
+    assert_eq!(cartridge.prg_ram.len(), 32768);
+    assert_eq!(cartridge.chr_ram.len(), 8192);
+    assert_eq!(cartridge.cpu_read(0xE000), 0xEA);
+
+    cartridge.cpu_write(0xC000, 0x42);
+    assert_eq!(cartridge.cpu_read(0xC000), 0x42);
+}
+
+#[test]
+fn rejects_bad_fds_bios_and_images() {
+    assert!(matches!(
+        Cartridge::from_fds(&fds_image(false), &[0; 4096]),
+        Err(CartridgeError::InvalidFdsBios { length: 4096 })
+    ));
+
+    let mut image = fds_image(true);
+    image.truncate(1000);
+    assert!(matches!(
+        Cartridge::from_fds(&image, &[0; 8192]),
+        Err(CartridgeError::TruncatedDiskImage {
+            expected: 65500,
+            available: 984
+        })
+    ));
+}
+
+#[test]
+fn builds_nsf_cartridges() {
+    let mut data = vec![0; 0x80];
+    data[..5].copy_from_slice(b"NESM\x1A");
+    data[0x06] = 1;
+    data[0x08..0x0E].copy_from_slice(&[0x00, 0x90, 0x00, 0x90, 0x03, 0x90]);
+    data[0x70..0x78].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    data.extend_from_slice(&[0x11; 4096]);
+    data.extend_from_slice(&[0x22; 4096]);
+
+    let file = NsfFile::parse(&data).unwrap();
+    let mut cartridge = Cartridge::from_nsf(&file);
+    assert_eq!(cartridge.info.format, HeaderFormat::Nsf);
+    assert_eq!(cartridge.prg_rom.len(), 2 * 4096);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x11);
+    assert_eq!(cartridge.cpu_read(0xF000), 0x22);
+
+    cartridge.cpu_write(0x5FF8, 0x01);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x22);
+}
+
+#[test]
+fn battery_sram_round_trips_through_save_file() {
+    let path = std::env::temp_dir().join(format!("nes_emulator_sram_{}.sav", std::process::id()));
+    let rom = build_rom(ines_header(1, 1, 0x02, 0x00), 16384, 8192);
+
+    let mut cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert_eq!(cartridge.sram().len(), 8192);
+    assert!(!cartridge.load_sram_file(&path).unwrap());
+
+    cartridge.cpu_write(0x6000, 0x12);
+    cartridge.cpu_write(0x7FFF, 0x34);
+    cartridge.save_sram_file(&path).unwrap();
+
+    let mut restored = Cartridge::from_bytes(&rom).unwrap();
+    let loaded = restored.load_sram_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(loaded.unwrap());
+    assert_eq!(restored.cpu_read(0x6000), 0x12);
+    assert_eq!(restored.cpu_read(0x7FFF), 0x34);
+    assert_eq!(restored.sram(), cartridge.sram());
+    assert!(!restored.sram_dirty());
+}
+
+#[test]
+fn no_sram_without_battery() {
+    let mut cartridge = Cartridge::from_bytes(&build_rom(ines_header(1, 1, 0x00, 0x00), 16384, 8192)).unwrap();
+    assert!(cartridge.sram().is_empty());
+
+    cartridge.restore_sram(&[0x56; 8192]);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x00);
+
+    cartridge.cpu_write(0x6000, 0x78);
+    assert!(!cartridge.sram_dirty());
+}
+
+#[test]
+fn sram_dirty_tracks_changing_writes() {
+    let path = std::env::temp_dir().join(format!("nes_emulator_dirty_{}.sav", std::process::id()));
+    let mut cartridge = Cartridge::from_bytes(&build_rom(ines_header(1, 1, 0x02, 0x00), 16384, 8192)).unwrap();
+    assert!(!cartridge.sram_dirty());
+
+    // Writing the value that's already there isn't a change
+    cartridge.cpu_write(0x6000, 0x00);
+    assert!(!cartridge.sram_dirty());
+
+    cartridge.cpu_write(0x6000, 0x9A);
+    assert!(cartridge.sram_dirty());
+
+    let saved = cartridge.save_sram_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    saved.unwrap();
+    assert!(!cartridge.sram_dirty());
+}
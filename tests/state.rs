@@ -0,0 +1,47 @@
+use nes_emulator::{cartridge::Cartridge, emulator::Emulator};
+
+fn load_test_rom(path: &str) -> Emulator {
+    let cartridge = Cartridge::load(path).unwrap();
+    Emulator::new(cartridge)
+}
+
+fn run_frames(emulator: &mut Emulator, frames: usize) {
+    for _ in 0..frames {
+        emulator.run_frame();
+    }
+}
+
+#[test]
+fn state_round_trip_is_deterministic() {
+    let mut emulator = load_test_rom("test_roms/nestest.nes");
+    emulator.reset();
+    run_frames(&mut emulator, 30);
+
+    let snapshot = emulator.save_state();
+    run_frames(&mut emulator, 30);
+    let expected = emulator.save_state();
+
+    emulator.load_state(&snapshot).unwrap();
+    run_frames(&mut emulator, 30);
+
+    assert_eq!(emulator.save_state(), expected, "Replaying from a loaded state diverged");
+}
+
+#[test]
+fn state_rejects_truncated_data() {
+    let mut emulator = load_test_rom("test_roms/nestest.nes");
+    emulator.reset();
+
+    let snapshot = emulator.save_state();
+    assert!(emulator.load_state(&snapshot[..snapshot.len() / 2]).is_err());
+}
+
+#[test]
+fn state_rejects_bad_header() {
+    let mut emulator = load_test_rom("test_roms/nestest.nes");
+    emulator.reset();
+
+    let mut snapshot = emulator.save_state();
+    snapshot[0] ^= 0xFF;
+    assert!(emulator.load_state(&snapshot).is_err());
+}